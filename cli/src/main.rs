@@ -1,28 +1,57 @@
 use std::collections::HashMap;
+use std::convert::TryInto;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufRead, Write};
 use std::mem::size_of;
+use std::num::NonZeroU64;
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
 use clap::Clap;
-use common::{Cluster, convert_assertion_error, create_account_rent_exempt, create_signer_key_and_nonce, create_token_account, read_keypair_file, send_instructions};
+use common::{Cluster, convert_assertion_error, create_account_rent_exempt, create_signer_key_and_nonce, create_token_account, gen_signer_key, read_keypair_file, send_instructions, send_instructions_batched, send_txn};
 use fixed::types::U64F64;
-use mango::state::{Loadable, MangoGroup, MarginAccount, NUM_TOKENS};
+use mango::processor::get_prices;
+use mango::state::{DEFAULT_BORROW_FEE_PARAMS, Loadable, MangoGroup, MangoSrmAccount, MarginAccount, NUM_MARKETS, NUM_TOKENS};
+use safe_transmute::to_bytes::transmute_to_bytes;
 use serde_json::{json, Value};
+use serum_dex::instruction::{CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior};
+use serum_dex::matching::{OrderType, Side};
+use serum_dex::state::MarketState;
+use solana_account_decoder::UiAccountEncoding;
 use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
 use solana_client::rpc_request::TokenAccountsFilter;
 use solana_sdk::account::{Account};
+use solana_sdk::account_info::{AccountInfo, IntoAccountInfo};
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::program_pack::Pack;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::{Signer};
-use mango::instruction::{init_mango_group, init_margin_account, withdraw, borrow, deposit, settle_borrow, change_borrow_limit};
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use mango::instruction::{init_mango_group, init_margin_account, withdraw, borrow, deposit, settle_borrow, change_borrow_limit, place_order, settle_funds, cancel_order, partial_liquidate};
 
 #[derive(Clap, Debug)]
 pub struct Opts {
     #[clap(default_value = "mainnet")]
     pub cluster: Cluster,
+    // Instead of signing and submitting, write the assembled instructions plus a recent blockhash
+    // and the pubkeys that must sign to this path as a JSON envelope for offline/air-gapped
+    // signing. See `Command::SubmitSigned`.
+    #[clap(long)]
+    pub output_unsigned: Option<String>,
+    // Where `Command::Sync` persists its local cache of mango group state, and where other
+    // commands look it up before falling back to RPC. See `GroupCacheEntry`.
+    #[clap(long, default_value = "mango-cache.json")]
+    pub cache_path: String,
+    // Bypass the local cache for this invocation and hit RPC directly, e.g. because the cache
+    // is known to be stale from a `--cache-path` that hasn't been `sync`ed recently.
+    #[clap(long)]
+    pub refresh: bool,
     #[clap(subcommand)]
     pub command: Command,
 }
@@ -100,8 +129,9 @@ pub enum Command {
         mango_group_name: String,
         #[clap(long)]
         margin_account: String,
+        // Omit to settle every token with a nonzero borrow in one batched set of transactions
         #[clap(long, short)]
-        token_symbol: String,
+        token_symbol: Option<String>,
         #[clap(long, short)]
         quantity: Option<f64>
     },
@@ -117,13 +147,54 @@ pub enum Command {
     },
 
     PlaceOrder {
-
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        margin_account: String,
+        #[clap(long)]
+        market_symbol: String,
+        #[clap(long)]
+        side: String,  // "buy" or "sell"
+        #[clap(long)]
+        price: f64,
+        #[clap(long)]
+        size: f64,
+        #[clap(long, default_value = "0")]
+        client_order_id: u64,
+        #[clap(long)]
+        reduce_only: bool,
     },
     SettleFunds {
-
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        margin_account: String,
+        #[clap(long)]
+        market_symbol: String,
     },
     CancelOrder {
-
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        margin_account: String,
+        #[clap(long)]
+        market_symbol: String,
+        #[clap(long)]
+        side: String,  // "buy" or "sell"
+        #[clap(long)]
+        order_id: u128,
     },
     ChangeBorrowLimit {
         #[clap(long, short)]
@@ -136,9 +207,154 @@ pub enum Command {
         token_symbol: String,
         #[clap(long)]
         borrow_limit: f64
+    },
+    Liquidate {
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        margin_account: String,
+        #[clap(long)]
+        in_token_symbol: String,  // token the liquidator deposits to cover the liqee's borrow
+        #[clap(long)]
+        out_token_symbol: String,  // collateral token the liquidator receives in return
+        #[clap(long)]
+        max_deposit: f64,
+    },
+    ScanLiquidations {
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        in_token_symbol: String,
+        #[clap(long)]
+        out_token_symbol: String,
+        #[clap(long)]
+        max_deposit: f64,
+        #[clap(long, default_value = "5")]
+        interval_secs: u64,
+        #[clap(long, default_value = "0")]
+        min_profit: f64,  // skip liquidations worth less than this much quote currency
+    },
+    // Keeps a single spot market's serum dex event queue drained so its open-orders accounts
+    // (including those embedded in margin accounts) stay settled. Runs forever.
+    Crank {
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        market_symbol: String,
+        #[clap(long, default_value = "10")]
+        batch_size: usize,
+        #[clap(long, default_value = "5")]
+        poll_interval_secs: u64,
+    },
+    GroupStatus {
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+        #[clap(long)]
+        margin_account: Option<String>,
+        // When given, the report's "fee_tier" section shows the effective maker/taker
+        // rate this account's staked SRM/MSRM currently qualifies for.
+        #[clap(long)]
+        mango_srm_account: Option<String>,
+    },
+    // Fetches the mango group account, its current slot, and each token's mint decimals and
+    // index, then persists them to `--cache-path` so commands like `ChangeBorrowLimit` can look
+    // up decimals locally instead of re-fetching the mint every run. Re-run whenever the group's
+    // token list changes; the cache entry's `slot` records when it was last synced.
+    Sync {
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long)]
+        mango_group_name: String,
+    },
+    // Merges `other_path`'s cluster ids document into `ids_path` using the last-writer-wins CRDT
+    // rules in `LwwMap`, then writes the merged, conflict-free result back to `ids_path`. Lets two
+    // operators who each added symbols or mango groups offline reconcile their files without
+    // clobbering each other's edits; re-running is idempotent since merge is commutative.
+    MergeIds {
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long, short)]
+        other_path: String,
+    },
+    // Broadcasts an envelope written by some other command's `--output-unsigned`, after attaching
+    // signatures from `keypairs`. The fee payer and every pubkey in `required_signers` must be
+    // covered by the supplied keypairs.
+    SubmitSigned {
+        #[clap(long, short)]
+        tx_path: String,
+        #[clap(long, short)]
+        keypairs: Vec<String>,
+    },
+    // Converts a plaintext keypair file into the password-encrypted keystore format read by
+    // `common::read_encrypted_keypair`. Prompts for the password interactively.
+    EncryptKeypair {
+        #[clap(long, short)]
+        keypair_path: String,
+        #[clap(long, short)]
+        output_path: String,
+    },
+    // Loads the cluster ids file and reads the payer keypair once, then opens a prompt loop so
+    // several commands can be issued against the same cached `ClusterIds`/`MangoGroupIds` without
+    // re-reading the ids file or the keypair each time. The payer must be a plaintext keypair
+    // file; encrypted keystores written by `EncryptKeypair` are not accepted here. Type `help` at
+    // the prompt for the list of commands and `quit` to exit.
+    Interactive {
+        #[clap(long, short)]
+        payer: String,
+        #[clap(long, short)]
+        ids_path: String,
+        #[clap(long, short)]
+        mango_group_name: String,
     }
 }
 
+// The commands available inside `Command::Interactive`'s prompt loop. A deliberately small subset
+// of `Command` with the already-loaded `payer`/`ClusterIds`/`MangoGroupIds` omitted from the args.
+#[derive(Clap, Debug)]
+#[clap(name = "mango")]
+enum ReplCommand {
+    GroupStatus {
+        margin_account: Option<String>,
+        mango_srm_account: Option<String>,
+    },
+    ChangeBorrowLimit {
+        token_symbol: String,
+        borrow_limit: f64,
+    },
+    SettleBorrow {
+        margin_account: String,
+        token_symbol: Option<String>,
+        quantity: Option<f64>,
+    },
+    PlaceOrder {
+        margin_account: String,
+        market_symbol: String,
+        side: String,
+        price: f64,
+        size: f64,
+        #[clap(default_value = "0")]
+        client_order_id: u64,
+        #[clap(default_value = "false")]
+        reduce_only: bool,
+    },
+    Help,
+    Quit,
+}
+
 impl Opts {
     fn client(&self) -> RpcClient {
         RpcClient::new_with_commitment(self.cluster.url().to_string(),
@@ -186,7 +402,18 @@ impl ClusterIds {
 
     #[allow(dead_code)]
     pub fn to_json(&self) -> Value {
-        json!({"hello": "world"})
+        let mango_groups: serde_json::Map<String, Value> = self.mango_groups.iter().map(
+            |(k, v)| (k.clone(), v.to_json())
+        ).collect();
+
+        json!({
+            "mango_program_id": self.mango_program_id.to_string(),
+            "dex_program_id": self.dex_program_id.to_string(),
+            "mango_groups": mango_groups,
+            "oracles": map_of_pks_to_strs(self.oracles.clone()),
+            "spot_markets": map_of_pks_to_strs(self.spot_markets.clone()),
+            "symbols": map_of_pks_to_strs(self.symbols.clone()),
+        })
     }
 }
 
@@ -212,11 +439,223 @@ impl MangoGroupIds {
     pub fn get_token_index(&self, token_pk: &Pubkey) -> Option<usize> {
         self.mint_pks.iter().position(|pk| pk == token_pk)
     }
+    pub fn to_json(&self) -> Value {
+        json!({
+            "mango_group_pk": self.mango_group_pk.to_string(),
+            "mint_pks": self.mint_pks.iter().map(|pk| pk.to_string()).collect::<Vec<String>>(),
+            "spot_market_pks": self.spot_market_pks.iter().map(|pk| pk.to_string()).collect::<Vec<String>>(),
+            "vault_pks": self.vault_pks.iter().map(|pk| pk.to_string()).collect::<Vec<String>>(),
+            "oracle_pks": self.oracle_pks.iter().map(|pk| pk.to_string()).collect::<Vec<String>>(),
+        })
+    }
+}
+
+// A `Command::Sync`'d snapshot of one mango group's mint decimals and token indices, keyed in the
+// cache file by "<cluster>:<mango_group_name>". `slot` records the slot the snapshot was taken at;
+// re-run `Command::Sync` to refresh it, there's no automatic invalidation.
+struct GroupCacheEntry {
+    pub slot: u64,
+    pub mango_group_pk: Pubkey,
+    pub decimals: HashMap<String, u8>,
+    pub token_indices: HashMap<String, usize>,
+}
+
+impl GroupCacheEntry {
+    pub fn load(value: &Value) -> Self {
+        GroupCacheEntry {
+            slot: value["slot"].as_u64().unwrap(),
+            mango_group_pk: get_pk(value, "mango_group_pk"),
+            decimals: value["decimals"].as_object().unwrap().iter()
+                .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as u8)).collect(),
+            token_indices: value["token_indices"].as_object().unwrap().iter()
+                .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as usize)).collect(),
+        }
+    }
+    pub fn to_json(&self) -> Value {
+        json!({
+            "slot": self.slot,
+            "mango_group_pk": self.mango_group_pk.to_string(),
+            "decimals": self.decimals,
+            "token_indices": self.token_indices,
+        })
+    }
+}
+
+fn group_cache_key(cluster_name: &str, mango_group_name: &str) -> String {
+    format!("{}:{}", cluster_name, mango_group_name)
+}
+
+fn load_group_cache(cache_path: &str, key: &str) -> Option<GroupCacheEntry> {
+    let file = File::open(cache_path).ok()?;
+    let cache: Value = serde_json::from_reader(file).ok()?;
+    cache.get(key).map(GroupCacheEntry::load)
+}
+
+fn save_group_cache(cache_path: &str, key: &str, entry: &GroupCacheEntry) -> Result<()> {
+    let mut cache: Value = File::open(cache_path).ok()
+        .and_then(|f| serde_json::from_reader(f).ok())
+        .unwrap_or_else(|| json!({}));
+    cache[key] = entry.to_json();
+    File::create(cache_path)?.write_all(serde_json::to_string_pretty(&cache)?.as_bytes())?;
+    Ok(())
+}
+
+// A last-writer-wins CRDT map over one section of a cluster-ids document (e.g. "symbols" or
+// "mango_groups"). Each key's clock lives in a sibling "_clock" object inside the same cluster, so
+// the document on disk stays a plain, human-editable JSON file with no separate log to ship.
+struct LwwMap {
+    entries: serde_json::Map<String, Value>,
+    clocks: HashMap<String, u64>,
+}
+
+impl LwwMap {
+    fn load(section: &Value, clocks: &Value) -> Self {
+        LwwMap {
+            entries: section.as_object().cloned().unwrap_or_default(),
+            clocks: clocks.as_object().map(|m| {
+                m.iter().map(|(k, v)| (k.clone(), v.as_u64().unwrap_or(0))).collect()
+            }).unwrap_or_default(),
+        }
+    }
+
+    // Merges `other` into `self` in place, keeping whichever side has the newer clock per key
+    // and, on a tied clock, the lexicographically greater encoded value, so both operators
+    // converge on the same result regardless of merge order.
+    fn merge(&mut self, other: &LwwMap) {
+        for (key, other_value) in other.entries.iter() {
+            let other_clock = other.clocks.get(key).copied().unwrap_or(0);
+            let other_wins = match self.entries.get(key) {
+                None => true,
+                Some(self_value) => {
+                    let self_clock = self.clocks.get(key).copied().unwrap_or(0);
+                    match other_clock.cmp(&self_clock) {
+                        std::cmp::Ordering::Greater => true,
+                        std::cmp::Ordering::Less => false,
+                        std::cmp::Ordering::Equal => other_value.to_string() > self_value.to_string(),
+                    }
+                }
+            };
+            if other_wins {
+                self.entries.insert(key.clone(), other_value.clone());
+                self.clocks.insert(key.clone(), other_clock);
+            }
+        }
+    }
+
+    fn entries_json(&self) -> Value {
+        Value::Object(self.entries.clone())
+    }
+
+    fn clocks_json(&self) -> Value {
+        json!(self.clocks)
+    }
+}
+
+fn merge_section(a_cluster: &Value, b_cluster: &Value, name: &str) -> (Value, Value) {
+    let mut lww = LwwMap::load(&a_cluster[name], &a_cluster["_clock"][name]);
+    lww.merge(&LwwMap::load(&b_cluster[name], &b_cluster["_clock"][name]));
+    (lww.entries_json(), lww.clocks_json())
+}
+
+// Merges `a_cluster[name]`/`b_cluster[name]` as a one-entry `LwwMap` so single-value fields like
+// `mango_program_id` share the same last-writer-wins rule as the map sections.
+fn merge_singleton(a_cluster: &Value, b_cluster: &Value, name: &str) -> (Value, u64) {
+    let load_one = |cluster: &Value| -> LwwMap {
+        let mut entries = serde_json::Map::new();
+        if !cluster[name].is_null() {
+            entries.insert(name.to_string(), cluster[name].clone());
+        }
+        let mut clocks = HashMap::new();
+        if let Some(clock) = cluster["_clock"][name].as_u64() {
+            clocks.insert(name.to_string(), clock);
+        }
+        LwwMap { entries, clocks }
+    };
+    let mut lww = load_one(a_cluster);
+    lww.merge(&load_one(b_cluster));
+    let clock = lww.clocks.get(name).copied().unwrap_or(0);
+    (lww.entries.get(name).cloned().unwrap_or(Value::Null), clock)
+}
+
+// Wall-clock seconds, used as the `_clock` value stamped on a freshly-written `LwwMap`/
+// `merge_singleton` entry. This is what makes `LwwMap::merge`'s "newer clock wins" rule track
+// actual recency instead of falling through to its string tie-break on every real conflict --
+// see `bump_clock`/`bump_singleton_clock`.
+fn now_unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+// Stamps `cluster_ids[section][key]`'s clock in the sibling `_clock` object, creating either
+// object if this is the first write to that section. Call this alongside every write to a
+// `LwwMap`-managed section (`mango_groups`, `oracles`, `spot_markets`, `symbols`) so `MergeIds`
+// can tell which side's edit is actually newer.
+fn bump_clock(cluster_ids: &mut serde_json::Map<String, Value>, section: &str, key: &str) {
+    let clock_section = cluster_ids.entry("_clock").or_insert_with(|| json!({}))
+        .as_object_mut().unwrap()
+        .entry(section).or_insert_with(|| json!({}))
+        .as_object_mut().unwrap();
+    clock_section.insert(key.to_string(), json!(now_unix_secs()));
+}
+
+// Same as `bump_clock`, but for a singleton field like `mango_program_id` whose clock lives
+// directly at `_clock[name]` rather than nested under a section -- see `merge_singleton`.
+fn bump_singleton_clock(cluster_ids: &mut serde_json::Map<String, Value>, name: &str) {
+    let clock = cluster_ids.entry("_clock").or_insert_with(|| json!({}))
+        .as_object_mut().unwrap();
+    clock.insert(name.to_string(), json!(now_unix_secs()));
+}
+
+// Merges one cluster's `ClusterIds`-shaped JSON (mango_program_id, dex_program_id, mango_groups,
+// oracles, spot_markets, symbols) field-by-field via `LwwMap`/`merge_singleton`.
+fn merge_cluster_ids(a_cluster: &Value, b_cluster: &Value) -> Value {
+    let (mango_groups, mango_groups_clock) = merge_section(a_cluster, b_cluster, "mango_groups");
+    let (oracles, oracles_clock) = merge_section(a_cluster, b_cluster, "oracles");
+    let (spot_markets, spot_markets_clock) = merge_section(a_cluster, b_cluster, "spot_markets");
+    let (symbols, symbols_clock) = merge_section(a_cluster, b_cluster, "symbols");
+    let (mango_program_id, mango_program_id_clock) = merge_singleton(a_cluster, b_cluster, "mango_program_id");
+    let (dex_program_id, dex_program_id_clock) = merge_singleton(a_cluster, b_cluster, "dex_program_id");
+
+    json!({
+        "mango_program_id": mango_program_id,
+        "dex_program_id": dex_program_id,
+        "mango_groups": mango_groups,
+        "oracles": oracles,
+        "spot_markets": spot_markets,
+        "symbols": symbols,
+        "_clock": {
+            "mango_program_id": mango_program_id_clock,
+            "dex_program_id": dex_program_id_clock,
+            "mango_groups": mango_groups_clock,
+            "oracles": oracles_clock,
+            "spot_markets": spot_markets_clock,
+            "symbols": symbols_clock,
+        },
+    })
 }
 
+// Merges two full ids documents (each keyed by cluster name) by unioning whichever clusters
+// appear in either side and merging each one's sections with `merge_cluster_ids`. Commutative and
+// idempotent, so operators can merge in any order and re-merge without drifting.
+fn merge_ids_documents(a: &Value, b: &Value) -> Value {
+    let empty = serde_json::Map::new();
+    let a_obj = a.as_object().unwrap_or(&empty);
+    let b_obj = b.as_object().unwrap_or(&empty);
+    let cluster_names: std::collections::BTreeSet<&String> = a_obj.keys().chain(b_obj.keys()).collect();
+
+    let mut merged = serde_json::Map::new();
+    for cluster_name in cluster_names {
+        let a_cluster = a_obj.get(cluster_name).unwrap_or(&Value::Null);
+        let b_cluster = b_obj.get(cluster_name).unwrap_or(&Value::Null);
+        merged.insert(cluster_name.clone(), merge_cluster_ids(a_cluster, b_cluster));
+    }
+    Value::Object(merged)
+}
 
 pub fn start(opts: Opts) -> Result<()> {
     let client = opts.client();
+    let output_unsigned = opts.output_unsigned.clone();
+    let cache_path = opts.cache_path.clone();
+    let refresh = opts.refresh;
     match opts.command {
         Command::InitMangoGroup {
             payer,
@@ -258,6 +697,7 @@ pub fn start(opts: Opts) -> Result<()> {
 
 
             let (signer_key, signer_nonce) = create_signer_key_and_nonce(&mango_program_id, &mango_group_pk);
+            let signer_nonce = signer_nonce as u64;
             let dex_program_id = Pubkey::from_str(dex_program_id)?;
             assert!(tokens.len() <= NUM_TOKENS && tokens.len() >= 2);
 
@@ -326,11 +766,12 @@ pub fn start(opts: Opts) -> Result<()> {
                 signer_nonce,
                 U64F64::from_num(1.1),
                 U64F64::from_num(1.2),
-                borr_lims
+                borr_lims,
+                [DEFAULT_BORROW_FEE_PARAMS; NUM_TOKENS]
             )?;
             let instructions = vec![instruction];
             let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
             println!("InitMangoGroup success");
             // Edit the json file and add the keys associated with this mango group
             let group_name: String = tokens.join("_");
@@ -353,8 +794,10 @@ pub fn start(opts: Opts) -> Result<()> {
             let ids = ids.as_object_mut().unwrap();
             let cluster_ids = ids.get_mut(cluster_name).unwrap().as_object_mut().unwrap();
             cluster_ids.insert("mango_program_id".to_string(), Value::from(mango_program_id.to_string()));
-            let mango_groups = cluster_ids.get_mut("mango_groups").unwrap().as_object_mut().unwrap();
-            mango_groups.insert(group_name, group_keys);
+            bump_singleton_clock(cluster_ids, "mango_program_id");
+            cluster_ids.get_mut("mango_groups").unwrap().as_object_mut().unwrap()
+                .insert(group_name.clone(), group_keys);
+            bump_clock(cluster_ids, "mango_groups", &group_name);
             let f = File::create(ids_path.as_str()).unwrap();
             serde_json::to_writer_pretty(&f, &ids).unwrap();
 
@@ -390,7 +833,7 @@ pub fn start(opts: Opts) -> Result<()> {
             )?;
             let instructions = vec![instruction];
             let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
 
             println!("MarginAccount created");
             println!("{}", margin_account_pk.to_string());
@@ -449,7 +892,7 @@ pub fn start(opts: Opts) -> Result<()> {
 
             let instructions = vec![instruction];
             let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
 
         }
         Command::Borrow {
@@ -486,16 +929,23 @@ pub fn start(opts: Opts) -> Result<()> {
             }
 
             let mint_pks = get_vec_pks(&mango_group_ids["mint_pks"]);
+            let vault_pks = get_vec_pks(&mango_group_ids["vault_pks"]);
 
             let token_index = tokens.iter().position(|t| *t == token_symbol.as_str()).unwrap();
             let mint_acc = client.get_account(&mint_pks[token_index])?;
             let mint = spl_token::state::Mint::unpack(mint_acc.data.as_slice())?;
 
+            let mango_group_acc = client.get_account(&mango_group_pk)?;
+            let mango_group = MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
             let instruction = borrow(
                 &mango_program_id,
                 &mango_group_pk,
                 &margin_account_pk,
                 &margin_account.owner,
+                &vault_pks[token_index],
+                &mango_group.signer_key,
+                None,  // TODO expose a --host flag to split the origination fee with a referrer
                 &open_orders_pks,
                 oracle_pks.as_slice(),
                 token_index,
@@ -504,7 +954,7 @@ pub fn start(opts: Opts) -> Result<()> {
 
             let instructions = vec![instruction];
             let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
 
         }
         Command::ConvertAssertionError {
@@ -566,7 +1016,7 @@ pub fn start(opts: Opts) -> Result<()> {
             )?;
             let instructions = vec![instruction];
             let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
 
             println!("Deposited");
             let margin_account_acc = client.get_account(&margin_account_pk)?;
@@ -607,36 +1057,150 @@ pub fn start(opts: Opts) -> Result<()> {
             let mgids = cids.mango_groups[&mango_group_name].clone();
 
             let margin_account_pk = Pubkey::from_str(margin_account.as_str())?;
-            let margin_account = client.get_account(&margin_account_pk)?;
-            let margin_account = MarginAccount::load_from_bytes(margin_account.data.as_slice())?;
+            let margin_account_acc = client.get_account(&margin_account_pk)?;
+            let margin_account = *MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
             assert_eq!(margin_account.owner, payer.pubkey());
 
-            let token_pk = &cids.symbols[&token_symbol];
-            let token_i = mgids.get_token_index(token_pk).unwrap();
+            let instructions = build_settle_borrow_instructions(
+                &client, &cids, &mgids, &margin_account_pk, &margin_account,
+                token_symbol.as_deref(), quantity
+            )?;
+            let signers = vec![&payer];
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
+        }
+        Command::PlaceOrder {
+            payer,
+            ids_path,
+            mango_group_name,
+            margin_account,
+            market_symbol,
+            side,
+            price,
+            size,
+            client_order_id,
+            reduce_only,
+        } => {
+            println!("PlaceOrder");
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
 
-            let mint_acc = client.get_account(token_pk)?;
-            let mint = spl_token::state::Mint::unpack(mint_acc.data.as_slice())?;
+            let instruction = build_place_order_instruction(
+                &client, &cids, &mgids, &payer.pubkey(), mango_group_name.as_str(),
+                margin_account.as_str(), market_symbol.as_str(), side.as_str(), price, size, client_order_id,
+                reduce_only
+            )?;
+            let instructions = vec![instruction];
+            let signers = vec![&payer];
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
+        }
+        Command::SettleFunds {
+            payer,
+            ids_path,
+            mango_group_name,
+            margin_account,
+            market_symbol,
+        } => {
+            println!("SettleFunds");
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
 
-            let quantity = match quantity {
-                None => unimplemented!(),
-                Some(q) => spl_token::ui_amount_to_amount(q, mint.decimals)
-            };
-            let instruction = settle_borrow(
+            let margin_account_pk = Pubkey::from_str(margin_account.as_str())?;
+            let margin_account_acc = client.get_account(&margin_account_pk)?;
+            let margin_account = MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
+            assert_eq!(margin_account.owner, payer.pubkey());
+
+            let market_index = market_index_for_symbol(mango_group_name.as_str(), market_symbol.as_str());
+            let spot_market_pk = mgids.spot_market_pks[market_index];
+            let open_orders_pk = margin_account.open_orders[market_index];
+
+            let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+            let mango_group = MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
+            let dex_addrs = load_dex_market_addrs(&client, &cids.dex_program_id, &spot_market_pk)?;
+
+            let instruction = settle_funds(
                 &cids.mango_program_id,
                 &mgids.mango_group_pk,
+                &payer.pubkey(),
                 &margin_account_pk,
-                &margin_account.owner,
-                token_i,
-                quantity
+                &cids.dex_program_id,
+                &spot_market_pk,
+                &open_orders_pk,
+                &mango_group.signer_key,
+                &dex_addrs.base_vault_pk,
+                &dex_addrs.quote_vault_pk,
+                &mgids.vault_pks[market_index],
+                mgids.vault_pks.last().unwrap(),
+                &dex_addrs.vault_signer_pk,
             )?;
+
             let instructions = vec![instruction];
             let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
+        }
+        Command::CancelOrder {
+            payer,
+            ids_path,
+            mango_group_name,
+            margin_account,
+            market_symbol,
+            side,
+            order_id,
+        } => {
+            println!("CancelOrder");
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+
+            let margin_account_pk = Pubkey::from_str(margin_account.as_str())?;
+            let margin_account_acc = client.get_account(&margin_account_pk)?;
+            let margin_account = MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
+            assert_eq!(margin_account.owner, payer.pubkey());
+
+            let market_index = market_index_for_symbol(mango_group_name.as_str(), market_symbol.as_str());
+            let spot_market_pk = mgids.spot_market_pks[market_index];
+            let open_orders_pk = margin_account.open_orders[market_index];
+
+            let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+            let mango_group = MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
 
+            let dex_addrs = load_dex_market_addrs(&client, &cids.dex_program_id, &spot_market_pk)?;
+
+            let order = CancelOrderInstructionV2 {
+                side: parse_side(side.as_str()),
+                order_id,
+            };
+
+            let instruction = cancel_order(
+                &cids.mango_program_id,
+                &mgids.mango_group_pk,
+                &payer.pubkey(),
+                &margin_account_pk,
+                &cids.dex_program_id,
+                &spot_market_pk,
+                &dex_addrs.bids_pk,
+                &dex_addrs.asks_pk,
+                &open_orders_pk,
+                &mango_group.signer_key,
+                &dex_addrs.event_queue_pk,
+                order,
+            )?;
+
+            let instructions = vec![instruction];
+            let signers = vec![&payer];
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
         }
-        Command::PlaceOrder { .. } => {}
-        Command::SettleFunds { .. } => {}
-        Command::CancelOrder { .. } => {}
         Command::ChangeBorrowLimit {
             payer,
             ids_path,
@@ -652,27 +1216,882 @@ pub fn start(opts: Opts) -> Result<()> {
             let cids = ClusterIds::load(cluster_ids);
             let mgids = cids.mango_groups[&mango_group_name].clone();
 
-            let token_pk = &cids.symbols[&token_symbol];
+            let key = group_cache_key(cluster_name, mango_group_name.as_str());
+            let cache = if refresh { None } else { load_group_cache(cache_path.as_str(), key.as_str()) };
+            let instruction = build_change_borrow_limit_instruction(
+                &client, &cids, &mgids, &payer.pubkey(), token_symbol.as_str(), borrow_limit, cache.as_ref()
+            )?;
+            let instructions = vec![instruction];
+            let signers = vec![&payer];
+            dispatch_instructions(output_unsigned.as_deref(), &client, instructions, signers, &payer.pubkey())?;
+        }
+        Command::Liquidate {
+            payer,
+            ids_path,
+            mango_group_name,
+            margin_account,
+            in_token_symbol,
+            out_token_symbol,
+            max_deposit,
+        } => {
+            println!("Liquidate");
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+
+            let margin_account_pk = Pubkey::from_str(margin_account.as_str())?;
+            let margin_account_acc = client.get_account(&margin_account_pk)?;
+            let margin_account = *MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
+
+            let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+            let mango_group = *MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
+            let mut oracle_accounts = get_accounts(&client, mgids.oracle_pks.as_slice());
+            let oracle_accs: Vec<AccountInfo> = oracle_accounts.iter_mut()
+                .map(|(pk, acc)| (&*pk, acc).into_account_info())
+                .collect();
+            let coll_ratio = compute_coll_ratio(&client, &mango_group, &margin_account, oracle_accs.as_slice())?;
+
+            if coll_ratio >= mango_group.maint_coll_ratio {
+                println!("margin account {} is above maint_coll_ratio ({}); not liquidatable", margin_account_pk, coll_ratio);
+                return Ok(());
+            }
+
+            liquidate_margin_account(
+                &client, &cids, &mgids, &mango_group, &payer, &margin_account_pk, &margin_account,
+                in_token_symbol.as_str(), out_token_symbol.as_str(), max_deposit,
+            )?;
+        }
+        Command::ScanLiquidations {
+            payer,
+            ids_path,
+            mango_group_name,
+            in_token_symbol,
+            out_token_symbol,
+            max_deposit,
+            interval_secs,
+            min_profit,
+        } => {
+            println!("ScanLiquidations");
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+
+            loop {
+                let scan_result: Result<()> = (|| {
+                    let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+                    let mango_group = *MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
+                    let mut oracle_accounts = get_accounts(&client, mgids.oracle_pks.as_slice());
+                    let oracle_accs: Vec<AccountInfo> = oracle_accounts.iter_mut()
+                        .map(|(pk, acc)| (&*pk, acc).into_account_info())
+                        .collect();
+
+                    let margin_accounts = get_margin_accounts(&client, &cids.mango_program_id, &mgids.mango_group_pk)?;
+                    for (margin_account_pk, margin_account) in margin_accounts {
+                        let coll_ratio = match compute_coll_ratio(&client, &mango_group, &margin_account, oracle_accs.as_slice()) {
+                            Ok(c) => c,
+                            Err(e) => {
+                                println!("failed to price margin account {}: {}", margin_account_pk, e);
+                                continue;
+                            }
+                        };
+                        if coll_ratio >= mango_group.maint_coll_ratio {
+                            continue;
+                        }
+
+                        // Rough profit estimate: how far the account sits below maint_coll_ratio,
+                        // times max_deposit (in in_token_symbol ui units).
+                        let deficit = (mango_group.maint_coll_ratio - coll_ratio).to_num::<f64>() * max_deposit;
+                        if deficit < min_profit {
+                            println!("margin account {} is below maint_coll_ratio ({}) but deficit {} < min_profit {}; skipping",
+                                margin_account_pk, coll_ratio, deficit, min_profit);
+                            continue;
+                        }
+
+                        println!("liquidating margin account {} (coll_ratio {})", margin_account_pk, coll_ratio);
+                        if let Err(e) = liquidate_margin_account(
+                            &client, &cids, &mgids, &mango_group, &payer, &margin_account_pk, &margin_account,
+                            in_token_symbol.as_str(), out_token_symbol.as_str(), max_deposit,
+                        ) {
+                            println!("failed to liquidate margin account {}: {}", margin_account_pk, e);
+                        }
+                    }
+                    Ok(())
+                })();
+
+                if let Err(e) = scan_result {
+                    println!("scan failed: {}", e);
+                }
+                thread::sleep(Duration::from_secs(interval_secs));
+            }
+        }
+        Command::Crank {
+            payer,
+            ids_path,
+            mango_group_name,
+            market_symbol,
+            batch_size,
+            poll_interval_secs,
+        } => {
+            println!("Crank");
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+
+            let market_index = market_index_for_symbol(mango_group_name.as_str(), market_symbol.as_str());
+            let spot_market_pk = mgids.spot_market_pks[market_index];
+            let dex_addrs = load_dex_market_addrs(&client, &cids.dex_program_id, &spot_market_pk)?;
+
+            common::crank_market(
+                &client,
+                &cids.dex_program_id,
+                &spot_market_pk,
+                &dex_addrs.event_queue_pk,
+                &dex_addrs.base_vault_pk,
+                &dex_addrs.quote_vault_pk,
+                &payer,
+                batch_size,
+                Duration::from_secs(poll_interval_secs),
+            )?;
+        }
+        Command::GroupStatus {
+            ids_path,
+            mango_group_name,
+            margin_account,
+            mango_srm_account,
+        } => {
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+
+            print_group_status(
+                &client,
+                &cids,
+                &mgids,
+                mango_group_name.as_str(),
+                margin_account.as_deref(),
+                mango_srm_account.as_deref(),
+            )?;
+        }
+        Command::Sync {
+            ids_path,
+            mango_group_name,
+        } => {
+            println!("Sync");
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+
+            let response = client.get_account_with_commitment(&mgids.mango_group_pk, CommitmentConfig::confirmed())?;
+            let slot = response.context.slot;
+            response.value.ok_or_else(|| anyhow::anyhow!("mango group {} not found", mgids.mango_group_pk))?;
+
+            let token_symbols: Vec<&str> = mango_group_name.split("_").collect();
+            let mut decimals = HashMap::new();
+            let mut token_indices = HashMap::new();
+            for (i, symbol) in token_symbols.iter().enumerate() {
+                let token_pk = &cids.symbols[*symbol];
+                let mint_acc = client.get_account(token_pk)?;
+                let mint = spl_token::state::Mint::unpack(mint_acc.data.as_slice())?;
+                decimals.insert(symbol.to_string(), mint.decimals);
+                token_indices.insert(symbol.to_string(), i);
+            }
+
+            let entry = GroupCacheEntry { slot, mango_group_pk: mgids.mango_group_pk, decimals, token_indices };
+            let key = group_cache_key(cluster_name, mango_group_name.as_str());
+            save_group_cache(cache_path.as_str(), key.as_str(), &entry)?;
+            println!("Synced {} at slot {} to {}", mango_group_name, slot, cache_path);
+        }
+        Command::MergeIds {
+            ids_path,
+            other_path,
+        } => {
+            println!("MergeIds");
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let other: Value = serde_json::from_reader(File::open(&other_path)?)?;
+            let merged = merge_ids_documents(&ids, &other);
+            File::create(&ids_path)?.write_all(serde_json::to_string_pretty(&merged)?.as_bytes())?;
+            println!("Merged {} into {}", other_path, ids_path);
+        }
+        Command::SubmitSigned {
+            tx_path,
+            keypairs,
+        } => {
+            println!("SubmitSigned");
+            let envelope: Value = serde_json::from_reader(File::open(&tx_path)?)?;
+
+            let fee_payer = Pubkey::from_str(envelope["fee_payer"].as_str().unwrap())?;
+            let recent_blockhash = Hash::from_str(envelope["recent_blockhash"].as_str().unwrap())?;
+            let required_signers: Vec<Pubkey> = envelope["required_signers"].as_array().unwrap().iter()
+                .map(|s| Pubkey::from_str(s.as_str().unwrap()))
+                .collect::<std::result::Result<Vec<Pubkey>, _>>()?;
+            let instructions: Vec<Instruction> = envelope["instructions"].as_array().unwrap().iter()
+                .map(instruction_from_json)
+                .collect::<Result<Vec<Instruction>>>()?;
+
+            let keypairs: Vec<Keypair> = keypairs.iter()
+                .map(|p| read_keypair_file(p.as_str()))
+                .collect::<Result<Vec<Keypair>>>()?;
+            let signers: Vec<&Keypair> = keypairs.iter().collect();
+
+            for pk in &required_signers {
+                assert!(signers.iter().any(|k| k.pubkey() == *pk), "no keypair supplied for required signer {}", pk);
+            }
+
+            let txn = Transaction::new_signed_with_payer(&instructions, Some(&fee_payer), &signers, recent_blockhash);
+            send_txn(&client, &txn, false)?;
+            println!("Submitted signed transaction");
+        }
+        Command::EncryptKeypair {
+            keypair_path,
+            output_path,
+        } => {
+            println!("EncryptKeypair");
+            let keypair = read_keypair_file(keypair_path.as_str())?;
+            common::write_encrypted_keypair(output_path.as_str(), &keypair)?;
+            println!("Wrote encrypted keypair to {}", output_path);
+        }
+        Command::Interactive {
+            payer,
+            ids_path,
+            mango_group_name,
+        } => {
+            let payer = read_keypair_file(payer.as_str())?;
+            let ids: Value = serde_json::from_reader(File::open(&ids_path)?)?;
+            let cluster_name = opts.cluster.name();
+            let cluster_ids = &ids[cluster_name];
+            let cids = ClusterIds::load(cluster_ids);
+            let mgids = cids.mango_groups[&mango_group_name].clone();
+            let cache_key = group_cache_key(cluster_name, mango_group_name.as_str());
+
+            println!("Loaded mango group \"{}\" on {}. Type `help` for commands, `quit` to exit.",
+                mango_group_name, cluster_name);
+
+            let stdin = std::io::stdin();
+            loop {
+                print!("mango> ");
+                std::io::stdout().flush()?;
+
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line)? == 0 {
+                    break;
+                }
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let mut words = vec!["mango"];
+                words.extend(line.split_whitespace());
+                let repl_command = match ReplCommand::try_parse_from(words) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+
+                if matches!(repl_command, ReplCommand::Quit) {
+                    break;
+                }
+
+                let result = (|| -> Result<()> {
+                    match repl_command {
+                        ReplCommand::Quit => unreachable!(),
+                        ReplCommand::Help => {
+                            println!("Available commands:");
+                            println!("  group-status [<margin_account>] [<mango_srm_account>]");
+                            println!("  change-borrow-limit <token_symbol> <borrow_limit>");
+                            println!("  settle-borrow <margin_account> [<token_symbol> <quantity>]");
+                            println!("  place-order <margin_account> <market_symbol> <side> <price> <size> [<client_order_id>] [<reduce_only>]");
+                            println!("  help");
+                            println!("  quit");
+                        }
+                        ReplCommand::GroupStatus { margin_account, mango_srm_account } => {
+                            print_group_status(
+                                &client,
+                                &cids,
+                                &mgids,
+                                mango_group_name.as_str(),
+                                margin_account.as_deref(),
+                                mango_srm_account.as_deref(),
+                            )?;
+                        }
+                        ReplCommand::ChangeBorrowLimit { token_symbol, borrow_limit } => {
+                            let cache = if refresh { None } else { load_group_cache(cache_path.as_str(), cache_key.as_str()) };
+                            let instruction = build_change_borrow_limit_instruction(
+                                &client, &cids, &mgids, &payer.pubkey(), token_symbol.as_str(), borrow_limit, cache.as_ref()
+                            )?;
+                            if confirm(&format!("Set {} borrow limit to {}?", token_symbol, borrow_limit))? {
+                                dispatch_instructions(None, &client, vec![instruction], vec![&payer], &payer.pubkey())?;
+                            }
+                        }
+                        ReplCommand::SettleBorrow { margin_account, token_symbol, quantity } => {
+                            let margin_account_pk = Pubkey::from_str(margin_account.as_str())?;
+                            let margin_account_acc = client.get_account(&margin_account_pk)?;
+                            let margin_account = *MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
+                            let instructions = build_settle_borrow_instructions(
+                                &client, &cids, &mgids, &margin_account_pk, &margin_account,
+                                token_symbol.as_deref(), quantity
+                            )?;
+                            if confirm("Settle borrow(s) against this margin account?")? {
+                                dispatch_instructions(None, &client, instructions, vec![&payer], &payer.pubkey())?;
+                            }
+                        }
+                        ReplCommand::PlaceOrder { margin_account, market_symbol, side, price, size, client_order_id, reduce_only } => {
+                            let instruction = build_place_order_instruction(
+                                &client, &cids, &mgids, &payer.pubkey(), mango_group_name.as_str(),
+                                margin_account.as_str(), market_symbol.as_str(), side.as_str(), price, size, client_order_id,
+                                reduce_only
+                            )?;
+                            if confirm(&format!("Place {} order for {} {} @ {}?", side, size, market_symbol, price))? {
+                                dispatch_instructions(None, &client, vec![instruction], vec![&payer], &payer.pubkey())?;
+                            }
+                        }
+                    }
+                    Ok(())
+                })();
+
+                if let Err(e) = result {
+                    println!("error: {}", e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_side(side: &str) -> Side {
+    match side {
+        "buy" => Side::Bid,
+        "sell" => Side::Ask,
+        _ => panic!("side must be \"buy\" or \"sell\""),
+    }
+}
+
+// Prompts `prompt (y/N): ` on stdout and returns true only if the user answers "y" or "yes".
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} (y/N): ", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Finds the index of `market_symbol`'s base token among the mango group's tokens, in the same
+// order as `MangoGroupIds::spot_market_pks`/`oracle_pks`.
+fn market_index_for_symbol(mango_group_name: &str, market_symbol: &str) -> usize {
+    let tokens: Vec<&str> = mango_group_name.split("_").collect();
+    let base_symbol = market_symbol.split("/").next().unwrap();
+    tokens.iter().position(|t| *t == base_symbol).unwrap()
+}
+
+struct DexMarketAddrs {
+    request_queue_pk: Pubkey,
+    event_queue_pk: Pubkey,
+    bids_pk: Pubkey,
+    asks_pk: Pubkey,
+    base_vault_pk: Pubkey,
+    quote_vault_pk: Pubkey,
+    vault_signer_pk: Pubkey,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+}
+
+fn load_dex_market_addrs(client: &RpcClient, dex_program_id: &Pubkey, market_pk: &Pubkey) -> Result<DexMarketAddrs> {
+    let market_acc = client.get_account(market_pk)?;
+    // Serum dex accounts are wrapped in a 5 byte "serum" header and 7 byte "padding" trailer
+    let data = &market_acc.data[5..market_acc.data.len() - 7];
+    let market_state: MarketState = *safe_transmute::transmute_one_pedantic(data)
+        .map_err(|e| anyhow::anyhow!("failed to parse MarketState: {:?}", e))?;
+
+    let vault_signer_pk = gen_signer_key(market_state.vault_signer_nonce, market_pk, dex_program_id)?;
+
+    Ok(DexMarketAddrs {
+        request_queue_pk: Pubkey::new(transmute_to_bytes(&market_state.req_q)),
+        event_queue_pk: Pubkey::new(transmute_to_bytes(&market_state.event_q)),
+        bids_pk: Pubkey::new(transmute_to_bytes(&market_state.bids)),
+        asks_pk: Pubkey::new(transmute_to_bytes(&market_state.asks)),
+        base_vault_pk: Pubkey::new(transmute_to_bytes(&market_state.coin_vault)),
+        quote_vault_pk: Pubkey::new(transmute_to_bytes(&market_state.pc_vault)),
+        vault_signer_pk,
+        coin_lot_size: market_state.coin_lot_size,
+        pc_lot_size: market_state.pc_lot_size,
+    })
+}
+
+fn print_group_status(
+    client: &RpcClient,
+    cids: &ClusterIds,
+    mgids: &MangoGroupIds,
+    mango_group_name: &str,
+    margin_account: Option<&str>,
+    mango_srm_account: Option<&str>,
+) -> Result<()> {
+    let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+    let mango_group = *MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
+    let mut oracle_accounts = get_accounts(client, mgids.oracle_pks.as_slice());
+    let oracle_accs: Vec<AccountInfo> = oracle_accounts.iter_mut()
+        .map(|(pk, acc)| (&*pk, acc).into_account_info())
+        .collect();
+    let prices = get_prices(&mango_group, oracle_accs.as_slice())?;
+
+    let token_symbols: Vec<String> = mango_group_name.split("_").map(|s| s.to_string()).collect();
+
+    let mut tokens = serde_json::Map::new();
+    let mut utilization = serde_json::Map::new();
+    for i in 0..NUM_TOKENS {
+        let index = &mango_group.indexes[i];
+        let native_deposits: u64 = mango_group.total_deposits[i].checked_mul(index.deposit).unwrap().to_num();
+        let native_borrows: u64 = mango_group.total_borrows[i].checked_mul(index.borrow).unwrap().to_num();
+        let price = if i < NUM_MARKETS { prices[i].to_num::<f64>() } else { 1.0 };
+        tokens.insert(token_symbols[i].clone(), json!({
+            "total_deposits": native_deposits,
+            "total_borrows": native_borrows,
+            "borrow_limit": mango_group.borrow_limits[i],
+            "price": price,
+        }));
+        utilization.insert(token_symbols[i].clone(), json!(
+            if mango_group.borrow_limits[i] == 0 { 0f64 }
+            else { native_borrows as f64 / mango_group.borrow_limits[i] as f64 }
+        ));
+    }
+
+    let margin_accounts: Vec<Value> = match margin_account {
+        Some(margin_account_str) => {
+            let margin_account_pk = Pubkey::from_str(margin_account_str)?;
+            let margin_account_acc = client.get_account(&margin_account_pk)?;
+            let margin_account = *MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
+            vec![margin_account_to_json(client, &mango_group, &margin_account_pk, &margin_account, &prices, &token_symbols)?]
+        }
+        None => {
+            get_margin_accounts(client, &cids.mango_program_id, &mgids.mango_group_pk)?
+                .iter()
+                .map(|(pk, ma)| margin_account_to_json(client, &mango_group, pk, ma, &prices, &token_symbols))
+                .collect::<Result<Vec<Value>>>()?
+        }
+    };
+
+    let fee_tier = match mango_srm_account {
+        Some(mango_srm_account_str) => {
+            let mango_srm_account_pk = Pubkey::from_str(mango_srm_account_str)?;
+            let mango_srm_account_acc = client.get_account(&mango_srm_account_pk)?;
+            let mango_srm_account = *MangoSrmAccount::load_from_bytes(mango_srm_account_acc.data.as_slice())?;
+            let rates = mango_srm_account.fee_rates(&mango_group);
+            json!({
+                "tier": mango_srm_account.fee_tier(&mango_group),
+                "maker_bps": rates.maker_bps,
+                "taker_bps": rates.taker_bps,
+            })
+        }
+        None => Value::Null,
+    };
+
+    let report = json!({
+        "mango_group_pk": mgids.mango_group_pk.to_string(),
+        "maint_coll_ratio": mango_group.maint_coll_ratio.to_num::<f64>(),
+        "init_coll_ratio": mango_group.init_coll_ratio.to_num::<f64>(),
+        "tokens": tokens,
+        "utilization": utilization,
+        "margin_accounts": margin_accounts,
+        "fee_tier": fee_tier,
+    });
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn build_change_borrow_limit_instruction(
+    client: &RpcClient,
+    cids: &ClusterIds,
+    mgids: &MangoGroupIds,
+    payer_pk: &Pubkey,
+    token_symbol: &str,
+    borrow_limit: f64,
+    cache: Option<&GroupCacheEntry>,
+) -> Result<Instruction> {
+    let token_pk = &cids.symbols[token_symbol];
+    let token_i = cache.and_then(|c| c.token_indices.get(token_symbol).copied())
+        .unwrap_or_else(|| mgids.get_token_index(token_pk).unwrap());
+
+    let decimals = match cache.and_then(|c| c.decimals.get(token_symbol)) {
+        Some(&decimals) => decimals,
+        None => {
+            let mint_acc = client.get_account(token_pk)?;
+            spl_token::state::Mint::unpack(mint_acc.data.as_slice())?.decimals
+        }
+    };
+    Ok(change_borrow_limit(
+        &cids.mango_program_id,
+        &mgids.mango_group_pk,
+        payer_pk,
+        token_i,
+        spl_token::ui_amount_to_amount(borrow_limit, decimals)
+    )?)
+}
+
+fn build_settle_borrow_instructions(
+    client: &RpcClient,
+    cids: &ClusterIds,
+    mgids: &MangoGroupIds,
+    margin_account_pk: &Pubkey,
+    margin_account: &MarginAccount,
+    token_symbol: Option<&str>,
+    quantity: Option<f64>,
+) -> Result<Vec<Instruction>> {
+    match token_symbol {
+        Some(token_symbol) => {
+            let token_pk = &cids.symbols[token_symbol];
             let token_i = mgids.get_token_index(token_pk).unwrap();
 
             let mint_acc = client.get_account(token_pk)?;
             let mint = spl_token::state::Mint::unpack(mint_acc.data.as_slice())?;
-            let instruction = change_borrow_limit(
+
+            let quantity = spl_token::ui_amount_to_amount(
+                quantity.expect("quantity is required when token_symbol is given"), mint.decimals
+            );
+            Ok(vec![settle_borrow(
                 &cids.mango_program_id,
                 &mgids.mango_group_pk,
-                &payer.pubkey(),
+                margin_account_pk,
+                &margin_account.owner,
                 token_i,
-                spl_token::ui_amount_to_amount(borrow_limit, mint.decimals)
+                quantity
+            )?])
+        }
+        None => {
+            // Settle every token with a nonzero borrow, one instruction per token,
+            // batched across as few transactions as the packet size limit allows.
+            let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+            let mango_group = *MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
+            Ok((0..NUM_TOKENS)
+                .filter(|&i| margin_account.get_native_borrow(&mango_group.indexes[i], i) > 0)
+                .map(|i| settle_borrow(
+                    &cids.mango_program_id,
+                    &mgids.mango_group_pk,
+                    margin_account_pk,
+                    &margin_account.owner,
+                    i,
+                    u64::MAX
+                ))
+                .collect::<Result<Vec<_>, _>>()?)
+        }
+    }
+}
 
-            )?;
-            let instructions = vec![instruction];
-            let signers = vec![&payer];
-            send_instructions(&client, instructions, signers, &payer.pubkey())?;
+fn build_place_order_instruction(
+    client: &RpcClient,
+    cids: &ClusterIds,
+    mgids: &MangoGroupIds,
+    payer_pk: &Pubkey,
+    mango_group_name: &str,
+    margin_account: &str,
+    market_symbol: &str,
+    side: &str,
+    price: f64,
+    size: f64,
+    client_order_id: u64,
+    reduce_only: bool,
+) -> Result<Instruction> {
+    let margin_account_pk = Pubkey::from_str(margin_account)?;
+    let margin_account_acc = client.get_account(&margin_account_pk)?;
+    let margin_account = MarginAccount::load_from_bytes(margin_account_acc.data.as_slice())?;
+    assert_eq!(margin_account.owner, *payer_pk);
+
+    let market_index = market_index_for_symbol(mango_group_name, market_symbol);
+    let spot_market_pk = mgids.spot_market_pks[market_index];
+
+    let mango_group_acc = client.get_account(&mgids.mango_group_pk)?;
+    let mango_group = MangoGroup::load_from_bytes(mango_group_acc.data.as_slice())?;
+
+    let base_mint_acc = client.get_account(&mgids.mint_pks[market_index])?;
+    let base_mint = spl_token::state::Mint::unpack(base_mint_acc.data.as_slice())?;
+    let quote_mint_acc = client.get_account(mgids.mint_pks.last().unwrap())?;
+    let quote_mint = spl_token::state::Mint::unpack(quote_mint_acc.data.as_slice())?;
+
+    let dex_addrs = load_dex_market_addrs(client, &cids.dex_program_id, &spot_market_pk)?;
+
+    let base_unit = 10f64.powi(base_mint.decimals as i32);
+    let quote_unit = 10f64.powi(quote_mint.decimals as i32);
+    let limit_price = (((price * quote_unit) * dex_addrs.coin_lot_size as f64)
+        / (base_unit * dex_addrs.pc_lot_size as f64)) as u64;
+    let max_coin_qty = ((size * base_unit) / dex_addrs.coin_lot_size as f64) as u64;
+    let max_native_pc_qty = limit_price.checked_mul(max_coin_qty).unwrap()
+        .checked_mul(dex_addrs.pc_lot_size).unwrap();
+
+    let order = NewOrderInstructionV3 {
+        side: parse_side(side),
+        limit_price: NonZeroU64::new(limit_price).unwrap(),
+        max_coin_qty: NonZeroU64::new(max_coin_qty).unwrap(),
+        max_native_pc_qty_including_fees: NonZeroU64::new(max_native_pc_qty).unwrap(),
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        order_type: OrderType::Limit,
+        client_order_id,
+        limit: 65535,
+    };
+
+    Ok(place_order(
+        &cids.mango_program_id,
+        &mgids.mango_group_pk,
+        payer_pk,
+        &margin_account_pk,
+        &cids.dex_program_id,
+        &spot_market_pk,
+        &dex_addrs.request_queue_pk,
+        &dex_addrs.event_queue_pk,
+        &dex_addrs.bids_pk,
+        &dex_addrs.asks_pk,
+        &mgids.vault_pks[market_index],
+        &mango_group.signer_key,
+        &dex_addrs.base_vault_pk,
+        &dex_addrs.quote_vault_pk,
+        &mango_group.srm_vault,
+        &margin_account.open_orders,
+        mgids.oracle_pks.as_slice(),
+        order,
+        reduce_only,
+    )?)
+}
+
+fn instruction_to_json(ix: &Instruction) -> Value {
+    json!({
+        "program_id": ix.program_id.to_string(),
+        "accounts": ix.accounts.iter().map(|m| json!({
+            "pubkey": m.pubkey.to_string(),
+            "is_signer": m.is_signer,
+            "is_writable": m.is_writable,
+        })).collect::<Vec<Value>>(),
+        "data": base64::encode(&ix.data),
+    })
+}
+
+fn instruction_from_json(v: &Value) -> Result<Instruction> {
+    let program_id = Pubkey::from_str(v["program_id"].as_str().unwrap())?;
+    let accounts = v["accounts"].as_array().unwrap().iter().map(|a| -> Result<AccountMeta> {
+        Ok(AccountMeta {
+            pubkey: Pubkey::from_str(a["pubkey"].as_str().unwrap())?,
+            is_signer: a["is_signer"].as_bool().unwrap(),
+            is_writable: a["is_writable"].as_bool().unwrap(),
+        })
+    }).collect::<Result<Vec<AccountMeta>>>()?;
+    let data = base64::decode(v["data"].as_str().unwrap())?;
+    Ok(Instruction { program_id, accounts, data })
+}
+
+// Writes `instructions` plus a recent blockhash and the pubkeys that must sign to `path` as a
+// portable JSON envelope, instead of signing and submitting. Meant to be carried to an
+// air-gapped machine and completed there with `Command::SubmitSigned`.
+fn write_unsigned_envelope(
+    client: &RpcClient,
+    path: &str,
+    instructions: &[Instruction],
+    signer_pks: &[Pubkey],
+    payer_pk: &Pubkey,
+) -> Result<()> {
+    let (recent_blockhash, _fee_calc) = client.get_recent_blockhash()?;
+    let envelope = json!({
+        "fee_payer": payer_pk.to_string(),
+        "recent_blockhash": recent_blockhash.to_string(),
+        "required_signers": signer_pks.iter().map(|pk| pk.to_string()).collect::<Vec<String>>(),
+        "instructions": instructions.iter().map(instruction_to_json).collect::<Vec<Value>>(),
+    });
+    let mut f = File::create(path)?;
+    f.write_all(serde_json::to_string_pretty(&envelope)?.as_bytes())?;
+    println!("Wrote unsigned transaction envelope to {}", path);
+    Ok(())
+}
+
+// Commands route their assembled instructions through here: signed and submitted
+// immediately, unless `--output-unsigned` asked for an offline envelope instead.
+// `liquidate_margin_account` and the crank loop send directly and never write an envelope.
+fn dispatch_instructions(
+    output_unsigned: Option<&str>,
+    client: &RpcClient,
+    instructions: Vec<Instruction>,
+    signers: Vec<&Keypair>,
+    payer_pk: &Pubkey,
+) -> Result<()> {
+    match output_unsigned {
+        Some(path) => {
+            let signer_pks: Vec<Pubkey> = signers.iter().map(|k| k.pubkey()).collect();
+            write_unsigned_envelope(client, path, &instructions, signer_pks.as_slice(), payer_pk)
         }
+        None => send_instructions_batched(client, instructions, signers, payer_pk),
     }
+}
+
+// Fetches the `NUM_MARKETS` open orders accounts referenced by a margin account. Slots that are
+// still `Pubkey::default()` (never bootstrapped) are filled in with an empty account of the same
+// key, mirroring the on-chain skip-if-default convention in `MarginAccount::get_assets_val`.
+fn load_open_orders_accounts(client: &RpcClient, open_orders_pks: &[Pubkey; NUM_MARKETS]) -> Result<Vec<(Pubkey, Account)>> {
+    let real_pks: Vec<Pubkey> = open_orders_pks.iter().cloned().filter(|pk| *pk != Pubkey::default()).collect();
+    let mut fetched: HashMap<Pubkey, Account> = if real_pks.is_empty() {
+        HashMap::new()
+    } else {
+        client.get_multiple_accounts(&real_pks)?
+            .into_iter()
+            .zip(real_pks.iter())
+            .map(|(acc, pk)| acc.map(|a| (*pk, a)).ok_or_else(|| anyhow::anyhow!("missing open orders account {}", pk)))
+            .collect::<Result<HashMap<Pubkey, Account>>>()?
+    };
+    Ok(open_orders_pks.iter().map(|pk| {
+        if *pk == Pubkey::default() {
+            (*pk, Account::new(0, 0, &spl_token::ID))
+        } else {
+            (*pk, fetched.remove(pk).unwrap())
+        }
+    }).collect())
+}
+
+// Replicates `MarginAccount::get_collateral_ratio` client-side so the CLI can decide whether an
+// account is liquidatable without sending a transaction first.
+fn compute_coll_ratio(
+    client: &RpcClient,
+    mango_group: &MangoGroup,
+    margin_account: &MarginAccount,
+    oracle_accs: &[AccountInfo],
+) -> Result<U64F64> {
+    let mut open_orders_accounts = load_open_orders_accounts(client, &margin_account.open_orders)?;
+    let open_orders_accs: Vec<AccountInfo> = open_orders_accounts.iter_mut()
+        .map(|(pk, acc)| (&*pk, acc).into_account_info())
+        .collect();
+    let open_orders_accs: &[AccountInfo; NUM_MARKETS] = open_orders_accs.as_slice().try_into().unwrap();
+
+    let prices = get_prices(mango_group, oracle_accs)?;
+    Ok(margin_account.get_collateral_ratio(mango_group, &prices, open_orders_accs)?)
+}
+
+// Every MarginAccount belonging to `mango_group_pk`, found via the `mango_group` field at byte
+// offset 8 (after `account_flags: u64`).
+fn get_margin_accounts(client: &RpcClient, mango_program_id: &Pubkey, mango_group_pk: &Pubkey) -> Result<Vec<(Pubkey, MarginAccount)>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(size_of::<MarginAccount>() as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(8, &mango_group_pk.to_bytes())),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    Ok(client.get_program_accounts_with_config(mango_program_id, config)?
+        .into_iter()
+        .map(|(pk, acc)| (pk, *MarginAccount::load_from_bytes(acc.data.as_slice()).unwrap()))
+        .collect())
+}
+
+// Builds and sends the `partial_liquidate` instruction that brings `margin_account_pk` back
+// above `init_coll_ratio`, paid for by `payer`'s `in_token_symbol` holdings in exchange for
+// `out_token_symbol` collateral.
+fn liquidate_margin_account(
+    client: &RpcClient,
+    cids: &ClusterIds,
+    mgids: &MangoGroupIds,
+    mango_group: &MangoGroup,
+    payer: &solana_sdk::signature::Keypair,
+    margin_account_pk: &Pubkey,
+    margin_account: &MarginAccount,
+    in_token_symbol: &str,
+    out_token_symbol: &str,
+    max_deposit: f64,
+) -> Result<()> {
+    let in_token_pk = cids.symbols[in_token_symbol];
+    let out_token_pk = cids.symbols[out_token_symbol];
+    let in_token_index = mgids.get_token_index(&in_token_pk).unwrap();
+    let out_token_index = mgids.get_token_index(&out_token_pk).unwrap();
+
+    let in_mint_acc = client.get_account(&in_token_pk)?;
+    let in_mint = spl_token::state::Mint::unpack(in_mint_acc.data.as_slice())?;
+
+    let liqor_in_token_acc = client.get_token_accounts_by_owner_with_commitment(
+        &payer.pubkey(), TokenAccountsFilter::Mint(in_token_pk), CommitmentConfig::confirmed()
+    )?.value;
+    assert!(liqor_in_token_acc.len() > 0);
+    let liqor_in_token_pk = Pubkey::from_str(liqor_in_token_acc[0].pubkey.as_str())?;
+
+    let liqor_out_token_acc = client.get_token_accounts_by_owner_with_commitment(
+        &payer.pubkey(), TokenAccountsFilter::Mint(out_token_pk), CommitmentConfig::confirmed()
+    )?.value;
+    assert!(liqor_out_token_acc.len() > 0);
+    let liqor_out_token_pk = Pubkey::from_str(liqor_out_token_acc[0].pubkey.as_str())?;
+
+    let instruction = partial_liquidate(
+        &cids.mango_program_id,
+        &mgids.mango_group_pk,
+        &payer.pubkey(),
+        &liqor_in_token_pk,
+        &liqor_out_token_pk,
+        margin_account_pk,
+        &mgids.vault_pks[in_token_index],
+        &mgids.vault_pks[out_token_index],
+        &mango_group.signer_key,
+        &margin_account.open_orders,
+        mgids.oracle_pks.as_slice(),
+        mango_group.oracles2.as_slice(),
+        spl_token::ui_amount_to_amount(max_deposit, in_mint.decimals),
+    )?;
+
+    let instructions = vec![instruction];
+    let signers = vec![payer];
+    send_instructions(client, instructions, signers, &payer.pubkey())?;
     Ok(())
 }
 
+// Builds the per-account slice of a `GroupStatus` report: native deposits/borrows per token,
+// equity, and collateral ratio.
+fn margin_account_to_json(
+    client: &RpcClient,
+    mango_group: &MangoGroup,
+    margin_account_pk: &Pubkey,
+    margin_account: &MarginAccount,
+    prices: &[U64F64; NUM_TOKENS],
+    token_symbols: &[String],
+) -> Result<Value> {
+    let mut open_orders_accounts = load_open_orders_accounts(client, &margin_account.open_orders)?;
+    let open_orders_accs: Vec<AccountInfo> = open_orders_accounts.iter_mut()
+        .map(|(pk, acc)| (&*pk, acc).into_account_info())
+        .collect();
+    let open_orders_accs: &[AccountInfo; NUM_MARKETS] = open_orders_accs.as_slice().try_into().unwrap();
+
+    let equity = margin_account.get_equity(mango_group, prices, open_orders_accs)?;
+    let coll_ratio = margin_account.get_collateral_ratio(mango_group, prices, open_orders_accs)?;
+
+    let mut deposits = serde_json::Map::new();
+    let mut borrows = serde_json::Map::new();
+    for i in 0..NUM_TOKENS {
+        let index = &mango_group.indexes[i];
+        deposits.insert(token_symbols[i].clone(), json!(margin_account.get_native_deposit(index, i)));
+        borrows.insert(token_symbols[i].clone(), json!(margin_account.get_native_borrow(index, i)));
+    }
+
+    Ok(json!({
+        "margin_account_pk": margin_account_pk.to_string(),
+        "owner": margin_account.owner.to_string(),
+        "being_liquidated": margin_account.being_liquidated,
+        "deposits": deposits,
+        "borrows": borrows,
+        "equity": equity.to_num::<f64>(),
+        "collateral_ratio": coll_ratio.to_num::<f64>(),
+    }))
+}
+
 fn get_pk(json: &Value, name: &str) -> Pubkey {
     Pubkey::from_str(json[name].as_str().unwrap()).unwrap()
 }
@@ -3,13 +3,19 @@ use std::convert::Into;
 use std::str::FromStr;
 
 use anyhow::{anyhow, format_err, Result};
-use bytemuck::{bytes_of, Pod, Contiguous};
+use bytemuck::{bytes_of, cast_slice, Pod, Zeroable};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, NewAead};
 use rand::rngs::OsRng;
+use rand::RngCore;
+use safe_transmute::to_bytes::transmute_to_bytes;
+use serum_dex::state::Event;
 use solana_client::rpc_client::RpcClient;
 use solana_client::rpc_config::RpcSendTransactionConfig;
 use solana_client::rpc_request::RpcRequest;
 use solana_client::rpc_response::{RpcResult, RpcSimulateTransactionResult};
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::packet::PACKET_DATA_SIZE;
 use solana_sdk::program_pack::{Pack as TokenPack, Pack};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signature, Signer};
@@ -19,7 +25,9 @@ use spl_token::solana_program::instruction::Instruction;
 use spl_token::solana_program::program_pack::IsInitialized;
 use bip39::{Mnemonic, Seed, Language};
 use tiny_hderive::bip32::ExtendedPrivKey;
+use std::collections::BTreeSet;
 use std::{thread, time};
+use zeroize::Zeroize;
 
 #[derive(Clone, Debug)]
 pub enum Cluster {
@@ -83,6 +91,84 @@ pub fn read_keypair_file(s: &str) -> Result<Keypair> {
         .map_err(|_| format_err!("failed to read keypair from {}", s))
 }
 
+// File layout of an encrypted keypair: magic || version || salt || nonce || ciphertext.
+// The password is never written to disk; it's derived into the AEAD key with argon2 and the
+// derived key and decrypted keypair bytes are zeroized as soon as they've been consumed.
+const ENCRYPTED_KEYPAIR_MAGIC: &[u8; 4] = b"MGK1";
+const ENCRYPTED_KEYPAIR_VERSION: u8 = 1;
+const ENCRYPTED_KEYPAIR_SALT_LEN: usize = 16;
+const ENCRYPTED_KEYPAIR_NONCE_LEN: usize = 12;
+
+fn derive_encryption_key(password: &[u8], salt: &[u8]) -> Result<[u8; 32]> {
+    let hash = argon2::hash_raw(password, salt, &argon2::Config::default())
+        .map_err(|e| anyhow!("failed to derive key from password: {}", e))?;
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hash[0..32]);
+    Ok(key)
+}
+
+// Prompts for a password (with confirmation) and writes `keypair` to `path` in the encrypted
+// keystore format. Overwrites `path` if it already exists.
+pub fn write_encrypted_keypair(path: &str, keypair: &Keypair) -> Result<()> {
+    let password = rpassword::prompt_password_stdout("Password to encrypt keypair with: ")?;
+    let confirmation = rpassword::prompt_password_stdout("Confirm password: ")?;
+    if password != confirmation {
+        return Err(anyhow!("passwords did not match"));
+    }
+
+    let mut salt = [0u8; ENCRYPTED_KEYPAIR_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce_bytes = [0u8; ENCRYPTED_KEYPAIR_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce_bytes);
+
+    let mut key = derive_encryption_key(password.as_bytes(), &salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let mut plaintext = keypair.to_bytes().to_vec();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_slice())
+        .map_err(|_| anyhow!("failed to encrypt keypair"))?;
+    plaintext.zeroize();
+    key.zeroize();
+
+    let mut out = Vec::with_capacity(4 + 1 + salt.len() + nonce_bytes.len() + ciphertext.len());
+    out.extend_from_slice(ENCRYPTED_KEYPAIR_MAGIC);
+    out.push(ENCRYPTED_KEYPAIR_VERSION);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&ciphertext);
+    std::fs::write(path, out)?;
+    Ok(())
+}
+
+// Prompts for the password and decrypts the keystore at `path` back into a Keypair. The derived
+// key and decrypted scratch buffer are zeroized as soon as the Keypair has been parsed out of them.
+pub fn read_encrypted_keypair(path: &str) -> Result<Keypair> {
+    let data = std::fs::read(path)?;
+    let header_len = 4 + 1 + ENCRYPTED_KEYPAIR_SALT_LEN + ENCRYPTED_KEYPAIR_NONCE_LEN;
+    if data.len() < header_len || &data[0..4] != ENCRYPTED_KEYPAIR_MAGIC {
+        return Err(anyhow!("{} is not a recognized encrypted keypair file", path));
+    }
+    if data[4] != ENCRYPTED_KEYPAIR_VERSION {
+        return Err(anyhow!("unsupported encrypted keypair version {}", data[4]));
+    }
+    let salt = &data[5..5 + ENCRYPTED_KEYPAIR_SALT_LEN];
+    let nonce_bytes = &data[5 + ENCRYPTED_KEYPAIR_SALT_LEN..header_len];
+    let ciphertext = &data[header_len..];
+
+    let password = rpassword::prompt_password_stdout("Password to decrypt keypair: ")?;
+    let mut key = derive_encryption_key(password.as_bytes(), salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let mut plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|_| anyhow!("failed to decrypt keypair: wrong password or corrupt file"))?;
+    key.zeroize();
+
+    let keypair = Keypair::from_bytes(&plaintext)
+        .map_err(|_| anyhow!("decrypted data is not a valid keypair"))?;
+    plaintext.zeroize();
+    Ok(keypair)
+}
+
 
 pub fn create_account_instr(
     client: &RpcClient,
@@ -255,42 +341,81 @@ pub fn mint_to_new_account(
     Ok(recip_keypair)
 }
 
-pub fn send_txn(client: &RpcClient, txn: &Transaction, _simulate: bool) -> Result<Signature> {
-    // Ok(client.send_transaction_with_config(
-    //     txn,
-    //     RpcSendTransactionConfig {
-    //         skip_preflight: true,
-    //         preflight_commitment: None,
-    //         encoding: None
-    //     }
-    //
-    // )?)
+/// A transaction that reverted on-chain, or never confirmed in time. Carries enough to act on
+/// without re-deriving it from a bare RPC error: the decoded `(file_id, line)` of the failing
+/// `check!`/`check_eq!` assertion (see `convert_assertion_error`) when the program reported one,
+/// plus the simulation logs that produced it.
+#[derive(thiserror::Error, Debug)]
+pub enum SendTxnError {
+    #[error("transaction simulation failed at {src_file_id}:{line}: {logs:#?}")]
+    AssertionFailed { src_file_id: u32, line: u32, logs: Vec<String> },
+    #[error("transaction failed: {tx_error}: {logs:#?}")]
+    Reverted { tx_error: solana_sdk::transaction::TransactionError, logs: Vec<String> },
+    #[error("transaction {signature} unconfirmed after {elapsed:?}")]
+    Timeout { signature: Signature, elapsed: time::Duration },
+}
+
+/// Builds a `SendTxnError` out of a failed transaction by re-simulating it to recover logs, and
+/// decoding the custom program error code through `convert_assertion_error` when there is one.
+fn decode_txn_error(client: &RpcClient, txn: &Transaction, tx_error: solana_sdk::transaction::TransactionError) -> SendTxnError {
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::transaction::TransactionError;
+
+    let logs = simulate_transaction(client, txn, false, CommitmentConfig::confirmed())
+        .ok()
+        .and_then(|r| r.value.logs)
+        .unwrap_or_default();
+
+    if let TransactionError::InstructionError(_, InstructionError::Custom(code)) = tx_error {
+        let (line, src_file_id) = convert_assertion_error(code);
+        SendTxnError::AssertionFailed { src_file_id, line, logs }
+    } else {
+        SendTxnError::Reverted { tx_error, logs }
+    }
+}
+
+/// Submits `txn`, optionally simulating it first to fail fast with decoded logs, then resends on
+/// an exponential backoff while the signature remains unconfirmed under `CommitmentConfig::confirmed()`,
+/// up to a one-minute total timeout. On revert or timeout, returns a `SendTxnError`.
+pub fn send_txn(client: &RpcClient, txn: &Transaction, simulate: bool) -> Result<Signature> {
+    let commitment = CommitmentConfig::confirmed();
 
-    let txid = client.send_transaction_with_config(txn, RpcSendTransactionConfig {
+    if simulate {
+        let result = simulate_transaction(client, txn, true, commitment)?;
+        if let Some(tx_error) = result.value.err {
+            return Err(decode_txn_error(client, txn, tx_error).into());
+        }
+    }
+
+    let signature = client.send_transaction_with_config(txn, RpcSendTransactionConfig {
         skip_preflight: true,
         ..RpcSendTransactionConfig::default()
     })?;
 
-    for _ in 0..9 {
-        thread::sleep(time::Duration::from_millis(500));
+    let start = time::Instant::now();
+    let total_timeout = time::Duration::from_secs(60);
+    let mut backoff = time::Duration::from_millis(500);
+
+    loop {
+        match client.get_signature_status_with_commitment(&signature, commitment)? {
+            Some(Ok(())) => return Ok(signature),
+            Some(Err(tx_error)) => return Err(decode_txn_error(client, txn, tx_error).into()),
+            None => {}
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed >= total_timeout {
+            return Err(SendTxnError::Timeout { signature, elapsed }.into());
+        }
+
+        println!("Confirming txid: {}", signature.to_string());
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(time::Duration::from_secs(8));
         client.send_transaction_with_config(txn, RpcSendTransactionConfig {
             skip_preflight: true,
             ..RpcSendTransactionConfig::default()
         })?;
     }
-    println!("Confirming txid: {}", txid.to_string());
-    client.confirm_transaction(&txid)?;
-    Ok(txid)
-
-
-    // Ok(client.send_and_confirm_transaction_with_spinner_and_config(
-    //     txn,
-    //     CommitmentConfig::confirmed(),
-    //     RpcSendTransactionConfig {
-    //         skip_preflight: true,
-    //         ..RpcSendTransactionConfig::default()
-    //     },
-    // )?)
 }
 
 pub fn simulate_transaction(
@@ -335,41 +460,12 @@ pub fn account_unpacked<T: Pack + IsInitialized>(client: &RpcClient, addr: &Pubk
 }
 
 
-pub trait SignerNonce: Pod {
-    fn gen_signer_seeds<'a>(nonce: &'a Self, acc_pk: &'a Pubkey) -> [&'a [u8]; 2] {
-        [acc_pk.as_ref(), bytes_of(nonce)]
-    }
-    fn gen_signer_key(nonce: Self, acc_pk: &Pubkey, program_id: &Pubkey) -> Result<Pubkey>;
-    fn create_signer_key_and_nonce(program_id: &Pubkey, acc_pk: &Pubkey) -> (Pubkey, Self);
-}
-impl SignerNonce for u8 {
-
-    fn gen_signer_key(
-        nonce: Self,
-        acc_pk: &Pubkey,
-        program_id: &Pubkey,
-    ) -> Result<Pubkey> {
-        let seeds = Self::gen_signer_seeds(&nonce, acc_pk);
-        Ok(Pubkey::create_program_address(&seeds, program_id)?)
-    }
-
-    fn create_signer_key_and_nonce(program_id: &Pubkey, acc_pk: &Pubkey) -> (Pubkey, Self) {
-
-        for i in 0..=Self::MAX {
-            if let Ok(pk) = Self::gen_signer_key(i, acc_pk, program_id) {
-                return (pk, i);
-            }
-        }
-        panic!("Could not generate signer key");
-
-    }
-}
-
+// Used to re-derive the vault signer for serum dex markets, which define their own
+// `vault_signer_nonce: u64` convention independent of Mango's own group signer below.
 pub fn gen_signer_seeds<'a>(nonce: &'a u64, acc_pk: &'a Pubkey) -> [&'a [u8]; 2] {
     [acc_pk.as_ref(), bytes_of(nonce)]
 }
 
-
 pub fn gen_signer_key(
     nonce: u64,
     acc_pk: &Pubkey,
@@ -379,16 +475,31 @@ pub fn gen_signer_key(
     Ok(Pubkey::create_program_address(&seeds, program_id)?)
 }
 
+/// Canonical one-byte bump seed for a `MangoGroup`'s signer, matching
+/// `Pubkey::find_program_address`'s convention. See `program::utils::gen_signer_seeds`.
+pub fn gen_signer_seeds_bump<'a>(bump: &'a u8, acc_pk: &'a Pubkey) -> [&'a [u8]; 2] {
+    [acc_pk.as_ref(), std::slice::from_ref(bump)]
+}
 
-pub fn create_signer_key_and_nonce(program_id: &Pubkey, acc_pk: &Pubkey) -> (Pubkey, u64) {
+pub fn gen_signer_key_bump(
+    bump: u8,
+    acc_pk: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Pubkey> {
+    let seeds = gen_signer_seeds_bump(&bump, acc_pk);
+    Ok(Pubkey::create_program_address(&seeds, program_id)?)
+}
 
-    for i in 0..=u64::MAX_VALUE {
-        if let Ok(pk) = gen_signer_key(i, acc_pk, program_id) {
-            return (pk, i);
+/// Searches bump seeds downward from 255 to 0 -- the same order and convention as
+/// `Pubkey::find_program_address` -- and returns the first (highest) valid signer key and bump
+/// for a new `MangoGroup`. See `program::utils::create_signer_key_and_nonce`.
+pub fn create_signer_key_and_nonce(program_id: &Pubkey, acc_pk: &Pubkey) -> (Pubkey, u8) {
+    for bump in (0..=u8::MAX).rev() {
+        if let Ok(pk) = gen_signer_key_bump(bump, acc_pk, program_id) {
+            return (pk, bump);
         }
     }
     panic!("Could not generate signer key");
-
 }
 
 pub fn convert_assertion_error(e: u32) -> (u32, u32) {
@@ -398,6 +509,41 @@ pub fn convert_assertion_error(e: u32) -> (u32, u32) {
     (line, file_id)
 }
 
+/// Client-side table mapping a `ProgramError::Custom(n)` code recovered from a failed mango
+/// transaction to a human-readable description, kept in sync by hand with the
+/// `#[error(...)]` messages on `mango::error::MangoErrorCode` (see
+/// `mango::error::describe_error_code` for the on-chain-crate-side equivalent). Exists so SDKs
+/// that don't want to pull in the full program crate can still decode `Custom(n)`
+/// deterministically.
+pub fn describe_mango_error(code: u32) -> &'static str {
+    match code {
+        0 => "This instruction would exceed the borrow limit",
+        1 => "Your collateral ratio is below the minimum initial collateral ratio",
+        2 => "Quantity requested is above the available balance",
+        3 => "InvalidMangoGroupSize",
+        4 => "InvalidGroupOwner",
+        5 => "InvalidGroupFlags",
+        6 => "This margin account is not owned by the wallet address",
+        7 => "GroupNotRentExempt",
+        8 => "InvalidSignerKey",
+        9 => "InvalidProgramId",
+        10 => "NotLiquidatable",
+        11 => "InvalidOpenOrdersAccount",
+        12 => "SignerNecessary",
+        13 => "InvalidMangoVault",
+        14 => "The margin account has restricted functionality while being liquidated",
+        15 => "SRM is already part of MangoGroup. Deposit and withdraw SRM functionality disabled.",
+        16 => "Deprecated",
+        17 => "An account below init_coll_ratio may only place orders that reduce its existing net position",
+        18 => "MangoGroup indexes have not been updated recently enough to liquidate against; indexes were refreshed instead, retry",
+        19 => "An oracle's last aggregator round is older than MangoGroup's max_index_staleness",
+        20 => "A market's primary and secondary oracle medians disagree by more than MangoGroup's max_oracle_spread_bps",
+        21 => "This account was written by an older version of the program; call the matching migrate instruction before using it",
+        22 => "Account data length does not match the size of the type being loaded",
+        _ => "Unknown MangoErrorCode",
+    }
+}
+
 pub fn send_instructions(
     client: &RpcClient,
     instructions: Vec<Instruction>,
@@ -421,6 +567,145 @@ pub fn send_instructions(
     Ok(())
 }
 
+/// Like `send_instructions`, but packs `instructions` into as few transactions as fit under
+/// Solana's packet size limit instead of assuming they all fit in one. Instructions are kept in
+/// the order given, so callers should only rely on atomicity within a single resulting
+/// transaction, not across the whole batch.
+pub fn send_instructions_batched(
+    client: &RpcClient,
+    instructions: Vec<Instruction>,
+    signers: Vec<&Keypair>,
+    payer_pk: &Pubkey
+) -> Result<()> {
+    let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+
+    let mut batch: Vec<Instruction> = vec![];
+    for instruction in instructions {
+        let mut candidate = batch.clone();
+        candidate.push(instruction.clone());
+        let candidate_txn = Transaction::new_signed_with_payer(&candidate, Some(payer_pk), &signers, recent_hash);
+
+        if !batch.is_empty() && bincode::serialize(&candidate_txn)?.len() > PACKET_DATA_SIZE {
+            let txn = Transaction::new_signed_with_payer(&batch, Some(payer_pk), &signers, recent_hash);
+            send_txn(&client, &txn, false)?;
+            batch = vec![instruction];
+        } else {
+            batch = candidate;
+        }
+    }
+
+    if !batch.is_empty() {
+        let txn = Transaction::new_signed_with_payer(&batch, Some(payer_pk), &signers, recent_hash);
+        send_txn(&client, &txn, false)?;
+    }
+    Ok(())
+}
+
+
+// Mirrors serum-dex's `Queue<Event>` ring-buffer header. serum_dex doesn't expose a loader for
+// this off-chain (only the on-chain critbit/orderbook headers are public), so we hand-roll it the
+// same way Mango's own program hand-rolls `OrderBookStateHeader` for bids/asks.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct EventQueueHeader {
+    account_flags: u64,
+    head: u64,
+    count: u64,
+    seq_num: u64,
+}
+unsafe impl Zeroable for EventQueueHeader {}
+unsafe impl Pod for EventQueueHeader {}
+
+/// Reads `event_queue_pk`'s pending fill/out events and returns the distinct open-orders accounts
+/// that need servicing, oldest-event-first, capped to `batch_size` accounts.
+fn open_orders_pending_events(
+    client: &RpcClient,
+    event_queue_pk: &Pubkey,
+    batch_size: usize,
+) -> Result<(Vec<Pubkey>, usize)> {
+    let event_q_acc = client.get_account(event_queue_pk)?;
+    // Serum dex accounts are wrapped in a 5 byte "serum" header and 7 byte "padding" trailer
+    let data = &event_q_acc.data[5..event_q_acc.data.len() - 7];
+
+    let (header_bytes, event_bytes) = data.split_at(std::mem::size_of::<EventQueueHeader>());
+    let header: &EventQueueHeader = bytemuck::from_bytes(header_bytes);
+    let events: &[Event] = cast_slice(event_bytes);
+    let ring_len = events.len();
+
+    let mut seen = BTreeSet::new();
+    let mut open_orders_pks = vec![];
+    for i in 0..(header.count as usize) {
+        if open_orders_pks.len() >= batch_size {
+            break;
+        }
+        let event = &events[(header.head as usize + i) % ring_len];
+        let owner = Pubkey::new(transmute_to_bytes(&event.owner));
+        if seen.insert(owner) {
+            open_orders_pks.push(owner);
+        }
+    }
+    Ok((open_orders_pks, header.count as usize))
+}
+
+/// Cranks `market_pk`'s serum dex event queue: repeatedly consumes pending fill/out events so the
+/// embedded open-orders accounts (including those in Mango `MarginAccount::open_orders`) settle,
+/// looping until the queue drains and backing off with `poll_interval` when it's empty.
+pub fn crank_market(
+    client: &RpcClient,
+    dex_program_id: &Pubkey,
+    market_pk: &Pubkey,
+    event_queue_pk: &Pubkey,
+    coin_fee_receivable_pk: &Pubkey,
+    pc_fee_receivable_pk: &Pubkey,
+    payer: &Keypair,
+    batch_size: usize,
+    poll_interval: time::Duration,
+) -> Result<()> {
+    loop {
+        let crank_result: Result<bool> = (|| {
+            let (mut open_orders_pks, pending_count) =
+                open_orders_pending_events(client, event_queue_pk, batch_size)?;
+
+            if open_orders_pks.is_empty() {
+                return Ok(false);
+            }
+
+            // serum dex requires the open-orders accounts passed to ConsumeEvents to be sorted
+            open_orders_pks.sort();
+            println!(
+                "crank: {} consuming {} events across {} open-orders accounts ({} pending)",
+                market_pk, batch_size.min(pending_count), open_orders_pks.len(), pending_count
+            );
+
+            let open_orders_refs: Vec<&Pubkey> = open_orders_pks.iter().collect();
+            let consume_events_instruction = serum_dex::instruction::consume_events(
+                dex_program_id,
+                open_orders_refs,
+                market_pk,
+                event_queue_pk,
+                coin_fee_receivable_pk,
+                pc_fee_receivable_pk,
+                batch_size as u16,
+            )?;
+
+            send_instructions(client, vec![consume_events_instruction], vec![payer], &payer.pubkey())?;
+            Ok(true)
+        })();
+
+        match crank_result {
+            Ok(true) => continue,
+            Ok(false) => {
+                println!("crank: {} up to date, sleeping", market_pk);
+                thread::sleep(poll_interval);
+            }
+            Err(e) => {
+                println!("crank: {} failed: {}", market_pk, e);
+                thread::sleep(poll_interval);
+            }
+        }
+    }
+}
+
 
 fn seedphrase_to_seed(seed_phrase: &str, passphrase: &str) -> Result<Vec<u8>> {
     let mnemonic = Mnemonic::from_phrase(seed_phrase, Language::English).unwrap();
@@ -1,5 +1,5 @@
 use bytemuck::Contiguous;
-use num_enum::IntoPrimitive;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use solana_program::program_error::ProgramError;
 use thiserror::Error;
 
@@ -30,7 +30,7 @@ pub enum MangoError {
     MangoErrorCode { mango_error_code: MangoErrorCode, line: u32, source_file_id: SourceFileId},
 }
 
-#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, IntoPrimitive)]
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u32)]
 pub enum MangoErrorCode {
     #[error("MangoErrorCode::BorrowLimitExceeded This instruction would exceed the borrow limit")]
@@ -67,6 +67,18 @@ pub enum MangoErrorCode {
     FeeDiscountFunctionality,
     #[error("MangoErrorCode::Deprecated")]
     Deprecated,
+    #[error("MangoErrorCode::ReduceOnlyViolated An account below init_coll_ratio may only place orders that reduce its existing net position")]
+    ReduceOnlyViolated,
+    #[error("MangoErrorCode::StaleIndexes MangoGroup indexes have not been updated recently enough to liquidate against; indexes were refreshed instead, retry")]
+    StaleIndexes,
+    #[error("MangoErrorCode::StaleOracle An oracle's last aggregator round is older than MangoGroup's max_index_staleness")]
+    StaleOracle,
+    #[error("MangoErrorCode::StaleOrUnreliableOracle A market's primary and secondary oracle medians disagree by more than MangoGroup's max_oracle_spread_bps")]
+    StaleOrUnreliableOracle,
+    #[error("MangoErrorCode::UnsupportedVersion This account was written by an older version of the program; call the matching migrate instruction before using it")]
+    UnsupportedVersion,
+    #[error("MangoErrorCode::InvalidAccountSize Account data length does not match the size of the type being loaded")]
+    InvalidAccountSize,
 
     #[error("MangoErrorCode::Default Check the source code for more info")]
     Default = u32::MAX_VALUE,
@@ -92,6 +104,18 @@ impl From<serum_dex::error::DexError> for MangoError {
     }
 }
 
+/// Decodes a raw `ProgramError::Custom(n)` code back into a human-readable description, so a
+/// client that only has the numeric code from a failed transaction (no `MangoError` value to
+/// `Display`) can still explain the failure. Mirrors the discriminants `MangoErrorCode` derives
+/// via `IntoPrimitive`/`TryFromPrimitive`; see `common::describe_mango_error` for the SDK-side
+/// table clients should use when they don't link against this crate.
+pub fn describe_error_code(code: u32) -> String {
+    match MangoErrorCode::try_from_primitive(code) {
+        Ok(mango_error_code) => mango_error_code.to_string(),
+        Err(_) => format!("unknown MangoErrorCode {}", code),
+    }
+}
+
 #[inline]
 pub fn check_assert(
     cond: bool,
@@ -0,0 +1,36 @@
+use bytemuck::{Pod, Zeroable};
+use fixed::types::U64F64;
+use solana_program::log::sol_log_data;
+use solana_program::pubkey::Pubkey;
+
+use crate::state::NUM_TOKENS;
+
+/// Fixed-layout, bytemuck-encoded record of a liquidation's before/after state, emitted via
+/// `emit` instead of formatted into `msg!`. A `msg!`-formatted JSON-ish string costs compute
+/// units to build, gets silently truncated by the runtime's log size limit, and forces indexers
+/// to regex-scrape log lines; logging this struct's raw bytes through `sol_log_data` lets them
+/// decode it deterministically with `bytemuck::from_bytes` instead.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct LiquidationEvent {
+    pub liqee: Pubkey,
+    pub liqor: Pubkey,
+    pub slot: u64,
+    pub start_assets: [U64F64; NUM_TOKENS],
+    pub start_liabs: [U64F64; NUM_TOKENS],
+    pub end_assets: [U64F64; NUM_TOKENS],
+    pub end_liabs: [U64F64; NUM_TOKENS],
+    pub prices: [U64F64; NUM_TOKENS],
+    pub total_deposits: [U64F64; NUM_TOKENS],
+    pub socialized_losses: u8,
+    pub padding: [u8; 7],
+}
+unsafe impl Zeroable for LiquidationEvent {}
+unsafe impl Pod for LiquidationEvent {}
+
+impl LiquidationEvent {
+    /// Base64-encodes `bytemuck::bytes_of(self)` and logs it as a single `Program data: ...` line.
+    pub fn emit(&self) {
+        sol_log_data(&[bytemuck::bytes_of(self)]);
+    }
+}
@@ -1,7 +1,7 @@
 use std::convert::TryInto;
 use std::num::NonZeroU64;
 
-use arrayref::{array_ref, array_refs};
+use arrayref::{array_ref, array_refs, mut_array_refs};
 use bytemuck::{cast_slice, cast_slice_mut};
 use fixed::types::U64F64;
 use num_enum::TryFromPrimitive;
@@ -10,7 +10,7 @@ use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
 
-use crate::state::{NUM_TOKENS, INFO_LEN};
+use crate::state::{BorrowFeeParams, InterestRateParams, LiquidationParams, NUM_TOKENS, NUM_MARKETS, INFO_LEN};
 
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -41,7 +41,9 @@ pub enum MangoInstruction {
         signer_nonce: u64,
         maint_coll_ratio: U64F64,
         init_coll_ratio: U64F64,
-        borrow_limits: [u64; NUM_TOKENS]
+        borrow_limits: [u64; NUM_TOKENS],
+        /// Per-token `borrow` origination fee and host-referral split; see `BorrowFeeParams`.
+        borrow_fee_params: [BorrowFeeParams; NUM_TOKENS]
     },
 
     /// Initialize a margin account for a user
@@ -88,16 +90,24 @@ pub enum MangoInstruction {
         quantity: u64
     },
 
-    /// Borrow by incrementing MarginAccount.borrows given collateral ratio is below init_coll_rat
+    /// Borrow by incrementing MarginAccount.borrows given collateral ratio is below init_coll_rat.
+    /// Charges `mango_group.borrow_fee_params[token_index]`'s origination fee, added on top of
+    /// `quantity` to the borrower's debt; the host's `host_fee_bps` share is paid out of
+    /// `vault_acc` to `host_acc` immediately, the rest is tallied into `mango_group.fees`. Pass
+    /// `vault_acc`'s own key as `host_acc` to opt out of a host split for this call.
     ///
-    /// Accounts expected by this instruction (4 + 2 * NUM_MARKETS):
+    /// Accounts expected by this instruction (8 + 2 * NUM_MARKETS):
     ///
     /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
     /// 1. `[writable]` margin_account_acc - the margin account for this user
     /// 2. `[signer]` owner_acc - Solana account of owner of the margin account
     /// 3. `[]` clock_acc - Clock sysvar account
-    /// 4..4+NUM_MARKETS `[]` open_orders_accs - open orders for each of the spot market
-    /// 4+NUM_MARKETS..4+2*NUM_MARKETS `[]`
+    /// 4. `[writable]` vault_acc - TokenAccount owned by MangoGroup for this token
+    /// 5. `[]` signer_acc - acc pointed to by signer_key
+    /// 6. `[]` token_prog_acc - acc pointed to by SPL token program id
+    /// 7. `[writable]` host_acc - TokenAccount to receive the host's share of the origination fee
+    /// 8..8+NUM_MARKETS `[]` open_orders_accs - open orders for each of the spot market
+    /// 8+NUM_MARKETS..8+2*NUM_MARKETS `[]`
     ///     oracle_accs - flux aggregator feed accounts
     Borrow {
         token_index: usize,
@@ -117,6 +127,16 @@ pub enum MangoInstruction {
         quantity: u64
     },
 
+    /// Use all of a MarginAccount's deposits to reduce its borrows, for every token at once
+    ///
+    /// Accounts expected by this instruction (4):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[writable]` margin_account_acc - the margin account for this user
+    /// 2. `[signer]` owner_acc - Solana account of owner of the margin account
+    /// 3. `[]` clock_acc - Clock sysvar account
+    SettleBorrowAll,
+
     /// Take over a MarginAccount that is below init_coll_ratio by depositing funds
     ///
     /// Accounts expected by this instruction (5 + 2 * NUM_MARKETS + 2 * NUM_TOKENS):
@@ -174,7 +194,9 @@ pub enum MangoInstruction {
     },
 
     // Proxy instructions to Dex
-    /// Place an order on the Serum Dex using Mango margin facilities
+    /// Place an order on the Serum Dex using Mango margin facilities. `reduce_only` is OR-ed with
+    /// the coll_ratio-derived reduce-only check, so a caller can require an order to only shrink
+    /// their existing position even while healthy, rather than silently opening a new borrow.
     ///
     /// Accounts expected by this instruction (17 + 2 * NUM_MARKETS):
     ///
@@ -199,7 +221,8 @@ pub enum MangoInstruction {
     /// 17+NUM_MARKETS..17+2*NUM_MARKETS `[]`
     ///     oracle_accs - flux aggregator feed accounts
     PlaceOrder {
-        order: serum_dex::instruction::NewOrderInstructionV3
+        order: serum_dex::instruction::NewOrderInstructionV3,
+        reduce_only: bool
     },
 
     /// Settle all funds from serum dex open orders into MarginAccount positions
@@ -260,6 +283,33 @@ pub enum MangoInstruction {
         client_id: u64
     },
 
+    /// Cancel up to `limit` of the caller's own resting orders on each spot market, without
+    /// needing to know each order's id or client_id. Mirrors the compute-budget-aware `limit`
+    /// pattern used by `ForceCancelOrders`, but is self-serve and requires the owner's signature
+    /// rather than a liquidator's.
+    ///
+    /// Accounts expected by this instruction (6 + 5 * NUM_MARKETS):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` owner_acc - MarginAccount owner
+    /// 2. `[]` margin_account_acc - MarginAccount
+    /// 3. `[]` clock_acc - Clock sysvar account
+    /// 4. `[]` dex_prog_acc - program id of serum dex
+    /// 5. `[]` signer_acc - MangoGroup signer key
+    /// 6..6+NUM_MARKETS `[writable]` spot_market_accs - serum dex MarketState for each spot market
+    /// 6+NUM_MARKETS..6+2*NUM_MARKETS `[writable]` bids_accs - serum dex bids for each spot market
+    /// 6+2*NUM_MARKETS..6+3*NUM_MARKETS `[writable]` asks_accs - serum dex asks for each spot market
+    /// 6+3*NUM_MARKETS..6+4*NUM_MARKETS `[writable]`
+    ///     open_orders_accs - OpenOrders for each spot market
+    /// 6+4*NUM_MARKETS..6+5*NUM_MARKETS `[writable]`
+    ///     dex_event_queue_accs - serum dex event queue for each spot market
+    CancelAllOrders {
+        /// Max orders to cancel per market -- could be useful to lower this if running into
+        /// compute limits
+        /// Recommended: 5
+        limit: u8
+    },
+
     /// Change the borrow limit using admin key. This will not affect any open positions on any MarginAccount
     /// This is intended to be an instruction only in alpha stage while liquidity is slowly improved
     ///
@@ -272,7 +322,23 @@ pub enum MangoInstruction {
         borrow_limit: u64
     },
 
-    /// Place an order on the Serum Dex and settle funds from the open orders account
+    /// Change a token's kinked interest rate curve using the admin key. Takes effect on the next
+    /// `update_indexes` call; does not retroactively touch interest already accrued into the
+    /// borrow/deposit indexes.
+    ///
+    /// Accounts expected by this instruction (2):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` admin_acc - admin of the MangoGroup
+    ChangeInterestParams {
+        token_index: usize,
+        interest_rate_params: InterestRateParams
+    },
+
+    /// Place an order on the Serum Dex and settle funds from the open orders account.
+    /// `reduce_only` is OR-ed with the coll_ratio-derived reduce-only check, so a caller can
+    /// require an order to only shrink their existing position even while healthy, rather than
+    /// silently opening a new borrow.
     ///
     /// Accounts expected by this instruction (19 + 2 * NUM_MARKETS):
     ///
@@ -299,12 +365,13 @@ pub enum MangoInstruction {
     /// 19+NUM_MARKETS..19+2*NUM_MARKETS `[]`
     ///     oracle_accs - flux aggregator feed accounts
     PlaceAndSettle {
-        order: serum_dex::instruction::NewOrderInstructionV3
+        order: serum_dex::instruction::NewOrderInstructionV3,
+        reduce_only: bool
     },
 
     /// Allow a liquidator to cancel open orders and settle to recoup funds for partial liquidation
     ///
-    /// Accounts expected by this instruction (16 + 2 * NUM_MARKETS):
+    /// Accounts expected by this instruction (16 + 3 * NUM_MARKETS):
     ///
     /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
     /// 1. `[signer]` liqor_acc - liquidator's solana account
@@ -325,6 +392,9 @@ pub enum MangoInstruction {
     /// 16..16+NUM_MARKETS `[writable]` open_orders_accs - open orders for each of the spot market
     /// 16+NUM_MARKETS..16+2*NUM_MARKETS `[]`
     ///     oracle_accs - flux aggregator feed accounts
+    /// 16+2*NUM_MARKETS..16+3*NUM_MARKETS `[]`
+    ///     oracle2_accs - secondary flux aggregator feed accounts, cross-checked against
+    ///     `mango_group.oracles2` wherever a market has one configured; see `get_prices`
     ForceCancelOrders {
         /// Max orders to cancel -- could be useful to lower this if running into compute limits
         /// Recommended: 5
@@ -333,7 +403,7 @@ pub enum MangoInstruction {
 
     /// Take over a MarginAccount that is below init_coll_ratio by depositing funds
     ///
-    /// Accounts expected by this instruction (10 + 2 * NUM_MARKETS):
+    /// Accounts expected by this instruction (10 + 3 * NUM_MARKETS):
     ///
     /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
     /// 1. `[signer]` liqor_acc - liquidator's solana account
@@ -348,6 +418,9 @@ pub enum MangoInstruction {
     /// 10..10+NUM_MARKETS `[]` open_orders_accs - open orders for each of the spot market
     /// 10+NUM_MARKETS..10+2*NUM_MARKETS `[]`
     ///     oracle_accs - flux aggregator feed accounts
+    /// 10+2*NUM_MARKETS..10+3*NUM_MARKETS `[]`
+    ///     oracle2_accs - secondary flux aggregator feed accounts, cross-checked against
+    ///     `mango_group.oracles2` wherever a market has one configured; see `get_prices`
     PartialLiquidate {
         /// Quantity of the token being deposited to repay borrows
         max_deposit: u64
@@ -356,33 +429,282 @@ pub enum MangoInstruction {
 
     AddMarginAccountInfo {
         info: [u8; INFO_LEN]
-    }
+    },
+
+    /// Deposit into a MarginAccount and place+settle a Serum Dex order in a single instruction,
+    /// so the account is never left in an in-between state if a separate `PlaceAndSettle` were
+    /// to fail after `Deposit` had already landed. `quantity` is deposited into whichever of
+    /// `base_vault_acc`/`quote_vault_acc` the order's side spends (quote for a bid, base for an
+    /// ask) before the order is placed.
+    ///
+    /// Accounts expected by this instruction (20 + 2 * NUM_MARKETS):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` owner_acc - MarginAccount owner
+    /// 2. `[writable]` margin_account_acc - MarginAccount
+    /// 3. `[]` clock_acc - Clock sysvar account
+    /// 4. `[writable]` token_account_acc - TokenAccount owned by user which will be sending the funds
+    /// 5. `[]` dex_prog_acc - program id of serum dex
+    /// 6. `[writable]` spot_market_acc - serum dex MarketState
+    /// 7. `[writable]` dex_request_queue_acc - serum dex request queue for this market
+    /// 8. `[writable]` dex_event_queue - serum dex event queue for this market
+    /// 9. `[writable]` bids_acc - serum dex bids for this market
+    /// 10. `[writable]` asks_acc - serum dex asks for this market
+    /// 11. `[writable]` base_vault_acc - mango's vault for base currency
+    /// 12. `[writable]` quote_vault_acc - mango's vault for quote currency
+    /// 13. `[]` signer_acc - mango signer key
+    /// 14. `[writable]` dex_base_acc - serum dex market's vault for base (coin) currency
+    /// 15. `[writable]` dex_quote_acc - serum dex market's vault for quote (pc) currency
+    /// 16. `[]` spl token program
+    /// 17. `[]` the rent sysvar
+    /// 18. `[writable]` srm_vault_acc - MangoGroup's srm_vault used for fee reduction
+    /// 19. `[]` dex_signer_acc - signer for serum dex MarketState
+    /// 20..20+NUM_MARKETS `[writable]` open_orders_accs - open orders for each of the spot market
+    /// 20+NUM_MARKETS..20+2*NUM_MARKETS `[]`
+    ///     oracle_accs - flux aggregator feed accounts
+    DepositAndPlace {
+        quantity: u64,
+        order: serum_dex::instruction::NewOrderInstructionV3
+    },
+
+    /// Deposit MSRM owed to this MarginAccount's fee-tier account. Holding any MSRM grants the
+    /// maximum fee tier -- see `MangoGroup::srm_fee_tier` for how it combines with SRM.
+    /// These MSRM are not at risk and are not counted towards collateral or any margin calculations.
+    ///
+    /// Accounts expected by this instruction (8):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[writable]` mango_srm_account_acc - the mango srm account for user
+    /// 2. `[signer]` owner_acc - Solana account of owner of the margin account
+    /// 3. `[writable]` msrm_account_acc - TokenAccount owned by user which will be sending the funds
+    /// 4. `[writable]` msrm_vault_acc - MSRM vault of MangoGroup
+    /// 5. `[]` token_prog_acc - acc pointed to by SPL token program id
+    /// 6. `[]` clock_acc - Clock sysvar account
+    /// 7. `[]` rent_acc - Rent sysvar account
+    DepositMsrm {
+        quantity: u64
+    },
+    /// Withdraw MSRM owed to this MarginAccount's fee-tier account.
+    ///
+    /// Accounts expected by this instruction (8):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[writable]` mango_srm_account_acc - the mango srm account for user
+    /// 2. `[signer]` owner_acc - Solana account of owner of the margin account
+    /// 3. `[writable]` msrm_account_acc - TokenAccount owned by user which will be sending the funds
+    /// 4. `[writable]` msrm_vault_acc - MSRM vault of MangoGroup
+    /// 5. `[]` signer_acc - acc pointed to by signer_key
+    /// 6. `[]` token_prog_acc - acc pointed to by SPL token program id
+    /// 7. `[]` clock_acc - Clock sysvar account
+    WithdrawMsrm {
+        quantity: u64
+    },
+
+    /// Place and settle Serum Dex orders on up to NUM_MARKETS spot markets in a single
+    /// instruction. Unlike calling `PlaceAndSettle` once per market, the collateral ratio and
+    /// borrow-limit checks run exactly once after every order has been booked instead of once
+    /// per market, so it costs one `get_collateral_ratio` instead of NUM_MARKETS of them.
+    /// `orders[i]` is `Some` for every market the caller wants to trade this call and `None` for
+    /// the rest; the account bundle for market `i` must still be supplied -- and must point at
+    /// the MangoGroup's spot market for `i` -- even when `orders[i]` is `None`, so market index
+    /// and account position always line up.
+    ///
+    /// Accounts expected by this instruction (10 + 9 * NUM_MARKETS + 2 * NUM_MARKETS):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` owner_acc - MarginAccount owner
+    /// 2. `[writable]` margin_account_acc - MarginAccount
+    /// 3. `[]` clock_acc - Clock sysvar account
+    /// 4. `[]` dex_prog_acc - program id of serum dex
+    /// 5. `[writable]` quote_vault_acc - mango vault for quote currency, shared by every market
+    /// 6. `[]` signer_acc - mango signer key
+    /// 7. `[]` token_prog_acc - SPL token program
+    /// 8. `[]` rent_acc - the rent sysvar
+    /// 9. `[writable]` srm_vault_acc - MangoGroup's srm_vault used for fee reduction
+    /// 10+9*i..10+9*i+9 for i in 0..NUM_MARKETS - the 9-account bundle for spot market i:
+    ///     `[writable]` spot_market_acc, `[writable]` dex_request_queue_acc,
+    ///     `[writable]` dex_event_queue_acc, `[writable]` bids_acc, `[writable]` asks_acc,
+    ///     `[writable]` base_vault_acc - mango vault for market i's base currency,
+    ///     `[writable]` dex_base_acc, `[writable]` dex_quote_acc, `[]` dex_signer_acc
+    /// 10+9*NUM_MARKETS..10+9*NUM_MARKETS+NUM_MARKETS `[writable]` open_orders_accs - open orders for each of the spot market
+    /// 10+9*NUM_MARKETS+NUM_MARKETS..10+9*NUM_MARKETS+2*NUM_MARKETS `[]`
+    ///     oracle_accs - flux aggregator feed accounts
+    PlaceAndSettleMulti {
+        orders: [Option<serum_dex::instruction::NewOrderInstructionV3>; NUM_MARKETS]
+    },
+
+    /// Cancel and settle a liquidatee's open orders across every spot market in a single
+    /// instruction. Unlike calling `ForceCancelOrders` once per market, `being_liquidated` and
+    /// the collateral ratio are only re-evaluated once, after every market with open orders has
+    /// been cleared, instead of once per market. `limit` caps the number of orders cancelled per
+    /// market, independently for each market -- it is not a budget shared across markets.
+    /// Markets where `liqee_margin_account.open_orders[i]` is the default pubkey are skipped, but
+    /// their account bundle must still be supplied so market index and account position line up.
+    ///
+    /// Accounts expected by this instruction (8 + 8 * NUM_MARKETS + 3 * NUM_MARKETS):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` liqor_acc - liquidator's solana account
+    /// 2. `[writable]` liqee_margin_account_acc - MarginAccount of liquidatee
+    /// 3. `[writable]` quote_vault_acc - mango vault for quote currency, shared by every market
+    /// 4. `[]` signer_acc - mango signer key
+    /// 5. `[]` token_prog_acc - SPL token program
+    /// 6. `[]` dex_prog_acc - Serum dex program id
+    /// 7. `[]` clock_acc - Clock sysvar account
+    /// 8+8*i..8+8*i+8 for i in 0..NUM_MARKETS - the 8-account bundle for spot market i:
+    ///     `[writable]` base_vault_acc - mango vault for market i's base currency,
+    ///     `[writable]` spot_market_acc, `[writable]` bids_acc, `[writable]` asks_acc,
+    ///     `[writable]` dex_event_queue_acc, `[writable]` dex_base_acc, `[writable]` dex_quote_acc,
+    ///     `[]` dex_signer_acc
+    /// 8+8*NUM_MARKETS..8+8*NUM_MARKETS+NUM_MARKETS `[writable]` open_orders_accs - open orders for each of the spot market
+    /// 8+8*NUM_MARKETS+NUM_MARKETS..8+8*NUM_MARKETS+2*NUM_MARKETS `[]`
+    ///     oracle_accs - flux aggregator feed accounts
+    /// 8+8*NUM_MARKETS+2*NUM_MARKETS..8+8*NUM_MARKETS+3*NUM_MARKETS `[]`
+    ///     oracle2_accs - secondary flux aggregator feed accounts, cross-checked against
+    ///     `mango_group.oracles2` wherever a market has one configured; see `get_prices`
+    ForceCancelAllOrders {
+        /// Max orders to cancel per market -- could be useful to lower this if running into compute limits
+        /// Recommended: 5
+        limit: u8
+    },
+
+    /// Change `MangoGroup::liquidation_params` using the admin key. Takes effect immediately on
+    /// the next `partial_liquidate`/`get_in_out_quantities` call.
+    ///
+    /// Accounts expected by this instruction (2):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` admin_acc - admin of the MangoGroup
+    ChangeLiquidationParams {
+        liquidation_params: LiquidationParams
+    },
+
+    /// Unwind a liquidatee's spot positions directly against the dex instead of waiting for a
+    /// liquidator to take the other side of the position -- see `force_liquidate_on_dex`. Places
+    /// one IOC order per market with a nonzero net position, priced through the oracle by
+    /// `FORCE_LIQUIDATE_SLIPPAGE_BPS`, settles the fill, then settles borrows against whatever
+    /// deposits the unwind freed up. Markets where `liqee_margin_account.open_orders[i]` is the
+    /// default pubkey are skipped, but their account bundle must still be supplied so market
+    /// index and account position line up.
+    ///
+    /// Accounts expected by this instruction (10 + 9 * NUM_MARKETS + 3 * NUM_MARKETS):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` liqor_acc - liquidator's solana account
+    /// 2. `[writable]` liqee_margin_account_acc - MarginAccount of liquidatee
+    /// 3. `[]` clock_acc - Clock sysvar account
+    /// 4. `[]` dex_prog_acc - Serum dex program id
+    /// 5. `[writable]` quote_vault_acc - mango vault for quote currency, shared by every market
+    /// 6. `[]` signer_acc - mango signer key
+    /// 7. `[]` token_prog_acc - SPL token program
+    /// 8. `[]` rent_acc - the rent sysvar
+    /// 9. `[writable]` srm_vault_acc - MangoGroup's srm_vault used for fee reduction
+    /// 10+9*i..10+9*i+9 for i in 0..NUM_MARKETS - the 9-account bundle for spot market i:
+    ///     `[writable]` spot_market_acc, `[writable]` dex_request_queue_acc,
+    ///     `[writable]` dex_event_queue_acc, `[writable]` bids_acc, `[writable]` asks_acc,
+    ///     `[writable]` base_vault_acc - mango vault for market i's base currency,
+    ///     `[writable]` dex_base_acc, `[writable]` dex_quote_acc, `[]` dex_signer_acc
+    /// 10+9*NUM_MARKETS..10+9*NUM_MARKETS+NUM_MARKETS `[writable]` open_orders_accs - open orders for each of the spot market
+    /// 10+9*NUM_MARKETS+NUM_MARKETS..10+9*NUM_MARKETS+2*NUM_MARKETS `[]`
+    ///     oracle_accs - flux aggregator feed accounts
+    /// 10+9*NUM_MARKETS+2*NUM_MARKETS..10+9*NUM_MARKETS+3*NUM_MARKETS `[]`
+    ///     oracle2_accs - secondary flux aggregator feed accounts, cross-checked against
+    ///     `mango_group.oracles2` wherever a market has one configured; see `get_prices`
+    ForceLiquidateOnDex {
+        /// Max orders the dex should match against per IOC order -- could be useful to lower
+        /// this if running into compute limits. Recommended: 5
+        limit: u16
+    },
+
+    /// Change a token's `MangoGroup::asset_weights`/`liab_weights` haircut using the admin key.
+    /// `asset_weight` must be in `(0, ONE_U64F64]` and `liab_weight` in `[ONE_U64F64, u64::MAX]`
+    /// -- collateral can only be discounted, never marked up, and liabilities only marked up,
+    /// never discounted. Takes effect immediately on the next margin calculation.
+    ///
+    /// Accounts expected by this instruction (2):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` admin_acc - admin of the MangoGroup
+    ChangeCollateralWeights {
+        token_index: usize,
+        asset_weight: U64F64,
+        liab_weight: U64F64,
+    },
+
+    /// Rewrites `target_acc` -- a `MangoGroup`, `MarginAccount`, or `MangoSrmAccount` -- from an
+    /// older on-disk layout into the current one in place, bumping its `version` field. The
+    /// account's `AccountFlag` bits say which of the three it is; see
+    /// `MangoGroup::migrate`/`MarginAccount::migrate`/`MangoSrmAccount::migrate` for what each
+    /// does. `load_checked`/`load_mut_checked` throw `MangoErrorCode::UnsupportedVersion` instead
+    /// of silently misreading an un-migrated account, so this is the fix for that error.
+    ///
+    /// Accounts expected by this instruction (2):
+    ///
+    /// 0. `[writable]` target_acc - the account to migrate
+    /// 1. `[]` rent_acc - the rent sysvar, checked against `target_acc`'s new size if it grows
+    ///    (only `MangoSrmAccount` does; see `MangoSrmAccount::migrate`)
+    Migrate,
+
+    /// Sets `mango_group.oracles2[token_index]`, the secondary price feed `get_prices` cross-checks
+    /// against `oracles[token_index]` for liquidation-sensitive callers -- previously there was no
+    /// instruction to populate this on a deployed group at all. `oracle2_acc` must be an
+    /// initialized flux aggregator account, same as a primary oracle passed to `InitMangoGroup`;
+    /// there's no way to unset a market back to "no secondary oracle" short of this, since a
+    /// dedicated unset would mean accepting an arbitrary, unvalidated account as the new
+    /// `Pubkey::default()` sentinel.
+    ///
+    /// Accounts expected by this instruction (3):
+    ///
+    /// 0. `[writable]` mango_group_acc - MangoGroup that this margin account is for
+    /// 1. `[signer]` admin_acc - admin of the MangoGroup
+    /// 2. `[]` oracle2_acc - the flux aggregator feed to use as `token_index`'s secondary oracle
+    SetOracle2 {
+        token_index: usize,
+    },
 }
 
 
 impl MangoInstruction {
+    /// Decodes `input` into a `MangoInstruction`, returning `None` for an unrecognized
+    /// discriminant or for a buffer too short to hold the fields a variant requires, rather
+    /// than panicking via `array_ref!`'s length assertion.
     pub fn unpack(input: &[u8]) -> Option<Self> {
+        if input.len() < 4 { return None; }
         let (&discrim, data) = array_refs![input, 4; ..;];
         let discrim = u32::from_le_bytes(discrim);
         Some(match discrim {
             0 => {
-                let data = array_ref![data, 0, 40 + 8 * NUM_TOKENS];
+                const BORROW_FEE_PARAMS_LEN: usize = 18;  // U64F64 (16) + u16 (2)
+                if data.len() < 40 + 8 * NUM_TOKENS + BORROW_FEE_PARAMS_LEN * NUM_TOKENS { return None; }
+                let data = array_ref![data, 0, 40 + 8 * NUM_TOKENS + BORROW_FEE_PARAMS_LEN * NUM_TOKENS];
                 let (
                     signer_nonce,
                     maint_coll_ratio,
                     init_coll_ratio,
-                    borrow_limits
-                ) = array_refs![data, 8, 16, 16, 8 * NUM_TOKENS];
+                    borrow_limits,
+                    borrow_fee_params_data
+                ) = array_refs![data, 8, 16, 16, 8 * NUM_TOKENS, BORROW_FEE_PARAMS_LEN * NUM_TOKENS];
 
                 let mut aligned_borrow_limits = [0u64; NUM_TOKENS];
                 let buffer: &mut [u8] = cast_slice_mut(&mut aligned_borrow_limits);
                 buffer.copy_from_slice(borrow_limits);
 
+                let mut borrow_fee_params = [crate::state::DEFAULT_BORROW_FEE_PARAMS; NUM_TOKENS];
+                for i in 0..NUM_TOKENS {
+                    let entry = array_ref![borrow_fee_params_data, i * BORROW_FEE_PARAMS_LEN, BORROW_FEE_PARAMS_LEN];
+                    let (origination_fee_rate, host_fee_bps) = array_refs![entry, 16, 2];
+                    borrow_fee_params[i] = BorrowFeeParams {
+                        origination_fee_rate: U64F64::from_le_bytes(*origination_fee_rate),
+                        host_fee_bps: u16::from_le_bytes(*host_fee_bps),
+                    };
+                }
+
                 MangoInstruction::InitMangoGroup {
                     signer_nonce: u64::from_le_bytes(*signer_nonce),
                     maint_coll_ratio: U64F64::from_le_bytes(*maint_coll_ratio),
                     init_coll_ratio: U64F64::from_le_bytes(*init_coll_ratio),
-                    borrow_limits: aligned_borrow_limits
+                    borrow_limits: aligned_borrow_limits,
+                    borrow_fee_params
                 }
             }
             1 => {
@@ -390,16 +712,19 @@ impl MangoInstruction {
                 MangoInstruction::InitMarginAccount
             },
             2 => {
+                if data.len() < 8 { return None; }
                 let quantity = array_ref![data, 0, 8];
                 MangoInstruction::Deposit { quantity: u64::from_le_bytes(*quantity) }
             },
             3 => {
+                if data.len() < 8 { return None; }
                 let data = array_ref![data, 0, 8];
                 MangoInstruction::Withdraw {
                     quantity: u64::from_le_bytes(*data)
                 }
             },
             4 => {
+                if data.len() < 16 { return None; }
                 let data = array_ref![data, 0, 16];
                 let (token_index, quantity) = array_refs![data, 8, 8];
 
@@ -409,6 +734,7 @@ impl MangoInstruction {
                 }
             },
             5 => {
+                if data.len() < 16 { return None; }
                 let data = array_ref![data, 0, 16];
                 let (token_index, quantity) = array_refs![data, 8, 8];
 
@@ -432,18 +758,23 @@ impl MangoInstruction {
                 }
             },
             7 => {
+                if data.len() < 8 { return None; }
                 let quantity = array_ref![data, 0, 8];
                 MangoInstruction::DepositSrm { quantity: u64::from_le_bytes(*quantity) }
             }
             8 => {
+                if data.len() < 8 { return None; }
                 let quantity = array_ref![data, 0, 8];
                 MangoInstruction::WithdrawSrm { quantity: u64::from_le_bytes(*quantity) }
             }
             9 => {
+                if data.len() < 46 + 1 { return None; }
                 let data_arr = array_ref![data, 0, 46];
                 let order = unpack_dex_new_order_v3(data_arr)?;
+                let reduce_only = array_ref![data, 46, 1];
                 MangoInstruction::PlaceOrder {
-                    order
+                    order,
+                    reduce_only: reduce_only[0] != 0
                 }
 
             },
@@ -451,6 +782,7 @@ impl MangoInstruction {
                 MangoInstruction::SettleFunds
             },
             11 => {
+                if data.len() < 20 { return None; }
                 let data_array = array_ref![data, 0, 20];
                 let fields = array_refs![data_array, 4, 16];
                 let side = match u32::from_le_bytes(*fields.0) {
@@ -469,6 +801,7 @@ impl MangoInstruction {
                 }
             },
             12 => {
+                if data.len() < 8 { return None; }
                 let client_id = array_ref![data, 0, 8];
                 MangoInstruction::CancelOrderByClientId {
                     client_id: u64::from_le_bytes(*client_id)
@@ -476,6 +809,7 @@ impl MangoInstruction {
 
             }
             13 => {
+                if data.len() < 16 { return None; }
                 let data = array_ref![data, 0, 16];
                 let (token_index, borrow_limit) = array_refs![data, 8, 8];
                 MangoInstruction::ChangeBorrowLimit {
@@ -484,38 +818,578 @@ impl MangoInstruction {
                 }
             }
             14 => {
+                if data.len() < 46 + 1 { return None; }
                 let data_arr = array_ref![data, 0, 46];
                 let order = unpack_dex_new_order_v3(data_arr)?;
+                let reduce_only = array_ref![data, 46, 1];
                 MangoInstruction::PlaceAndSettle {
-                    order
+                    order,
+                    reduce_only: reduce_only[0] != 0
                 }
             }
             15 => {
+                if data.is_empty() { return None; }
                 let limit = array_ref![data, 0, 1];
                 MangoInstruction::ForceCancelOrders {
                     limit: u8::from_le_bytes(*limit)
                 }
             }
             16 => {
+                if data.len() < 8 { return None; }
                 let max_deposit = array_ref![data, 0, 8];
                 MangoInstruction::PartialLiquidate {
                     max_deposit: u64::from_le_bytes(*max_deposit)
                 }
             }
             17 => {
+                if data.len() < INFO_LEN { return None; }
                 let info = array_ref![data, 0, INFO_LEN];
                 MangoInstruction::AddMarginAccountInfo {
                     info: *info
                 }
             }
+            18 => {
+                MangoInstruction::SettleBorrowAll
+            }
+            19 => {
+                if data.is_empty() { return None; }
+                let limit = array_ref![data, 0, 1];
+                MangoInstruction::CancelAllOrders {
+                    limit: u8::from_le_bytes(*limit)
+                }
+            }
+            20 => {
+                if data.len() < 8 + 46 { return None; }
+                let data = array_ref![data, 0, 8 + 46];
+                let (quantity, order_data) = array_refs![data, 8, 46];
+                let order = unpack_dex_new_order_v3(order_data)?;
+                MangoInstruction::DepositAndPlace {
+                    quantity: u64::from_le_bytes(*quantity),
+                    order
+                }
+            }
+            21 => {
+                if data.len() < 8 { return None; }
+                let quantity = array_ref![data, 0, 8];
+                MangoInstruction::DepositMsrm { quantity: u64::from_le_bytes(*quantity) }
+            }
+            22 => {
+                if data.len() < 8 { return None; }
+                let quantity = array_ref![data, 0, 8];
+                MangoInstruction::WithdrawMsrm { quantity: u64::from_le_bytes(*quantity) }
+            }
+            23 => {
+                if data.len() < 8 + 4 * 16 { return None; }
+                let data = array_ref![data, 0, 8 + 4 * 16];
+                let (token_index, optimal_util, base_rate, rate_slope1, rate_slope2) =
+                    array_refs![data, 8, 16, 16, 16, 16];
+                MangoInstruction::ChangeInterestParams {
+                    token_index: usize::from_le_bytes(*token_index),
+                    interest_rate_params: InterestRateParams {
+                        optimal_util: U64F64::from_le_bytes(*optimal_util),
+                        base_rate: U64F64::from_le_bytes(*base_rate),
+                        rate_slope1: U64F64::from_le_bytes(*rate_slope1),
+                        rate_slope2: U64F64::from_le_bytes(*rate_slope2),
+                    }
+                }
+            }
+            24 => {
+                if data.len() < 1 + 46 * NUM_MARKETS { return None; }
+                let data = array_ref![data, 0, 1 + 46 * NUM_MARKETS];
+                let (&bitmap, orders_data) = array_refs![data, 1, 46 * NUM_MARKETS];
+                let mut orders = [None; NUM_MARKETS];
+                for market_i in 0..NUM_MARKETS {
+                    if bitmap & (1 << market_i) != 0 {
+                        let order_data = array_ref![orders_data, 46 * market_i, 46];
+                        orders[market_i] = Some(unpack_dex_new_order_v3(order_data)?);
+                    }
+                }
+                MangoInstruction::PlaceAndSettleMulti { orders }
+            }
+            25 => {
+                if data.is_empty() { return None; }
+                let limit = array_ref![data, 0, 1];
+                MangoInstruction::ForceCancelAllOrders {
+                    limit: u8::from_le_bytes(*limit)
+                }
+            }
+            26 => {
+                if data.len() < 22 { return None; }
+                let data = array_ref![data, 0, 22];
+                let (dust_threshold, liquidation_fee_bps, max_socialized_loss_bps, close_factor_bps) =
+                    array_refs![data, 16, 2, 2, 2];
+                MangoInstruction::ChangeLiquidationParams {
+                    liquidation_params: LiquidationParams {
+                        dust_threshold: U64F64::from_le_bytes(*dust_threshold),
+                        liquidation_fee_bps: u16::from_le_bytes(*liquidation_fee_bps),
+                        max_socialized_loss_bps: u16::from_le_bytes(*max_socialized_loss_bps),
+                        close_factor_bps: u16::from_le_bytes(*close_factor_bps),
+                    }
+                }
+            }
+            27 => {
+                if data.len() < 2 { return None; }
+                let data = array_ref![data, 0, 2];
+                MangoInstruction::ForceLiquidateOnDex {
+                    limit: u16::from_le_bytes(*data)
+                }
+            }
+            28 => {
+                if data.len() < 8 + 16 + 16 { return None; }
+                let data = array_ref![data, 0, 8 + 16 + 16];
+                let (token_index, asset_weight, liab_weight) = array_refs![data, 8, 16, 16];
+                MangoInstruction::ChangeCollateralWeights {
+                    token_index: usize::from_le_bytes(*token_index),
+                    asset_weight: U64F64::from_le_bytes(*asset_weight),
+                    liab_weight: U64F64::from_le_bytes(*liab_weight),
+                }
+            }
+            29 => MangoInstruction::Migrate,
+            30 => {
+                if data.len() < 8 { return None; }
+                let data = array_ref![data, 0, 8];
+                MangoInstruction::SetOracle2 {
+                    token_index: usize::from_le_bytes(*data)
+                }
+            }
             _ => { return None; }
         })
     }
+    /// Serializes `self` into the exact wire layout that `unpack` expects: a 4-byte LE
+    /// discriminant followed by the fixed-offset field encoding for that variant. This must be
+    /// kept in lockstep with `unpack` -- bincode's default enum/derive encoding does not match.
     pub fn pack(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+        let mut buf = Vec::new();
+        match self {
+            MangoInstruction::InitMangoGroup {
+                signer_nonce, maint_coll_ratio, init_coll_ratio, borrow_limits, borrow_fee_params
+            } => {
+                buf.extend_from_slice(&0u32.to_le_bytes());
+                buf.extend_from_slice(&signer_nonce.to_le_bytes());
+                buf.extend_from_slice(&maint_coll_ratio.to_le_bytes());
+                buf.extend_from_slice(&init_coll_ratio.to_le_bytes());
+                for borrow_limit in borrow_limits.iter() {
+                    buf.extend_from_slice(&borrow_limit.to_le_bytes());
+                }
+                for params in borrow_fee_params.iter() {
+                    buf.extend_from_slice(&params.origination_fee_rate.to_le_bytes());
+                    buf.extend_from_slice(&params.host_fee_bps.to_le_bytes());
+                }
+            }
+            MangoInstruction::InitMarginAccount => {
+                buf.extend_from_slice(&1u32.to_le_bytes());
+            }
+            MangoInstruction::Deposit { quantity } => {
+                buf.extend_from_slice(&2u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::Withdraw { quantity } => {
+                buf.extend_from_slice(&3u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::Borrow { token_index, quantity } => {
+                buf.extend_from_slice(&4u32.to_le_bytes());
+                buf.extend_from_slice(&token_index.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::SettleBorrow { token_index, quantity } => {
+                buf.extend_from_slice(&5u32.to_le_bytes());
+                buf.extend_from_slice(&token_index.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::Liquidate { deposit_quantities } => {
+                buf.extend_from_slice(&6u32.to_le_bytes());
+                for deposit_quantity in deposit_quantities.iter() {
+                    buf.extend_from_slice(&deposit_quantity.to_le_bytes());
+                }
+            }
+            MangoInstruction::DepositSrm { quantity } => {
+                buf.extend_from_slice(&7u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::WithdrawSrm { quantity } => {
+                buf.extend_from_slice(&8u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::PlaceOrder { order, reduce_only } => {
+                buf.extend_from_slice(&9u32.to_le_bytes());
+                buf.extend_from_slice(&pack_dex_new_order_v3(order));
+                buf.extend_from_slice(&(*reduce_only as u8).to_le_bytes());
+            }
+            MangoInstruction::SettleFunds => {
+                buf.extend_from_slice(&10u32.to_le_bytes());
+            }
+            MangoInstruction::CancelOrder { order } => {
+                buf.extend_from_slice(&11u32.to_le_bytes());
+                buf.extend_from_slice(&(order.side as u32).to_le_bytes());
+                buf.extend_from_slice(&order.order_id.to_le_bytes());
+            }
+            MangoInstruction::CancelOrderByClientId { client_id } => {
+                buf.extend_from_slice(&12u32.to_le_bytes());
+                buf.extend_from_slice(&client_id.to_le_bytes());
+            }
+            MangoInstruction::ChangeBorrowLimit { token_index, borrow_limit } => {
+                buf.extend_from_slice(&13u32.to_le_bytes());
+                buf.extend_from_slice(&token_index.to_le_bytes());
+                buf.extend_from_slice(&borrow_limit.to_le_bytes());
+            }
+            MangoInstruction::PlaceAndSettle { order, reduce_only } => {
+                buf.extend_from_slice(&14u32.to_le_bytes());
+                buf.extend_from_slice(&pack_dex_new_order_v3(order));
+                buf.extend_from_slice(&(*reduce_only as u8).to_le_bytes());
+            }
+            MangoInstruction::ForceCancelOrders { limit } => {
+                buf.extend_from_slice(&15u32.to_le_bytes());
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            MangoInstruction::PartialLiquidate { max_deposit } => {
+                buf.extend_from_slice(&16u32.to_le_bytes());
+                buf.extend_from_slice(&max_deposit.to_le_bytes());
+            }
+            MangoInstruction::AddMarginAccountInfo { info } => {
+                buf.extend_from_slice(&17u32.to_le_bytes());
+                buf.extend_from_slice(info);
+            }
+            MangoInstruction::SettleBorrowAll => {
+                buf.extend_from_slice(&18u32.to_le_bytes());
+            }
+            MangoInstruction::CancelAllOrders { limit } => {
+                buf.extend_from_slice(&19u32.to_le_bytes());
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            MangoInstruction::DepositAndPlace { quantity, order } => {
+                buf.extend_from_slice(&20u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+                buf.extend_from_slice(&pack_dex_new_order_v3(order));
+            }
+            MangoInstruction::DepositMsrm { quantity } => {
+                buf.extend_from_slice(&21u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::WithdrawMsrm { quantity } => {
+                buf.extend_from_slice(&22u32.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            MangoInstruction::ChangeInterestParams { token_index, interest_rate_params } => {
+                buf.extend_from_slice(&23u32.to_le_bytes());
+                buf.extend_from_slice(&token_index.to_le_bytes());
+                buf.extend_from_slice(&interest_rate_params.optimal_util.to_le_bytes());
+                buf.extend_from_slice(&interest_rate_params.base_rate.to_le_bytes());
+                buf.extend_from_slice(&interest_rate_params.rate_slope1.to_le_bytes());
+                buf.extend_from_slice(&interest_rate_params.rate_slope2.to_le_bytes());
+            }
+            MangoInstruction::PlaceAndSettleMulti { orders } => {
+                buf.extend_from_slice(&24u32.to_le_bytes());
+                let mut bitmap: u8 = 0;
+                for (market_i, order) in orders.iter().enumerate() {
+                    if order.is_some() {
+                        bitmap |= 1 << market_i;
+                    }
+                }
+                buf.push(bitmap);
+                for order in orders.iter() {
+                    match order {
+                        Some(order) => buf.extend_from_slice(&pack_dex_new_order_v3(order)),
+                        None => buf.extend_from_slice(&[0u8; 46]),
+                    }
+                }
+            }
+            MangoInstruction::ForceCancelAllOrders { limit } => {
+                buf.extend_from_slice(&25u32.to_le_bytes());
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            MangoInstruction::ChangeLiquidationParams { liquidation_params } => {
+                buf.extend_from_slice(&26u32.to_le_bytes());
+                buf.extend_from_slice(&liquidation_params.dust_threshold.to_le_bytes());
+                buf.extend_from_slice(&liquidation_params.liquidation_fee_bps.to_le_bytes());
+                buf.extend_from_slice(&liquidation_params.max_socialized_loss_bps.to_le_bytes());
+                buf.extend_from_slice(&liquidation_params.close_factor_bps.to_le_bytes());
+            }
+            MangoInstruction::ForceLiquidateOnDex { limit } => {
+                buf.extend_from_slice(&27u32.to_le_bytes());
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            MangoInstruction::ChangeCollateralWeights { token_index, asset_weight, liab_weight } => {
+                buf.extend_from_slice(&28u32.to_le_bytes());
+                buf.extend_from_slice(&token_index.to_le_bytes());
+                buf.extend_from_slice(&asset_weight.to_le_bytes());
+                buf.extend_from_slice(&liab_weight.to_le_bytes());
+            }
+            MangoInstruction::Migrate => {
+                buf.extend_from_slice(&29u32.to_le_bytes());
+            }
+            MangoInstruction::SetOracle2 { token_index } => {
+                buf.extend_from_slice(&30u32.to_le_bytes());
+                buf.extend_from_slice(&token_index.to_le_bytes());
+            }
+        }
+        buf
+    }
+
+    /// Returns the account role for every slot in the account list this variant expects, in
+    /// order, mirroring the numbered doc comments above. Lets off-chain clients, explorers, and
+    /// simulation tooling line up an instruction's `accounts` with their meaning without
+    /// duplicating the offsets from those comments by hand.
+    pub fn account_layout(&self) -> Vec<AccountRole> {
+        use AccountRole::*;
+
+        fn open_orders_and_oracle_tail() -> Vec<AccountRole> {
+            let mut tail = vec![OpenOrders; NUM_MARKETS];
+            tail.extend(vec![Oracle; NUM_MARKETS]);
+            tail
+        }
+
+        // Liquidation-sensitive instructions additionally take a secondary oracle per market,
+        // cross-checked by `get_prices` against `mango_group.oracles2`; see `ForceCancelOrders`.
+        fn open_orders_and_two_oracle_tail() -> Vec<AccountRole> {
+            let mut tail = open_orders_and_oracle_tail();
+            tail.extend(vec![Oracle; NUM_MARKETS]);
+            tail
+        }
+
+        match self {
+            MangoInstruction::InitMangoGroup { .. } => {
+                let mut layout = vec![MangoGroup, Rent, Clock, SignerAcc, DexProgram, SrmVault, Admin];
+                layout.extend(vec![Mint; NUM_TOKENS]);
+                layout.extend(vec![Vault; NUM_TOKENS]);
+                layout.extend(vec![SpotMarket; NUM_MARKETS]);
+                layout.extend(vec![Oracle; NUM_MARKETS]);
+                layout
+            }
+            MangoInstruction::InitMarginAccount => {
+                vec![MangoGroup, MarginAccount, Owner, Rent]
+            }
+            MangoInstruction::Deposit { .. } => {
+                vec![MangoGroup, MarginAccount, Owner, TokenAccount, Vault, TokenProgram, Clock]
+            }
+            MangoInstruction::Withdraw { .. } => {
+                let mut layout = vec![
+                    MangoGroup, MarginAccount, Owner, TokenAccount, Vault, SignerAcc, TokenProgram, Clock
+                ];
+                layout.extend(open_orders_and_oracle_tail());
+                layout
+            }
+            MangoInstruction::Borrow { .. } => {
+                let mut layout = vec![
+                    MangoGroup, MarginAccount, Owner, Clock, Vault, SignerAcc, TokenProgram, HostAccount
+                ];
+                layout.extend(open_orders_and_oracle_tail());
+                layout
+            }
+            MangoInstruction::SettleBorrow { .. } => {
+                vec![MangoGroup, MarginAccount, Owner, Clock]
+            }
+            MangoInstruction::SettleBorrowAll => {
+                vec![MangoGroup, MarginAccount, Owner, Clock]
+            }
+            MangoInstruction::Liquidate { .. } => {
+                let mut layout = vec![MangoGroup, Liqor, LiqeeMarginAccount, TokenProgram, Clock];
+                layout.extend(open_orders_and_oracle_tail());
+                layout.extend(vec![Vault; NUM_TOKENS]);
+                layout.extend(vec![LiqorTokenAccount; NUM_TOKENS]);
+                layout
+            }
+            MangoInstruction::DepositSrm { .. } => {
+                vec![MangoGroup, MangoSrmAccount, Owner, SrmAccount, SrmVault, TokenProgram, Clock, Rent]
+            }
+            MangoInstruction::WithdrawSrm { .. } => {
+                vec![MangoGroup, MangoSrmAccount, Owner, SrmAccount, SrmVault, SignerAcc, TokenProgram, Clock]
+            }
+            MangoInstruction::PlaceOrder { .. } => {
+                let mut layout = vec![
+                    MangoGroup, Owner, MarginAccount, Clock, DexProgram, SpotMarket, DexRequestQueue,
+                    DexEventQueue, Bids, Asks, Vault, SignerAcc, DexBase, DexQuote, TokenProgram, Rent,
+                    SrmVault
+                ];
+                layout.extend(open_orders_and_oracle_tail());
+                layout
+            }
+            MangoInstruction::SettleFunds => {
+                vec![
+                    MangoGroup, Owner, MarginAccount, Clock, DexProgram, SpotMarket, OpenOrders,
+                    SignerAcc, DexBase, DexQuote, Vault, Vault, DexSigner, TokenProgram
+                ]
+            }
+            MangoInstruction::CancelOrder { .. } | MangoInstruction::CancelOrderByClientId { .. } => {
+                vec![
+                    MangoGroup, Owner, MarginAccount, Clock, DexProgram, SpotMarket, Bids, Asks,
+                    OpenOrders, SignerAcc, DexEventQueue
+                ]
+            }
+            MangoInstruction::CancelAllOrders { .. } => {
+                let mut layout = vec![MangoGroup, Owner, MarginAccount, Clock, DexProgram, SignerAcc];
+                layout.extend(vec![SpotMarket; NUM_MARKETS]);
+                layout.extend(vec![Bids; NUM_MARKETS]);
+                layout.extend(vec![Asks; NUM_MARKETS]);
+                layout.extend(vec![OpenOrders; NUM_MARKETS]);
+                layout.extend(vec![DexEventQueue; NUM_MARKETS]);
+                layout
+            }
+            MangoInstruction::ChangeBorrowLimit { .. } => {
+                vec![MangoGroup, Admin]
+            }
+            MangoInstruction::PlaceAndSettle { .. } | MangoInstruction::DepositAndPlace { .. } => {
+                let mut layout = vec![MangoGroup, Owner, MarginAccount, Clock];
+                if matches!(self, MangoInstruction::DepositAndPlace { .. }) {
+                    layout.push(TokenAccount);
+                }
+                layout.extend(vec![
+                    DexProgram, SpotMarket, DexRequestQueue, DexEventQueue, Bids, Asks, Vault, Vault,
+                    SignerAcc, DexBase, DexQuote, TokenProgram, Rent, SrmVault, DexSigner
+                ]);
+                layout.extend(open_orders_and_oracle_tail());
+                layout
+            }
+            MangoInstruction::ForceCancelOrders { .. } => {
+                let mut layout = vec![
+                    MangoGroup, Liqor, LiqeeMarginAccount, Vault, Vault, SpotMarket, Bids, Asks,
+                    SignerAcc, DexEventQueue, DexBase, DexQuote, DexSigner, TokenProgram, DexProgram, Clock
+                ];
+                layout.extend(open_orders_and_two_oracle_tail());
+                layout
+            }
+            MangoInstruction::PartialLiquidate { .. } => {
+                let mut layout = vec![
+                    MangoGroup, Liqor, LiqorTokenAccount, LiqorTokenAccount, LiqeeMarginAccount,
+                    Vault, Vault, SignerAcc, TokenProgram, Clock
+                ];
+                layout.extend(open_orders_and_two_oracle_tail());
+                layout
+            }
+            MangoInstruction::AddMarginAccountInfo { .. } => {
+                vec![MangoGroup, MarginAccount, Owner]
+            }
+            MangoInstruction::DepositMsrm { .. } => {
+                vec![MangoGroup, MangoSrmAccount, Owner, MsrmAccount, MsrmVault, TokenProgram, Clock, Rent]
+            }
+            MangoInstruction::WithdrawMsrm { .. } => {
+                vec![MangoGroup, MangoSrmAccount, Owner, MsrmAccount, MsrmVault, SignerAcc, TokenProgram, Clock]
+            }
+            MangoInstruction::ChangeInterestParams { .. } => {
+                vec![MangoGroup, Admin]
+            }
+            MangoInstruction::ChangeLiquidationParams { .. } => {
+                vec![MangoGroup, Admin]
+            }
+            MangoInstruction::PlaceAndSettleMulti { .. } => {
+                let mut layout = vec![
+                    MangoGroup, Owner, MarginAccount, Clock, DexProgram, Vault, SignerAcc,
+                    TokenProgram, Rent, SrmVault
+                ];
+                for _ in 0..NUM_MARKETS {
+                    layout.extend(vec![
+                        SpotMarket, DexRequestQueue, DexEventQueue, Bids, Asks, Vault, DexBase,
+                        DexQuote, DexSigner
+                    ]);
+                }
+                layout.extend(open_orders_and_oracle_tail());
+                layout
+            }
+            MangoInstruction::ForceCancelAllOrders { .. } => {
+                let mut layout = vec![
+                    MangoGroup, Liqor, LiqeeMarginAccount, Vault, SignerAcc, TokenProgram,
+                    DexProgram, Clock
+                ];
+                for _ in 0..NUM_MARKETS {
+                    layout.extend(vec![
+                        Vault, SpotMarket, Bids, Asks, DexEventQueue, DexBase, DexQuote, DexSigner
+                    ]);
+                }
+                layout.extend(open_orders_and_two_oracle_tail());
+                layout
+            }
+            MangoInstruction::ForceLiquidateOnDex { .. } => {
+                let mut layout = vec![
+                    MangoGroup, Liqor, LiqeeMarginAccount, Clock, DexProgram, Vault, SignerAcc,
+                    TokenProgram, Rent, SrmVault
+                ];
+                for _ in 0..NUM_MARKETS {
+                    layout.extend(vec![
+                        SpotMarket, DexRequestQueue, DexEventQueue, Bids, Asks, Vault, DexBase,
+                        DexQuote, DexSigner
+                    ]);
+                }
+                layout.extend(open_orders_and_two_oracle_tail());
+                layout
+            }
+            MangoInstruction::ChangeCollateralWeights { .. } => {
+                vec![MangoGroup, Admin]
+            }
+            MangoInstruction::Migrate => {
+                // `MangoGroup` here stands in for "whichever of the three account kinds
+                // `target_acc` turns out to be" -- `AccountRole` has no generic slot for that.
+                vec![MangoGroup, Rent]
+            }
+            MangoInstruction::SetOracle2 { .. } => {
+                vec![MangoGroup, Admin, Oracle]
+            }
+        }
     }
 }
 
+/// The role a single account slot plays within an instruction's account list, as returned by
+/// `MangoInstruction::account_layout`. Repeated roles (e.g. `OpenOrders`, `Oracle`) stand for the
+/// `NUM_MARKETS`- or `NUM_TOKENS`-sized tails documented on each variant; their position within
+/// the tail (not the variant name) tells you which market or token they belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountRole {
+    MangoGroup,
+    Admin,
+    Owner,
+    Liqor,
+    MarginAccount,
+    LiqeeMarginAccount,
+    MangoSrmAccount,
+    SrmAccount,
+    MsrmAccount,
+    MsrmVault,
+    TokenAccount,
+    LiqorTokenAccount,
+    Vault,
+    SignerAcc,
+    DexProgram,
+    SpotMarket,
+    DexRequestQueue,
+    DexEventQueue,
+    Bids,
+    Asks,
+    DexBase,
+    DexQuote,
+    DexSigner,
+    TokenProgram,
+    SrmVault,
+    Clock,
+    Rent,
+    Mint,
+    OpenOrders,
+    Oracle,
+    HostAccount,
+}
+
+fn pack_dex_new_order_v3(order: &serum_dex::instruction::NewOrderInstructionV3) -> [u8; 46] {
+    let mut buf = [0u8; 46];
+    let (
+        side_arr,
+        price_arr,
+        max_coin_qty_arr,
+        max_native_pc_qty_arr,
+        self_trade_behavior_arr,
+        otype_arr,
+        client_order_id_arr,
+        limit_arr,
+    ) = mut_array_refs![&mut buf, 4, 8, 8, 8, 4, 4, 8, 2];
+
+    *side_arr = (order.side as u32).to_le_bytes();
+    *price_arr = order.limit_price.get().to_le_bytes();
+    *max_coin_qty_arr = order.max_coin_qty.get().to_le_bytes();
+    *max_native_pc_qty_arr = order.max_native_pc_qty_including_fees.get().to_le_bytes();
+    *self_trade_behavior_arr = (order.self_trade_behavior as u32).to_le_bytes();
+    *otype_arr = (order.order_type as u32).to_le_bytes();
+    *client_order_id_arr = order.client_order_id.to_le_bytes();
+    *limit_arr = order.limit.to_le_bytes();
+
+    buf
+}
 
 fn unpack_dex_new_order_v3(data: &[u8; 46]) -> Option<serum_dex::instruction::NewOrderInstructionV3> {
     let (
@@ -557,6 +1431,49 @@ fn unpack_dex_new_order_v3(data: &[u8; 46]) -> Option<serum_dex::instruction::Ne
 }
 
 
+/// Appends the canonical `open_orders_pks` then `oracle_pks` tail shared by every builder that
+/// crosses all spot markets in a margin account, after checking both slices are exactly
+/// `NUM_MARKETS` long. Centralizing the check (and the ordering) means the many near-identical
+/// `accounts.extend(...)` pairs can't drift apart, and a caller that passes the wrong number of
+/// keys gets a `ProgramError` here instead of an opaque failure deep in the on-chain processor.
+fn extend_with_open_orders_and_oracles(
+    accounts: &mut Vec<AccountMeta>,
+    open_orders_pks: &[Pubkey],
+    oracle_pks: &[Pubkey],
+    open_orders_writable: bool,
+) -> Result<(), ProgramError> {
+    if open_orders_pks.len() != NUM_MARKETS || oracle_pks.len() != NUM_MARKETS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    accounts.extend(open_orders_pks.iter().map(|pk| {
+        if open_orders_writable {
+            AccountMeta::new(*pk, false)
+        } else {
+            AccountMeta::new_readonly(*pk, false)
+        }
+    }));
+    accounts.extend(oracle_pks.iter().map(|pk| AccountMeta::new_readonly(*pk, false)));
+    Ok(())
+}
+
+/// Appends the secondary oracle tail taken by liquidation-sensitive instructions (`ForceCancelOrders`,
+/// `PartialLiquidate`, `ForceCancelAllOrders`, `ForceLiquidateOnDex`), cross-checked by `get_prices`
+/// against `mango_group.oracles2`. Pass `Pubkey::default()` for a market with no secondary oracle
+/// configured -- `get_prices` skips the cross-check for that market but the account slot is still
+/// required so market index and account position line up.
+fn extend_with_secondary_oracles(
+    accounts: &mut Vec<AccountMeta>,
+    oracle2_pks: &[Pubkey],
+) -> Result<(), ProgramError> {
+    if oracle2_pks.len() != NUM_MARKETS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    accounts.extend(oracle2_pks.iter().map(|pk| AccountMeta::new_readonly(*pk, false)));
+    Ok(())
+}
+
 pub fn init_mango_group(
     program_id: &Pubkey,
     mango_group_pk: &Pubkey,
@@ -571,7 +1488,8 @@ pub fn init_mango_group(
     signer_nonce: u64,
     maint_coll_ratio: U64F64,
     init_coll_ratio: U64F64,
-    borrow_limits: [u64; NUM_TOKENS]
+    borrow_limits: [u64; NUM_TOKENS],
+    borrow_fee_params: [BorrowFeeParams; NUM_TOKENS]
 ) -> Result<Instruction, ProgramError> {
     let mut accounts = vec![
         AccountMeta::new(*mango_group_pk, false),
@@ -599,7 +1517,8 @@ pub fn init_mango_group(
         signer_nonce,
         maint_coll_ratio,
         init_coll_ratio,
-        borrow_limits
+        borrow_limits,
+        borrow_fee_params
     };
 
     let data = instr.pack();
@@ -683,12 +1602,7 @@ pub fn withdraw(
         AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, false)?;
 
     let instr = MangoInstruction::Withdraw { quantity };
     let data = instr.pack();
@@ -704,24 +1618,29 @@ pub fn borrow(
     mango_group_pk: &Pubkey,
     margin_account_pk: &Pubkey,
     owner_pk: &Pubkey,
+    vault_pk: &Pubkey,
+    signer_pk: &Pubkey,
+    host_pk: Option<&Pubkey>,
     open_orders_pks: &[Pubkey],
     oracle_pks: &[Pubkey],
     token_index: usize,
     quantity: u64
 ) -> Result<Instruction, ProgramError> {
+    // No host account means the origination fee's host split, if any, just flows back into the
+    // vault instead of out to a referrer; see `Processor::borrow`.
+    let host_pk = host_pk.unwrap_or(vault_pk);
     let mut accounts = vec![
         AccountMeta::new(*mango_group_pk, false),
         AccountMeta::new(*margin_account_pk, false),
         AccountMeta::new_readonly(*owner_pk, true),
         AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new(*vault_pk, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new(*host_pk, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, false)?;
 
     let instr = MangoInstruction::Borrow { token_index, quantity };
     let data = instr.pack();
@@ -756,6 +1675,28 @@ pub fn settle_borrow(
     })
 }
 
+pub fn settle_borrow_all(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    margin_account_pk: &Pubkey,
+    owner_pk: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new(*margin_account_pk, false),
+        AccountMeta::new_readonly(*owner_pk, true),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+    ];
+
+    let instr = MangoInstruction::SettleBorrowAll;
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
 pub fn liquidate(
     program_id: &Pubkey,
     mango_group_pk: &Pubkey,
@@ -775,12 +1716,10 @@ pub fn liquidate(
         AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, false)?;
+    if vault_pks.len() != NUM_TOKENS || liqor_token_account_pks.len() != NUM_TOKENS {
+        return Err(ProgramError::InvalidArgument);
+    }
     accounts.extend(vault_pks.iter().map(
         |pk| AccountMeta::new(*pk, false))
     );
@@ -856,6 +1795,65 @@ pub fn withdraw_srm(
     })
 }
 
+pub fn deposit_msrm(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    mango_srm_account_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    msrm_account_pk: &Pubkey,
+    msrm_vault_pk: &Pubkey,
+    quantity: u64
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new(*mango_srm_account_pk, false),
+        AccountMeta::new_readonly(*owner_pk, true),
+        AccountMeta::new(*msrm_account_pk, false),
+        AccountMeta::new(*msrm_vault_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new_readonly(solana_program::sysvar::rent::ID, false),
+    ];
+
+    let instr = MangoInstruction::DepositMsrm { quantity };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+pub fn withdraw_msrm(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    mango_srm_account_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    msrm_account_pk: &Pubkey,
+    msrm_vault_pk: &Pubkey,
+    signer_pk: &Pubkey,
+    quantity: u64
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new(*mango_srm_account_pk, false),
+        AccountMeta::new_readonly(*owner_pk, true),
+        AccountMeta::new(*msrm_account_pk, false),
+        AccountMeta::new(*msrm_vault_pk, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+    ];
+
+    let instr = MangoInstruction::WithdrawMsrm { quantity };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
 pub fn place_order(
     program_id: &Pubkey,
     mango_group_pk: &Pubkey,
@@ -874,7 +1872,8 @@ pub fn place_order(
     srm_vault_pk: &Pubkey,
     open_orders_pks: &[Pubkey],
     oracle_pks: &[Pubkey],
-    order: serum_dex::instruction::NewOrderInstructionV3
+    order: serum_dex::instruction::NewOrderInstructionV3,
+    reduce_only: bool
 ) -> Result<Instruction, ProgramError> {
 
     let mut accounts = vec![
@@ -897,14 +1896,9 @@ pub fn place_order(
         AccountMeta::new(*srm_vault_pk, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
 
-    let instr = MangoInstruction::PlaceOrder { order };
+    let instr = MangoInstruction::PlaceOrder { order, reduce_only };
     let data = instr.pack();
     Ok(Instruction {
         program_id: *program_id,
@@ -933,6 +1927,65 @@ pub fn place_and_settle(
     dex_signer_pk: &Pubkey,
     open_orders_pks: &[Pubkey],
     oracle_pks: &[Pubkey],
+    order: serum_dex::instruction::NewOrderInstructionV3,
+    reduce_only: bool
+) -> Result<Instruction, ProgramError> {
+
+    let mut accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*owner_pk, true),
+        AccountMeta::new(*margin_account_pk, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new_readonly(*dex_prog_id, false),
+        AccountMeta::new(*spot_market_pk, false),
+        AccountMeta::new(*dex_request_queue_pk, false),
+        AccountMeta::new(*dex_event_queue_pk, false),
+        AccountMeta::new(*bids_pk, false),
+        AccountMeta::new(*asks_pk, false),
+        AccountMeta::new(*base_vault_pk, false),
+        AccountMeta::new(*quote_vault_pk, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+        AccountMeta::new(*dex_base_pk, false),
+        AccountMeta::new(*dex_quote_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(solana_program::sysvar::rent::ID, false),
+        AccountMeta::new(*srm_vault_pk, false),
+        AccountMeta::new_readonly(*dex_signer_pk, false),
+    ];
+
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
+
+    let instr = MangoInstruction::PlaceAndSettle { order, reduce_only };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+pub fn deposit_and_place(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    margin_account_pk: &Pubkey,
+    token_account_pk: &Pubkey,
+    dex_prog_id: &Pubkey,
+    spot_market_pk: &Pubkey,
+    dex_request_queue_pk: &Pubkey,
+    dex_event_queue_pk: &Pubkey,
+    bids_pk: &Pubkey,
+    asks_pk: &Pubkey,
+    base_vault_pk: &Pubkey,
+    quote_vault_pk: &Pubkey,
+    signer_pk: &Pubkey,
+    dex_base_pk: &Pubkey,
+    dex_quote_pk: &Pubkey,
+    srm_vault_pk: &Pubkey,
+    dex_signer_pk: &Pubkey,
+    open_orders_pks: &[Pubkey],
+    oracle_pks: &[Pubkey],
+    quantity: u64,
     order: serum_dex::instruction::NewOrderInstructionV3
 ) -> Result<Instruction, ProgramError> {
 
@@ -941,6 +1994,7 @@ pub fn place_and_settle(
         AccountMeta::new_readonly(*owner_pk, true),
         AccountMeta::new(*margin_account_pk, false),
         AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new(*token_account_pk, false),
         AccountMeta::new_readonly(*dex_prog_id, false),
         AccountMeta::new(*spot_market_pk, false),
         AccountMeta::new(*dex_request_queue_pk, false),
@@ -958,14 +2012,83 @@ pub fn place_and_settle(
         AccountMeta::new_readonly(*dex_signer_pk, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
 
-    let instr = MangoInstruction::PlaceAndSettle { order };
+    let instr = MangoInstruction::DepositAndPlace { quantity, order };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+/// The per-market account bundle and optional order for one spot market slot of
+/// `place_and_settle_multi`. Pass one of these for every market index, leaving `order` as `None`
+/// for markets the caller isn't trading this call -- the accounts are still required so the
+/// bundle's position in the list lines up with its market index.
+pub struct PlaceAndSettleMultiMarket {
+    pub spot_market_pk: Pubkey,
+    pub dex_request_queue_pk: Pubkey,
+    pub dex_event_queue_pk: Pubkey,
+    pub bids_pk: Pubkey,
+    pub asks_pk: Pubkey,
+    pub base_vault_pk: Pubkey,
+    pub dex_base_pk: Pubkey,
+    pub dex_quote_pk: Pubkey,
+    pub dex_signer_pk: Pubkey,
+    pub order: Option<serum_dex::instruction::NewOrderInstructionV3>,
+}
+
+pub fn place_and_settle_multi(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    margin_account_pk: &Pubkey,
+    dex_prog_id: &Pubkey,
+    quote_vault_pk: &Pubkey,
+    signer_pk: &Pubkey,
+    srm_vault_pk: &Pubkey,
+    markets: &[PlaceAndSettleMultiMarket],
+    open_orders_pks: &[Pubkey],
+    oracle_pks: &[Pubkey],
+) -> Result<Instruction, ProgramError> {
+    if markets.len() != NUM_MARKETS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*owner_pk, true),
+        AccountMeta::new(*margin_account_pk, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new_readonly(*dex_prog_id, false),
+        AccountMeta::new(*quote_vault_pk, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(solana_program::sysvar::rent::ID, false),
+        AccountMeta::new(*srm_vault_pk, false),
+    ];
+
+    let mut orders = [None; NUM_MARKETS];
+    for (market_i, market) in markets.iter().enumerate() {
+        accounts.extend(vec![
+            AccountMeta::new(market.spot_market_pk, false),
+            AccountMeta::new(market.dex_request_queue_pk, false),
+            AccountMeta::new(market.dex_event_queue_pk, false),
+            AccountMeta::new(market.bids_pk, false),
+            AccountMeta::new(market.asks_pk, false),
+            AccountMeta::new(market.base_vault_pk, false),
+            AccountMeta::new(market.dex_base_pk, false),
+            AccountMeta::new(market.dex_quote_pk, false),
+            AccountMeta::new_readonly(market.dex_signer_pk, false),
+        ]);
+        orders[market_i] = market.order;
+    }
+
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
+
+    let instr = MangoInstruction::PlaceAndSettleMulti { orders };
     let data = instr.pack();
     Ok(Instruction {
         program_id: *program_id,
@@ -1090,6 +2213,47 @@ pub fn cancel_order_by_client_id(
     })
 }
 
+/// Cancels up to `limit` resting orders per market across every spot market the owner trades
+/// on, in one instruction. `spot_market_pks`, `bids_pks`, `asks_pks`, `open_orders_pks`, and
+/// `dex_event_queue_pks` must each have exactly `NUM_MARKETS` entries, in the same market order
+/// as `MangoGroup::spot_markets`.
+pub fn cancel_all_orders(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    margin_account_pk: &Pubkey,
+    dex_prog_id: &Pubkey,
+    signer_pk: &Pubkey,
+    spot_market_pks: &[Pubkey],
+    bids_pks: &[Pubkey],
+    asks_pks: &[Pubkey],
+    open_orders_pks: &[Pubkey],
+    dex_event_queue_pks: &[Pubkey],
+    limit: u8
+) -> Result<Instruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*owner_pk, true),
+        AccountMeta::new_readonly(*margin_account_pk, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new_readonly(*dex_prog_id, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+    ];
+    accounts.extend(spot_market_pks.iter().map(|pk| AccountMeta::new(*pk, false)));
+    accounts.extend(bids_pks.iter().map(|pk| AccountMeta::new(*pk, false)));
+    accounts.extend(asks_pks.iter().map(|pk| AccountMeta::new(*pk, false)));
+    accounts.extend(open_orders_pks.iter().map(|pk| AccountMeta::new(*pk, false)));
+    accounts.extend(dex_event_queue_pks.iter().map(|pk| AccountMeta::new(*pk, false)));
+
+    let instr = MangoInstruction::CancelAllOrders { limit };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
 
 pub fn change_borrow_limit(
     program_id: &Pubkey,
@@ -1112,6 +2276,182 @@ pub fn change_borrow_limit(
     })
 }
 
+pub fn change_interest_params(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    admin_pk: &Pubkey,
+    token_index: usize,
+    interest_rate_params: InterestRateParams
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*admin_pk, true),
+    ];
+
+    let instr = MangoInstruction::ChangeInterestParams { token_index, interest_rate_params };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+pub fn change_liquidation_params(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    admin_pk: &Pubkey,
+    liquidation_params: LiquidationParams
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*admin_pk, true),
+    ];
+
+    let instr = MangoInstruction::ChangeLiquidationParams { liquidation_params };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+pub fn change_collateral_weights(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    admin_pk: &Pubkey,
+    token_index: usize,
+    asset_weight: U64F64,
+    liab_weight: U64F64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*admin_pk, true),
+    ];
+
+    let instr = MangoInstruction::ChangeCollateralWeights { token_index, asset_weight, liab_weight };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+pub fn migrate(
+    program_id: &Pubkey,
+    target_pk: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*target_pk, false),
+        AccountMeta::new_readonly(solana_program::sysvar::rent::ID, false),
+    ];
+
+    let instr = MangoInstruction::Migrate;
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+pub fn set_oracle2(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    admin_pk: &Pubkey,
+    oracle2_pk: &Pubkey,
+    token_index: usize,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*admin_pk, true),
+        AccountMeta::new_readonly(*oracle2_pk, false),
+    ];
+
+    let instr = MangoInstruction::SetOracle2 { token_index };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
+/// The per-market account bundle for one spot market slot of `force_liquidate_on_dex`. Pass one
+/// of these for every market index, even for markets the liqee never traded -- the accounts are
+/// still required so the bundle's position in the list lines up with its market index.
+pub struct ForceLiquidateOnDexMarket {
+    pub spot_market_pk: Pubkey,
+    pub dex_request_queue_pk: Pubkey,
+    pub dex_event_queue_pk: Pubkey,
+    pub bids_pk: Pubkey,
+    pub asks_pk: Pubkey,
+    pub base_vault_pk: Pubkey,
+    pub dex_base_pk: Pubkey,
+    pub dex_quote_pk: Pubkey,
+    pub dex_signer_pk: Pubkey,
+}
+
+pub fn force_liquidate_on_dex(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    liqor_pk: &Pubkey,
+    liqee_margin_account_acc: &Pubkey,
+    dex_prog_id: &Pubkey,
+    quote_vault_pk: &Pubkey,
+    signer_pk: &Pubkey,
+    srm_vault_pk: &Pubkey,
+    markets: &[ForceLiquidateOnDexMarket],
+    open_orders_pks: &[Pubkey],
+    oracle_pks: &[Pubkey],
+    oracle2_pks: &[Pubkey],
+    limit: u16
+) -> Result<Instruction, ProgramError> {
+    if markets.len() != NUM_MARKETS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*liqor_pk, true),
+        AccountMeta::new(*liqee_margin_account_acc, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+        AccountMeta::new_readonly(*dex_prog_id, false),
+        AccountMeta::new(*quote_vault_pk, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(solana_program::sysvar::rent::ID, false),
+        AccountMeta::new(*srm_vault_pk, false),
+    ];
+
+    for market in markets.iter() {
+        accounts.extend(vec![
+            AccountMeta::new(market.spot_market_pk, false),
+            AccountMeta::new(market.dex_request_queue_pk, false),
+            AccountMeta::new(market.dex_event_queue_pk, false),
+            AccountMeta::new(market.bids_pk, false),
+            AccountMeta::new(market.asks_pk, false),
+            AccountMeta::new(market.base_vault_pk, false),
+            AccountMeta::new(market.dex_base_pk, false),
+            AccountMeta::new(market.dex_quote_pk, false),
+            AccountMeta::new_readonly(market.dex_signer_pk, false),
+        ]);
+    }
+
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
+    extend_with_secondary_oracles(&mut accounts, oracle2_pks)?;
+
+    let instr = MangoInstruction::ForceLiquidateOnDex { limit };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
 pub fn force_cancel_orders(
     program_id: &Pubkey,
     mango_group_pk: &Pubkey,
@@ -1130,6 +2470,7 @@ pub fn force_cancel_orders(
     dex_prog_id: &Pubkey,
     open_orders_pks: &[Pubkey],
     oracle_pks: &[Pubkey],
+    oracle2_pks: &[Pubkey],
     limit: u8
 ) -> Result<Instruction, ProgramError> {
 
@@ -1152,12 +2493,8 @@ pub fn force_cancel_orders(
         AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
+    extend_with_secondary_oracles(&mut accounts, oracle2_pks)?;
 
     let instr = MangoInstruction::ForceCancelOrders { limit };
     let data = instr.pack();
@@ -1168,6 +2505,74 @@ pub fn force_cancel_orders(
     })
 }
 
+/// The per-market account bundle for one spot market slot of `force_cancel_all_orders`. Pass one
+/// of these for every market index, even for markets the liqee never traded -- the accounts are
+/// still required so the bundle's position in the list lines up with its market index.
+pub struct ForceCancelAllOrdersMarket {
+    pub base_vault_pk: Pubkey,
+    pub spot_market_pk: Pubkey,
+    pub bids_pk: Pubkey,
+    pub asks_pk: Pubkey,
+    pub dex_event_queue_pk: Pubkey,
+    pub dex_base_pk: Pubkey,
+    pub dex_quote_pk: Pubkey,
+    pub dex_signer_pk: Pubkey,
+}
+
+pub fn force_cancel_all_orders(
+    program_id: &Pubkey,
+    mango_group_pk: &Pubkey,
+    liqor_pk: &Pubkey,
+    liqee_margin_account_acc: &Pubkey,
+    quote_vault_pk: &Pubkey,
+    signer_pk: &Pubkey,
+    dex_prog_id: &Pubkey,
+    markets: &[ForceCancelAllOrdersMarket],
+    open_orders_pks: &[Pubkey],
+    oracle_pks: &[Pubkey],
+    oracle2_pks: &[Pubkey],
+    limit: u8
+) -> Result<Instruction, ProgramError> {
+    if markets.len() != NUM_MARKETS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut accounts = vec![
+        AccountMeta::new(*mango_group_pk, false),
+        AccountMeta::new_readonly(*liqor_pk, true),
+        AccountMeta::new(*liqee_margin_account_acc, false),
+        AccountMeta::new(*quote_vault_pk, false),
+        AccountMeta::new_readonly(*signer_pk, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(*dex_prog_id, false),
+        AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
+    ];
+
+    for market in markets.iter() {
+        accounts.extend(vec![
+            AccountMeta::new(market.base_vault_pk, false),
+            AccountMeta::new(market.spot_market_pk, false),
+            AccountMeta::new(market.bids_pk, false),
+            AccountMeta::new(market.asks_pk, false),
+            AccountMeta::new(market.dex_event_queue_pk, false),
+            AccountMeta::new(market.dex_base_pk, false),
+            AccountMeta::new(market.dex_quote_pk, false),
+            AccountMeta::new_readonly(market.dex_signer_pk, false),
+        ]);
+    }
+
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, true)?;
+    extend_with_secondary_oracles(&mut accounts, oracle2_pks)?;
+
+    let instr = MangoInstruction::ForceCancelAllOrders { limit };
+    let data = instr.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data
+    })
+}
+
 
 pub fn partial_liquidate(
     program_id: &Pubkey,
@@ -1181,6 +2586,7 @@ pub fn partial_liquidate(
     signer_pk: &Pubkey,
     open_orders_pks: &[Pubkey],
     oracle_pks: &[Pubkey],
+    oracle2_pks: &[Pubkey],
     max_deposit: u64
 ) -> Result<Instruction, ProgramError> {
 
@@ -1197,12 +2603,8 @@ pub fn partial_liquidate(
         AccountMeta::new_readonly(solana_program::sysvar::clock::ID, false),
     ];
 
-    accounts.extend(open_orders_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
-    accounts.extend(oracle_pks.iter().map(
-        |pk| AccountMeta::new_readonly(*pk, false))
-    );
+    extend_with_open_orders_and_oracles(&mut accounts, open_orders_pks, oracle_pks, false)?;
+    extend_with_secondary_oracles(&mut accounts, oracle2_pks)?;
 
     let instr = MangoInstruction::PartialLiquidate { max_deposit };
     let data = instr.pack();
@@ -1233,4 +2635,287 @@ pub fn add_margin_account_info(
         accounts,
         data
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DEFAULT_BORROW_FEE_PARAMS;
+
+    fn sample_order() -> serum_dex::instruction::NewOrderInstructionV3 {
+        serum_dex::instruction::NewOrderInstructionV3 {
+            side: serum_dex::matching::Side::Bid,
+            limit_price: NonZeroU64::new(500).unwrap(),
+            max_coin_qty: NonZeroU64::new(10).unwrap(),
+            max_native_pc_qty_including_fees: NonZeroU64::new(5_000).unwrap(),
+            self_trade_behavior: serum_dex::instruction::SelfTradeBehavior::DecrementTake,
+            order_type: serum_dex::matching::OrderType::Limit,
+            client_order_id: 42,
+            limit: 65535,
+        }
+    }
+
+    fn assert_round_trips(instr: MangoInstruction) {
+        let packed = instr.pack();
+        let unpacked = MangoInstruction::unpack(&packed).unwrap();
+        assert_eq!(instr, unpacked);
+    }
+
+    #[test]
+    fn pack_unpack_round_trip() {
+        assert_round_trips(MangoInstruction::InitMangoGroup {
+            signer_nonce: 7,
+            maint_coll_ratio: U64F64::from_num(1.1),
+            init_coll_ratio: U64F64::from_num(1.2),
+            borrow_limits: [1, 2, 3, 4, 5],
+            borrow_fee_params: [
+                BorrowFeeParams { origination_fee_rate: U64F64::from_num(0.001), host_fee_bps: 2_000 },
+                BorrowFeeParams { origination_fee_rate: U64F64::from_num(0), host_fee_bps: 0 },
+                BorrowFeeParams { origination_fee_rate: U64F64::from_num(0.0005), host_fee_bps: 5_000 },
+                BorrowFeeParams { origination_fee_rate: U64F64::from_num(0), host_fee_bps: 0 },
+                BorrowFeeParams { origination_fee_rate: U64F64::from_num(0), host_fee_bps: 0 },
+            ],
+        });
+        assert_round_trips(MangoInstruction::InitMarginAccount);
+        assert_round_trips(MangoInstruction::Deposit { quantity: 123 });
+        assert_round_trips(MangoInstruction::Withdraw { quantity: 456 });
+        assert_round_trips(MangoInstruction::Borrow { token_index: 2, quantity: 789 });
+        assert_round_trips(MangoInstruction::SettleBorrow { token_index: 3, quantity: 10 });
+        assert_round_trips(MangoInstruction::SettleBorrowAll);
+        assert_round_trips(MangoInstruction::CancelAllOrders { limit: 5 });
+        assert_round_trips(MangoInstruction::DepositAndPlace { quantity: 999, order: sample_order() });
+        assert_round_trips(MangoInstruction::Liquidate { deposit_quantities: [1, 2, 3, 4, 5] });
+        assert_round_trips(MangoInstruction::DepositSrm { quantity: 11 });
+        assert_round_trips(MangoInstruction::WithdrawSrm { quantity: 22 });
+        assert_round_trips(MangoInstruction::PlaceOrder { order: sample_order(), reduce_only: false });
+        assert_round_trips(MangoInstruction::PlaceOrder { order: sample_order(), reduce_only: true });
+        assert_round_trips(MangoInstruction::SettleFunds);
+        assert_round_trips(MangoInstruction::CancelOrder {
+            order: serum_dex::instruction::CancelOrderInstructionV2 {
+                side: serum_dex::matching::Side::Ask,
+                order_id: 99999,
+            },
+        });
+        assert_round_trips(MangoInstruction::CancelOrderByClientId { client_id: 55 });
+        assert_round_trips(MangoInstruction::ChangeBorrowLimit { token_index: 1, borrow_limit: 66 });
+        assert_round_trips(MangoInstruction::PlaceAndSettle { order: sample_order(), reduce_only: false });
+        assert_round_trips(MangoInstruction::PlaceAndSettle { order: sample_order(), reduce_only: true });
+        assert_round_trips(MangoInstruction::ForceCancelOrders { limit: 5 });
+        assert_round_trips(MangoInstruction::PartialLiquidate { max_deposit: 77 });
+        assert_round_trips(MangoInstruction::AddMarginAccountInfo { info: [9u8; INFO_LEN] });
+        assert_round_trips(MangoInstruction::DepositMsrm { quantity: 88 });
+        assert_round_trips(MangoInstruction::WithdrawMsrm { quantity: 99 });
+        assert_round_trips(MangoInstruction::ChangeInterestParams {
+            token_index: 2,
+            interest_rate_params: InterestRateParams {
+                optimal_util: U64F64::from_num(0.8),
+                base_rate: U64F64::from_num(0.01),
+                rate_slope1: U64F64::from_num(0.05),
+                rate_slope2: U64F64::from_num(0.5),
+            },
+        });
+        assert_round_trips(MangoInstruction::SetOracle2 { token_index: 4 });
+
+        let mut orders = [None; NUM_MARKETS];
+        orders[0] = Some(sample_order());
+        orders[NUM_MARKETS - 1] = Some(sample_order());
+        assert_round_trips(MangoInstruction::PlaceAndSettleMulti { orders });
+        assert_round_trips(MangoInstruction::PlaceAndSettleMulti { orders: [None; NUM_MARKETS] });
+        assert_round_trips(MangoInstruction::ForceCancelAllOrders { limit: 5 });
+        assert_round_trips(MangoInstruction::ChangeLiquidationParams {
+            liquidation_params: LiquidationParams {
+                dust_threshold: U64F64::from_num(1),
+                liquidation_fee_bps: 500,
+                max_socialized_loss_bps: 2_500,
+                close_factor_bps: 5_000,
+            }
+        });
+        assert_round_trips(MangoInstruction::ForceLiquidateOnDex { limit: 5 });
+        assert_round_trips(MangoInstruction::ChangeCollateralWeights {
+            token_index: 1,
+            asset_weight: U64F64::from_num(0.9),
+            liab_weight: U64F64::from_num(1.1),
+        });
+    }
+
+    #[test]
+    fn unpack_empty_and_truncated_buffers_return_none() {
+        assert!(MangoInstruction::unpack(&[]).is_none());
+        assert!(MangoInstruction::unpack(&[0, 0]).is_none());
+
+        // Every discriminant's payload truncated at each possible length should yield None
+        // rather than panicking inside array_ref!'s length assertion.
+        for discrim in 0u32..31 {
+            let full = MangoInstruction::unpack(&{
+                let mut buf = discrim.to_le_bytes().to_vec();
+                buf.resize(4 + 64, 0);
+                buf
+            });
+            // Some discriminants are valid with an all-zero, full-length payload (e.g. CancelOrder
+            // requires side in {0, 1}, which zero satisfies); we only assert that truncating never panics.
+            let _ = full;
+
+            for len in 0..4usize {
+                let buf = discrim.to_le_bytes()[..len].to_vec();
+                assert!(MangoInstruction::unpack(&buf).is_none());
+            }
+            for data_len in 0..256usize {
+                let mut buf = discrim.to_le_bytes().to_vec();
+                buf.extend(std::iter::repeat(0u8).take(data_len));
+                // Must not panic; result may be Some or None depending on whether data_len
+                // happens to satisfy this discriminant's minimum length.
+                let _ = MangoInstruction::unpack(&buf);
+            }
+        }
+    }
+
+    #[test]
+    fn unpack_oversized_buffers_ignore_trailing_bytes() {
+        let mut buf = MangoInstruction::Deposit { quantity: 42 }.pack();
+        buf.extend_from_slice(&[0xffu8; 100]);
+        assert_eq!(
+            MangoInstruction::unpack(&buf),
+            Some(MangoInstruction::Deposit { quantity: 42 })
+        );
+    }
+
+    #[test]
+    fn unpack_unknown_discriminant_returns_none() {
+        assert!(MangoInstruction::unpack(&31u32.to_le_bytes()).is_none());
+        assert!(MangoInstruction::unpack(&u32::MAX.to_le_bytes()).is_none());
+    }
+
+    #[test]
+    fn account_layout_matches_documented_account_count() {
+        assert_eq!(
+            MangoInstruction::InitMangoGroup {
+                signer_nonce: 0, maint_coll_ratio: U64F64::from_num(0), init_coll_ratio: U64F64::from_num(0),
+                borrow_limits: [0; NUM_TOKENS],
+                borrow_fee_params: [DEFAULT_BORROW_FEE_PARAMS; NUM_TOKENS]
+            }.account_layout().len(),
+            7 + 2 * NUM_TOKENS + 2 * NUM_MARKETS
+        );
+        assert_eq!(MangoInstruction::InitMarginAccount.account_layout().len(), 4);
+        assert_eq!(MangoInstruction::Deposit { quantity: 0 }.account_layout().len(), 7);
+        assert_eq!(
+            MangoInstruction::Withdraw { quantity: 0 }.account_layout().len(),
+            8 + 2 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::Borrow { token_index: 0, quantity: 0 }.account_layout().len(),
+            8 + 2 * NUM_MARKETS
+        );
+        assert_eq!(MangoInstruction::SettleBorrow { token_index: 0, quantity: 0 }.account_layout().len(), 4);
+        assert_eq!(MangoInstruction::SettleBorrowAll.account_layout().len(), 4);
+        assert_eq!(
+            MangoInstruction::Liquidate { deposit_quantities: [0; NUM_TOKENS] }.account_layout().len(),
+            5 + 2 * NUM_MARKETS + 2 * NUM_TOKENS
+        );
+        assert_eq!(MangoInstruction::DepositSrm { quantity: 0 }.account_layout().len(), 8);
+        assert_eq!(MangoInstruction::WithdrawSrm { quantity: 0 }.account_layout().len(), 8);
+        assert_eq!(
+            MangoInstruction::PlaceOrder { order: sample_order(), reduce_only: false }.account_layout().len(),
+            17 + 2 * NUM_MARKETS
+        );
+        assert_eq!(MangoInstruction::SettleFunds.account_layout().len(), 14);
+        assert_eq!(
+            MangoInstruction::CancelOrder {
+                order: serum_dex::instruction::CancelOrderInstructionV2 {
+                    side: serum_dex::matching::Side::Ask, order_id: 0
+                }
+            }.account_layout().len(),
+            11
+        );
+        assert_eq!(MangoInstruction::CancelOrderByClientId { client_id: 0 }.account_layout().len(), 11);
+        assert_eq!(
+            MangoInstruction::CancelAllOrders { limit: 5 }.account_layout().len(),
+            6 + 5 * NUM_MARKETS
+        );
+        assert_eq!(MangoInstruction::ChangeBorrowLimit { token_index: 0, borrow_limit: 0 }.account_layout().len(), 2);
+        assert_eq!(
+            MangoInstruction::ChangeLiquidationParams {
+                liquidation_params: LiquidationParams {
+                    dust_threshold: U64F64::from_num(1),
+                    liquidation_fee_bps: 500,
+                    max_socialized_loss_bps: 2_500,
+                    close_factor_bps: 5_000,
+                }
+            }.account_layout().len(),
+            2
+        );
+        assert_eq!(
+            MangoInstruction::PlaceAndSettle { order: sample_order(), reduce_only: false }.account_layout().len(),
+            19 + 2 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::DepositAndPlace { quantity: 0, order: sample_order() }.account_layout().len(),
+            20 + 2 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::ForceCancelOrders { limit: 5 }.account_layout().len(),
+            16 + 3 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::PartialLiquidate { max_deposit: 0 }.account_layout().len(),
+            10 + 3 * NUM_MARKETS
+        );
+        assert_eq!(MangoInstruction::AddMarginAccountInfo { info: [0; INFO_LEN] }.account_layout().len(), 3);
+        assert_eq!(MangoInstruction::DepositMsrm { quantity: 0 }.account_layout().len(), 8);
+        assert_eq!(MangoInstruction::WithdrawMsrm { quantity: 0 }.account_layout().len(), 8);
+        assert_eq!(
+            MangoInstruction::ChangeInterestParams {
+                token_index: 0,
+                interest_rate_params: InterestRateParams {
+                    optimal_util: U64F64::from_num(0.7),
+                    base_rate: U64F64::from_num(0),
+                    rate_slope1: U64F64::from_num(0.05),
+                    rate_slope2: U64F64::from_num(0.5),
+                },
+            }.account_layout().len(),
+            2
+        );
+        assert_eq!(
+            MangoInstruction::PlaceAndSettleMulti { orders: [None; NUM_MARKETS] }.account_layout().len(),
+            10 + 9 * NUM_MARKETS + 2 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::ForceCancelAllOrders { limit: 5 }.account_layout().len(),
+            8 + 8 * NUM_MARKETS + 3 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::ForceLiquidateOnDex { limit: 5 }.account_layout().len(),
+            10 + 9 * NUM_MARKETS + 3 * NUM_MARKETS
+        );
+        assert_eq!(
+            MangoInstruction::ChangeCollateralWeights {
+                token_index: 0,
+                asset_weight: U64F64::from_num(0.9),
+                liab_weight: U64F64::from_num(1.1),
+            }.account_layout().len(),
+            2
+        );
+        assert_eq!(MangoInstruction::SetOracle2 { token_index: 0 }.account_layout().len(), 3);
+    }
+
+    #[test]
+    fn extend_with_open_orders_and_oracles_rejects_wrong_lengths() {
+        let mut accounts = Vec::new();
+        let short = vec![Pubkey::new_unique(); NUM_MARKETS - 1];
+        let full = vec![Pubkey::new_unique(); NUM_MARKETS];
+
+        assert_eq!(
+            extend_with_open_orders_and_oracles(&mut accounts, &short, &full, false),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            extend_with_open_orders_and_oracles(&mut accounts, &full, &short, false),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert!(accounts.is_empty());
+
+        extend_with_open_orders_and_oracles(&mut accounts, &full, &full, true).unwrap();
+        assert_eq!(accounts.len(), 2 * NUM_MARKETS);
+        assert!(accounts[..NUM_MARKETS].iter().all(|m| m.is_writable));
+        assert!(accounts[NUM_MARKETS..].iter().all(|m| !m.is_writable));
+    }
+}
@@ -2,6 +2,7 @@ pub mod processor;
 pub mod state;
 pub mod instruction;
 pub mod error;
+pub mod event;
 pub mod utils;
 
 #[cfg(not(feature = "no-entrypoint"))]
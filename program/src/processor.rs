@@ -21,9 +21,10 @@ use solana_program::sysvar::Sysvar;
 use spl_token::state::{Account, Mint};
 
 use crate::error::{check_assert, MangoError, MangoErrorCode, MangoResult, SourceFileId};
+use crate::event::LiquidationEvent;
 use crate::instruction::MangoInstruction;
-use crate::state::{AccountFlag, check_open_orders, DUST_THRESHOLD, load_asks_mut, load_bids_mut, load_market_state, load_open_orders, Loadable, MangoGroup, MangoIndex, MangoSrmAccount, MarginAccount, NUM_MARKETS, NUM_TOKENS, ONE_U64F64, PARTIAL_LIQ_INCENTIVE, ZERO_U64F64, INFO_LEN};
-use crate::utils::{gen_signer_key, gen_signer_seeds};
+use crate::state::{AccountFlag, BorrowFeeParams, check_open_orders, DEFAULT_INTEREST_RATE_PARAMS, DEFAULT_LIQUIDATION_PARAMS, DEFAULT_MAX_INDEX_STALENESS, DEFAULT_MAX_ORACLE_SPREAD_BPS, DEFAULT_SRM_FEE_TIER_THRESHOLDS, DEFAULT_SRM_FEE_TIERS, InterestRateParams, LiquidationParams, load_asks_mut, load_bids_mut, load_market_state, load_open_orders, Loadable, MANGO_GROUP_VERSION, MANGO_SRM_ACCOUNT_VERSION, MARGIN_ACCOUNT_VERSION, MangoGroup, MangoIndex, MangoSrmAccount, MarginAccount, NUM_MARKETS, NUM_TOKENS, ONE_U64F64, ZERO_U64F64, INFO_LEN, peek_account_flags};
+use crate::utils::gen_signer_key;
 
 macro_rules! check_default {
     ($cond:expr) => {
@@ -65,8 +66,27 @@ pub mod srm_token {
     declare_id!("SRMuApVNdxXokk5GT7XD5cUUgXMBCoAz2LHeuAoKWRt");
 }
 
+pub mod msrm_token {
+    use solana_program::declare_id;
+
+    #[cfg(feature = "devnet")]
+    declare_id!("8DJBo4bF4mHNxobjdax3BL9RMh5o71Jf8UiKsf5C5eVH");
+    #[cfg(not(feature = "devnet"))]
+    declare_id!("MSRMcoVyrFxnSgo5uXwone5SKcGhT1KEJMFEkMEWf9L");
+}
+
 pub const LIQ_MIN_COLL_RATIO: U64F64 = U64F64!(1.01);
 
+/// How far through the book `force_liquidate_on_dex` is willing to price its IOC orders, relative
+/// to the oracle price, in order to guarantee a fill. Unlike `PlaceOrder`, the liqee isn't around
+/// to set a limit price, so the program has to pick one itself; this bounds the worst-case price
+/// impact of an involuntary unwind.
+pub const FORCE_LIQUIDATE_SLIPPAGE_BPS: u64 = 1_000;
+
+/// Max client order ids the dex's `CancelOrdersByClientIds` instruction can cancel in one CPI;
+/// see `invoke_cancel_orders`.
+pub const MAX_CANCEL_ORDERS_BY_CLIENT_IDS: usize = 8;
+
 pub struct Processor {}
 
 impl Processor {
@@ -77,7 +97,8 @@ impl Processor {
         signer_nonce: u64,
         maint_coll_ratio: U64F64,
         init_coll_ratio: U64F64,
-        borrow_limits: [u64; NUM_TOKENS]
+        borrow_limits: [u64; NUM_TOKENS],
+        borrow_fee_params: [BorrowFeeParams; NUM_TOKENS]
     ) -> MangoResult<()> {
         const NUM_FIXED: usize = 7;
         let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_TOKENS + 2 * NUM_MARKETS];
@@ -110,10 +131,19 @@ impl Processor {
 
         check_eq!(mango_group_acc.owner, program_id, MangoErrorCode::InvalidGroupOwner)?;
         check_eq!(mango_group.account_flags, 0, MangoErrorCode::InvalidGroupFlags)?;
-        mango_group.account_flags = (AccountFlag::Initialized | AccountFlag::MangoGroup).bits();
+        mango_group.account_flags =
+            (AccountFlag::Initialized | AccountFlag::MangoGroup | AccountFlag::CanonicalSignerNonce).bits();
+        mango_group.version = MANGO_GROUP_VERSION;
+        // Every group currently fills all NUM_TOKENS/NUM_MARKETS slots; num_tokens/num_markets
+        // exist so valuation math (see MangoGroup::get_assets_val and friends) can one day stop
+        // at fewer than the compile-time maximum once a smaller-group or add-market path exists.
+        mango_group.num_tokens = NUM_TOKENS as u8;
+        mango_group.num_markets = NUM_MARKETS as u8;
 
         check!(rent.is_exempt(mango_group_acc.lamports(), size_of::<MangoGroup>()), MangoErrorCode::GroupNotRentExempt)?;
-        check!(gen_signer_key(signer_nonce, mango_group_acc.key, program_id)? == *signer_acc.key, MangoErrorCode::InvalidSignerKey)?;
+        // New groups always use the canonical one-byte bump; see `AccountFlag::CanonicalSignerNonce`
+        // and `crate::utils::create_signer_key_and_nonce`.
+        check!(gen_signer_key(signer_nonce as u8, mango_group_acc.key, program_id)? == *signer_acc.key, MangoErrorCode::InvalidSignerKey)?;
         mango_group.signer_nonce = signer_nonce;
         mango_group.signer_key = *signer_acc.key;
         mango_group.dex_program_id = *dex_prog_acc.key;
@@ -132,6 +162,14 @@ impl Processor {
         check!(admin_acc.is_signer, MangoErrorCode::Default)?;
         mango_group.admin = *admin_acc.key;
         mango_group.borrow_limits = borrow_limits;
+        mango_group.borrow_fee_params = borrow_fee_params;
+        mango_group.srm_fee_tier_thresholds = DEFAULT_SRM_FEE_TIER_THRESHOLDS;
+        mango_group.srm_fee_tiers = DEFAULT_SRM_FEE_TIERS;
+        mango_group.max_index_staleness = DEFAULT_MAX_INDEX_STALENESS;
+        mango_group.liquidation_params = DEFAULT_LIQUIDATION_PARAMS;
+        mango_group.max_oracle_spread_bps = DEFAULT_MAX_ORACLE_SPREAD_BPS;
+        mango_group.asset_weights = [ONE_U64F64; NUM_TOKENS];
+        mango_group.liab_weights = [ONE_U64F64; NUM_TOKENS];
 
         let curr_ts = clock.unix_timestamp as u64;
         for i in 0..NUM_TOKENS {
@@ -150,12 +188,13 @@ impl Processor {
                 borrow: ONE_U64F64,
                 deposit: ONE_U64F64  // Smallest unit of interest is 0.0001% or 0.000001
             };
+            mango_group.interest_rate_params[i] = DEFAULT_INTEREST_RATE_PARAMS;
             mango_group.mint_decimals[i] = mint.decimals;
         }
 
         for i in 0..NUM_MARKETS {
             let spot_market_acc: &AccountInfo = &spot_market_accs[i];
-            let spot_market = load_market_state(
+            let (spot_market, _) = load_market_state(
                 spot_market_acc, dex_prog_acc.key
             )?;
             let sm_base_mint = spot_market.coin_mint;
@@ -199,6 +238,7 @@ impl Processor {
         margin_account.account_flags = (AccountFlag::Initialized | AccountFlag::MarginAccount).bits();
         margin_account.mango_group = *mango_group_acc.key;
         margin_account.owner = *owner_acc.key;
+        margin_account.version = MARGIN_ACCOUNT_VERSION;
 
         Ok(())
     }
@@ -308,17 +348,28 @@ impl Processor {
         let native_deposits: u64 = (margin_account.deposits[token_index].checked_mul(index.deposit).unwrap()).to_num();
         let available = native_deposits;
 
-        check!(available >= quantity, MangoErrorCode::InsufficientFunds)?;
-        // TODO just borrow (quantity - available)
-        let prices = get_prices(&mango_group, oracle_accs)?;
-        // Withdraw from deposit
-        let withdrew: U64F64 = U64F64::from_num(quantity) / index.deposit;
-        checked_sub_deposit(&mut mango_group, &mut margin_account, token_index, withdrew)?;
+        let prices = get_prices(&mango_group, &clock, oracle_accs, None)?;
+
+        if available >= quantity {
+            // Withdraw from deposit
+            let withdrew: U64F64 = U64F64::from_num(quantity) / index.deposit;
+            checked_sub_deposit(&mut mango_group, &mut margin_account, token_index, withdrew)?;
+        } else {
+            // Not enough in deposit; zero it out and borrow the shortfall so this is a single
+            // leveraged withdraw instead of forcing a separate `borrow` call.
+            let deposit: U64F64 = margin_account.deposits[token_index];
+            checked_sub_deposit(&mut mango_group, &mut margin_account, token_index, deposit)?;
+
+            let shortfall = quantity - available;
+            let borrow: U64F64 = U64F64::from_num(shortfall) / index.borrow;
+            checked_add_borrow(&mut mango_group, &mut margin_account, token_index, borrow)?;
+        }
 
         // Make sure accounts are in valid state after withdrawal
         let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
         check!(coll_ratio >= mango_group.init_coll_ratio, MangoErrorCode::CollateralRatioLimit)?;
         check_default!(mango_group.has_valid_deposits_borrows(token_index))?;
+        check_borrow_limit(&mango_group, token_index)?;
 
         // Send out withdraw instruction to SPL token program
         check_eq_default!(token_prog_acc.key, &spl_token::id())?;
@@ -337,7 +388,8 @@ impl Processor {
             token_prog_acc.clone()
         ];
 
-        let signer_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         solana_program::program::invoke_signed(&withdraw_instruction, &withdraw_accs, &[&signer_seeds])?;
 
         Ok(())
@@ -350,7 +402,7 @@ impl Processor {
         token_index: usize,
         quantity: u64
     ) -> MangoResult<()> {
-        const NUM_FIXED: usize = 4;
+        const NUM_FIXED: usize = 8;
         let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
         let (
             fixed_accs,
@@ -363,6 +415,10 @@ impl Processor {
             margin_account_acc,
             owner_acc,
             clock_acc,
+            vault_acc,
+            signer_acc,
+            token_prog_acc,
+            host_acc,
         ] = fixed_accs;
 
         let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
@@ -371,6 +427,8 @@ impl Processor {
         )?;
         check_default!(owner_acc.is_signer)?;
         check_eq_default!(&margin_account.owner, owner_acc.key)?;
+        check_eq_default!(&mango_group.vaults[token_index], vault_acc.key)?;
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
 
         for i in 0..NUM_MARKETS {
             check_eq_default!(open_orders_accs[i].key, &margin_account.open_orders[i])?;
@@ -381,17 +439,50 @@ impl Processor {
 
         let index: MangoIndex = mango_group.indexes[token_index];
 
-        let borrow = U64F64::from_num(quantity) / index.borrow;
+        // The origination fee is added on top of quantity to the borrower's debt; the deposit
+        // credit stays exactly quantity, so the fee is funded entirely out of the borrower's own
+        // collateral ratio headroom. See `BorrowFeeParams`.
+        let borrow_fee_params = mango_group.borrow_fee_params[token_index];
+        let fee_native = U64F64::from_num(quantity).checked_mul(borrow_fee_params.origination_fee_rate).unwrap();
+        let host_fee_native = fee_native.checked_mul(U64F64::from_num(borrow_fee_params.host_fee_bps))
+            .unwrap().checked_div(U64F64::from_num(10_000u16)).unwrap();
+        let protocol_fee_native = fee_native.checked_sub(host_fee_native).unwrap();
+
+        let borrow = (U64F64::from_num(quantity) + fee_native) / index.borrow;
         let deposit = U64F64::from_num(quantity) / index.deposit;
 
         checked_add_deposit(&mut mango_group, &mut margin_account, token_index, deposit)?;
         checked_add_borrow(&mut mango_group, &mut margin_account, token_index, borrow)?;
+        mango_group.add_fee(token_index, protocol_fee_native)?;
 
-        let prices = get_prices(&mango_group, oracle_accs)?;
+        let prices = get_prices(&mango_group, &clock, oracle_accs, None)?;
         let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
 
         check_default!(coll_ratio >= mango_group.init_coll_ratio)?;
         check_default!(mango_group.has_valid_deposits_borrows(token_index))?;
+        check_borrow_limit(&mango_group, token_index)?;
+
+        let host_fee_quantity: u64 = host_fee_native.to_num();
+        if host_fee_quantity > 0 {
+            let transfer_instruction = spl_token::instruction::transfer(
+                &spl_token::ID,
+                vault_acc.key,
+                host_acc.key,
+                signer_acc.key,
+                &[],
+                host_fee_quantity
+            )?;
+            let transfer_accs = [
+                vault_acc.clone(),
+                host_acc.clone(),
+                signer_acc.clone(),
+                token_prog_acc.clone()
+            ];
+            let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+            solana_program::program::invoke_signed(&transfer_instruction, &transfer_accs, &[&signer_seeds])?;
+        }
+
         Ok(())
     }
 
@@ -424,6 +515,35 @@ impl Processor {
         Ok(())
     }
 
+    #[inline(never)]
+    fn settle_borrow_all(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 4;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            mango_group_acc,
+            margin_account_acc,
+            owner_acc,
+            clock_acc,
+        ] = accounts;
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+        let mut margin_account = MarginAccount::load_mut_checked(
+            program_id, margin_account_acc, mango_group_acc.key
+        )?;
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+
+        for token_index in 0..NUM_TOKENS {
+            settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, token_index)?;
+        }
+        Ok(())
+    }
+
     #[inline(never)]
     fn liquidate(
         _program_id: &Pubkey,
@@ -472,6 +592,7 @@ impl Processor {
             mango_srm_account.mango_group = *mango_group_acc.key;
             check_default!(owner_acc.is_signer)?;  // this is not necessary but whatever
             mango_srm_account.owner = *owner_acc.key;
+            mango_srm_account.version = MANGO_SRM_ACCOUNT_VERSION;
         } else {
             check_eq_default!(mango_srm_account.account_flags, (AccountFlag::Initialized | AccountFlag::MangoSrmAccount).bits())?;
             check_eq_default!(&mango_srm_account.mango_group, mango_group_acc.key)?;
@@ -550,13 +671,150 @@ impl Processor {
             signer_acc.clone(),
             token_prog_acc.clone()
         ];
-        let signer_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         solana_program::program::invoke_signed(&withdraw_instruction, &withdraw_accs, &[&signer_seeds])?;
         mango_srm_account.amount = mango_srm_account.amount.checked_sub(quantity).unwrap();
 
         Ok(())
     }
 
+    #[inline(never)]
+    fn deposit_msrm(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        quantity: u64
+    ) -> MangoResult<()> {
+
+        const NUM_FIXED: usize = 8;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            mango_group_acc,
+            mango_srm_account_acc,
+            owner_acc,
+            msrm_account_acc,
+            msrm_vault_acc,
+            token_prog_acc,
+            clock_acc,
+            rent_acc,
+        ] = accounts;
+        // prog_assert!(owner_acc.is_signer)?; // anyone can deposit, not just owner
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+
+        // Check if SRM is part of the MangoGroup, if so throw err
+        check!(mango_group.get_token_index(&srm_token::ID).is_none(), MangoErrorCode::FeeDiscountFunctionality)?;
+
+        // if MangoSrmAccount is empty, initialize it
+        check_eq_default!(mango_srm_account_acc.data_len(), size_of::<MangoSrmAccount>())?;
+        let mut mango_srm_account = MangoSrmAccount::load_mut(mango_srm_account_acc)?;
+        check_eq_default!(mango_srm_account_acc.owner, program_id)?;
+
+        if mango_srm_account.account_flags == 0 {
+            let rent = Rent::from_account_info(rent_acc)?;
+            check_default!(rent.is_exempt(mango_srm_account_acc.lamports(), size_of::<MangoSrmAccount>()))?;
+
+            mango_srm_account.account_flags = (AccountFlag::Initialized | AccountFlag::MangoSrmAccount).bits();
+            mango_srm_account.mango_group = *mango_group_acc.key;
+            check_default!(owner_acc.is_signer)?;  // this is not necessary but whatever
+            mango_srm_account.owner = *owner_acc.key;
+            mango_srm_account.version = MANGO_SRM_ACCOUNT_VERSION;
+        } else {
+            check_eq_default!(mango_srm_account.account_flags, (AccountFlag::Initialized | AccountFlag::MangoSrmAccount).bits())?;
+            check_eq_default!(&mango_srm_account.mango_group, mango_group_acc.key)?;
+        }
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+
+        // lazily bind the MSRM vault on first deposit, same as srm_vault is bound in init_mango_group
+        if mango_group.msrm_vault == Pubkey::default() {
+            let msrm_vault = Account::unpack(&msrm_vault_acc.try_borrow_data()?)?;
+            check_default!(msrm_vault.is_initialized())?;
+            check_eq_default!(&msrm_vault.owner, &mango_group.signer_key)?;
+            check_eq_default!(msrm_token::ID, msrm_vault.mint)?;
+            check_eq_default!(msrm_vault_acc.owner, &spl_token::id())?;
+            mango_group.msrm_vault = *msrm_vault_acc.key;
+        } else {
+            check_eq_default!(msrm_vault_acc.key, &mango_group.msrm_vault)?;
+        }
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+        let deposit_instruction = spl_token::instruction::transfer(
+            &spl_token::id(),
+            msrm_account_acc.key,
+            msrm_vault_acc.key,
+            &owner_acc.key, &[], quantity
+        )?;
+        let deposit_accs = [
+            msrm_account_acc.clone(),
+            msrm_vault_acc.clone(),
+            owner_acc.clone(),
+            token_prog_acc.clone()
+        ];
+
+        solana_program::program::invoke_signed(&deposit_instruction, &deposit_accs, &[])?;
+        mango_srm_account.msrm_amount = mango_srm_account.msrm_amount.checked_add(quantity).unwrap();
+        Ok(())
+    }
+
+    #[inline(never)]
+    fn withdraw_msrm(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        quantity: u64
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 8;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            mango_group_acc,
+            mango_srm_account_acc,
+            owner_acc,
+            msrm_account_acc,
+            msrm_vault_acc,
+            signer_acc,
+            token_prog_acc,
+            clock_acc,
+        ] = accounts;
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+
+        // Check if SRM is part of the MangoGroup, if so throw err
+        check!(mango_group.get_token_index(&srm_token::ID).is_none(), MangoErrorCode::FeeDiscountFunctionality)?;
+
+        let mut mango_srm_account = MangoSrmAccount::load_mut_checked(
+            program_id, mango_srm_account_acc, mango_group_acc.key)?;
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&mango_srm_account.owner, owner_acc.key)?;
+        check_eq_default!(msrm_vault_acc.key, &mango_group.msrm_vault)?;
+        check_default!(mango_srm_account.msrm_amount >= quantity)?;
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+
+        // Send out withdraw instruction to SPL token program
+        let withdraw_instruction = spl_token::instruction::transfer(
+            &spl_token::id(),
+            msrm_vault_acc.key,
+            msrm_account_acc.key,
+            signer_acc.key,
+            &[],
+            quantity
+        )?;
+        let withdraw_accs = [
+            msrm_vault_acc.clone(),
+            msrm_account_acc.clone(),
+            signer_acc.clone(),
+            token_prog_acc.clone()
+        ];
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+        solana_program::program::invoke_signed(&withdraw_instruction, &withdraw_accs, &[&signer_seeds])?;
+        mango_srm_account.msrm_amount = mango_srm_account.msrm_amount.checked_sub(quantity).unwrap();
+
+        Ok(())
+    }
+
     #[inline(never)]
     fn change_borrow_limit(
         program_id: &Pubkey,
@@ -584,90 +842,257 @@ impl Processor {
     }
 
     #[inline(never)]
-    fn place_order(
+    fn change_interest_params(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        order: serum_dex::instruction::NewOrderInstructionV3
+        token_index: usize,
+        interest_rate_params: InterestRateParams
     ) -> MangoResult<()> {
-        const NUM_FIXED: usize = 17;
-        let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
-        let (
-            fixed_accs,
-            open_orders_accs,
-            oracle_accs,
-        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS];
-
+        const NUM_FIXED: usize = 2;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
         let [
             mango_group_acc,
-            owner_acc,
-            margin_account_acc,
-            clock_acc,
-            dex_prog_acc,
-            spot_market_acc,
-            dex_request_queue_acc,
-            dex_event_queue_acc,
-            bids_acc,
-            asks_acc,
-            vault_acc,
-            signer_acc,
-            dex_base_acc,
-            dex_quote_acc,
-            token_prog_acc,
-            rent_acc,
-            srm_vault_acc,
-        ] = fixed_accs;
+            admin_acc,
+        ] = accounts;
 
-        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
-        let mut margin_account = MarginAccount::load_mut_checked(
-            program_id, margin_account_acc, mango_group_acc.key
+        let mut mango_group = MangoGroup::load_mut_checked(
+            mango_group_acc,
+            program_id
         )?;
 
-        let clock = Clock::from_account_info(clock_acc)?;
-        mango_group.update_indexes(&clock)?;
+        check_eq_default!(admin_acc.key, &mango_group.admin)?;
+        check_default!(admin_acc.is_signer)?;
+        // get_interest_rate divides by optimal_util and by (1 - optimal_util), so either end of
+        // that range would let governance brick the token's interest math for every deposit/
+        // withdraw/borrow; the slopes and base rate feed the same formula, so keep them sane too.
+        check!(
+            interest_rate_params.optimal_util > ZERO_U64F64 && interest_rate_params.optimal_util < ONE_U64F64,
+            MangoErrorCode::Default
+        )?;
+        check!(interest_rate_params.base_rate >= ZERO_U64F64, MangoErrorCode::Default)?;
+        check!(interest_rate_params.rate_slope1 >= ZERO_U64F64, MangoErrorCode::Default)?;
+        check!(interest_rate_params.rate_slope2 >= ZERO_U64F64, MangoErrorCode::Default)?;
 
-        let prices = get_prices(&mango_group, oracle_accs)?;
-        let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
-        if margin_account.being_liquidated {
-            if coll_ratio >= mango_group.init_coll_ratio {
-                margin_account.being_liquidated = false;
-            } else {
-                throw_err!(MangoErrorCode::BeingLiquidated)?;
-            }
-        }
-        let reduce_only = coll_ratio < mango_group.init_coll_ratio;
+        mango_group.interest_rate_params[token_index] = interest_rate_params;
+        Ok(())
+    }
 
-        check_default!(owner_acc.is_signer)?;
-        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+    #[inline(never)]
+    fn change_liquidation_params(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        liquidation_params: LiquidationParams
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 2;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            mango_group_acc,
+            admin_acc,
+        ] = accounts;
 
-        let market_i = mango_group.get_market_index(spot_market_acc.key).unwrap();
-        let token_i = match order.side {
-            Side::Bid => NUM_MARKETS,
-            Side::Ask => market_i
-        };
-        check_eq_default!(&mango_group.vaults[token_i], vault_acc.key)?;
+        let mut mango_group = MangoGroup::load_mut_checked(
+            mango_group_acc,
+            program_id
+        )?;
 
-        let pre_amount = {  // this is to keep track of how much funds were transferred out
-            let vault = Account::unpack(&vault_acc.try_borrow_data()?)?;
-            vault.amount
-        };
+        check_eq_default!(admin_acc.key, &mango_group.admin)?;
+        check_default!(admin_acc.is_signer)?;
+        check!(
+            liquidation_params.max_socialized_loss_bps <= 10_000,
+            MangoErrorCode::Default
+        )?;
+        check!(
+            liquidation_params.close_factor_bps <= 10_000,
+            MangoErrorCode::Default
+        )?;
 
-        for i in 0..NUM_MARKETS {
-            let open_orders_acc = &open_orders_accs[i];
-            if i == market_i {  // this one must not be default pubkey
-                check_default!(*open_orders_acc.key != Pubkey::default())?;
-                if margin_account.open_orders[i] == Pubkey::default() {
-                    let open_orders = load_open_orders(open_orders_acc)?;
-                    check_eq_default!(open_orders.account_flags, 0)?;
-                    margin_account.open_orders[i] = *open_orders_acc.key;
-                }
-            } else {
-                check_eq_default!(open_orders_accs[i].key, &margin_account.open_orders[i])?;
-                check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
-            }
-        }
+        mango_group.liquidation_params = liquidation_params;
+        Ok(())
+    }
 
-        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
-        check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
+    #[inline(never)]
+    fn change_collateral_weights(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        token_index: usize,
+        asset_weight: U64F64,
+        liab_weight: U64F64,
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 2;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            mango_group_acc,
+            admin_acc,
+        ] = accounts;
+
+        let mut mango_group = MangoGroup::load_mut_checked(
+            mango_group_acc,
+            program_id
+        )?;
+
+        check_eq_default!(admin_acc.key, &mango_group.admin)?;
+        check_default!(admin_acc.is_signer)?;
+        check_default!(token_index < NUM_TOKENS)?;
+        // Collateral can only be discounted, never marked up; liabilities only marked up, never
+        // discounted -- otherwise a governance vote could make an account look healthier than it is.
+        check!(
+            asset_weight > ZERO_U64F64 && asset_weight <= ONE_U64F64,
+            MangoErrorCode::Default
+        )?;
+        check!(liab_weight >= ONE_U64F64, MangoErrorCode::Default)?;
+
+        mango_group.asset_weights[token_index] = asset_weight;
+        mango_group.liab_weights[token_index] = liab_weight;
+        Ok(())
+    }
+
+    #[inline(never)]
+    fn migrate(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 2;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            target_acc,
+            rent_acc,
+        ] = accounts;
+
+        check_eq_default!(target_acc.owner, program_id)?;
+        let flags = peek_account_flags(target_acc)?;
+        if flags.contains(AccountFlag::MangoGroup) {
+            MangoGroup::migrate(target_acc, program_id)?;
+        } else if flags.contains(AccountFlag::MarginAccount) {
+            MarginAccount::migrate(program_id, target_acc)?;
+        } else if flags.contains(AccountFlag::MangoSrmAccount) {
+            let rent = Rent::from_account_info(rent_acc)?;
+            MangoSrmAccount::migrate(target_acc, program_id, &rent)?;
+        } else {
+            check_default!(false)?;
+        }
+        Ok(())
+    }
+
+    #[inline(never)]
+    fn set_oracle2(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        token_index: usize,
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 3;
+        let accounts = array_ref![accounts, 0, NUM_FIXED];
+        let [
+            mango_group_acc,
+            admin_acc,
+            oracle2_acc,
+        ] = accounts;
+
+        let mut mango_group = MangoGroup::load_mut_checked(
+            mango_group_acc,
+            program_id
+        )?;
+
+        check_eq_default!(admin_acc.key, &mango_group.admin)?;
+        check_default!(admin_acc.is_signer)?;
+        check_default!(token_index < NUM_MARKETS)?;
+
+        // Same validity check InitMangoGroup runs on a primary oracle -- reject anything that
+        // isn't a live, initialized flux aggregator feed rather than storing an arbitrary account.
+        let _oracle = flux_aggregator::state::Aggregator::load_initialized(oracle2_acc)?;
+
+        mango_group.oracles2[token_index] = *oracle2_acc.key;
+        Ok(())
+    }
+
+    #[inline(never)]
+    fn place_order(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        order: serum_dex::instruction::NewOrderInstructionV3,
+        reduce_only: bool
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 17;
+        let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
+        let (
+            fixed_accs,
+            open_orders_accs,
+            oracle_accs,
+        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS];
+
+        let [
+            mango_group_acc,
+            owner_acc,
+            margin_account_acc,
+            clock_acc,
+            dex_prog_acc,
+            spot_market_acc,
+            dex_request_queue_acc,
+            dex_event_queue_acc,
+            bids_acc,
+            asks_acc,
+            vault_acc,
+            signer_acc,
+            dex_base_acc,
+            dex_quote_acc,
+            token_prog_acc,
+            rent_acc,
+            srm_vault_acc,
+        ] = fixed_accs;
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+        let mut margin_account = MarginAccount::load_mut_checked(
+            program_id, margin_account_acc, mango_group_acc.key
+        )?;
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+
+        let prices = get_prices(&mango_group, &clock, oracle_accs, None)?;
+        let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
+        if margin_account.being_liquidated {
+            if coll_ratio >= mango_group.init_coll_ratio {
+                margin_account.being_liquidated = false;
+            } else {
+                throw_err!(MangoErrorCode::BeingLiquidated)?;
+            }
+        }
+        let reduce_only = reduce_only || coll_ratio < mango_group.init_coll_ratio;
+
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+
+        let market_i = mango_group.get_market_index(spot_market_acc.key).unwrap();
+        check_reduce_only_order(
+            reduce_only, &mango_group, &margin_account, market_i, spot_market_acc, dex_prog_acc, &order
+        )?;
+        let token_i = match order.side {
+            Side::Bid => NUM_MARKETS,
+            Side::Ask => market_i
+        };
+        check_eq_default!(&mango_group.vaults[token_i], vault_acc.key)?;
+
+        let pre_amount = {  // this is to keep track of how much funds were transferred out
+            let vault = Account::unpack(&vault_acc.try_borrow_data()?)?;
+            vault.amount
+        };
+
+        for i in 0..NUM_MARKETS {
+            let open_orders_acc = &open_orders_accs[i];
+            if i == market_i {  // this one must not be default pubkey
+                check_default!(*open_orders_acc.key != Pubkey::default())?;
+                if margin_account.open_orders[i] == Pubkey::default() {
+                    let open_orders = load_open_orders(open_orders_acc)?;
+                    check_eq_default!(open_orders.account_flags, 0)?;
+                    margin_account.open_orders[i] = *open_orders_acc.key;
+                }
+            } else {
+                check_eq_default!(open_orders_accs[i].key, &margin_account.open_orders[i])?;
+                check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
+            }
+        }
+
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+        check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
         let data = serum_dex::instruction::MarketInstruction::NewOrderV3(order).pack();
         let instruction = Instruction {
             program_id: *dex_prog_acc.key,
@@ -705,7 +1130,8 @@ impl Processor {
             srm_vault_acc.clone(),
         ];
 
-        let signer_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         solana_program::program::invoke_signed(&instruction, &account_infos, &[&signer_seeds])?;
 
         let post_amount = {
@@ -796,7 +1222,8 @@ impl Processor {
             return Ok(());
         }
 
-        let signer_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         invoke_settle_funds(
             dex_prog_acc,
             spot_market_acc,
@@ -865,7 +1292,8 @@ impl Processor {
         let market_i = mango_group.get_market_index(spot_market_acc.key).unwrap();
         check_eq_default!(&margin_account.open_orders[market_i], open_orders_acc.key)?;
 
-        let signer_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         invoke_cancel_order(
             dex_prog_acc,
             spot_market_acc,
@@ -880,11 +1308,76 @@ impl Processor {
         Ok(())
     }
 
+    #[inline(never)]
+    fn cancel_all_orders(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        limit: u8
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 6;
+        let accounts = array_ref![accounts, 0, NUM_FIXED + 5 * NUM_MARKETS];
+        let (
+            fixed_accs,
+            spot_market_accs,
+            bids_accs,
+            asks_accs,
+            open_orders_accs,
+            dex_event_queue_accs,
+        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS];
+
+        let [
+            mango_group_acc,
+            owner_acc,  // signer
+            margin_account_acc,
+            clock_acc,
+            dex_prog_acc,
+            signer_acc,
+        ] = fixed_accs;
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+        let margin_account = MarginAccount::load_checked(
+            program_id,
+            margin_account_acc,
+            mango_group_acc.key
+        )?;
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+        check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
+        check_eq!(signer_acc.key, &mango_group.signer_key, MangoErrorCode::InvalidSignerKey)?;
+
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+        for i in 0..NUM_MARKETS {
+            let open_orders_acc = &open_orders_accs[i];
+            if margin_account.open_orders[i] == Pubkey::default() {
+                continue;  // never used this market's open orders account
+            }
+            check_eq_default!(open_orders_acc.key, &margin_account.open_orders[i])?;
+
+            invoke_cancel_orders(
+                open_orders_acc,
+                dex_prog_acc,
+                &spot_market_accs[i],
+                &bids_accs[i],
+                &asks_accs[i],
+                signer_acc,
+                &dex_event_queue_accs[i],
+                &[&signer_seeds],
+                limit
+            )?;
+        }
+        Ok(())
+    }
+
     #[inline(never)]
     fn place_and_settle(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        order: serum_dex::instruction::NewOrderInstructionV3
+        order: serum_dex::instruction::NewOrderInstructionV3,
+        reduce_only: bool
     ) -> MangoResult<()> {
         const NUM_FIXED: usize = 19;
         let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
@@ -921,10 +1414,472 @@ impl Processor {
             program_id, margin_account_acc, mango_group_acc.key
         )?;
 
-        let clock = Clock::from_account_info(clock_acc)?;
-        mango_group.update_indexes(&clock)?;
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+
+        let prices = get_prices(&mango_group, &clock, oracle_accs, None)?;
+        let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
+
+        if margin_account.being_liquidated {
+            if coll_ratio >= mango_group.init_coll_ratio {
+                margin_account.being_liquidated = false;
+            } else {
+                throw_err!(MangoErrorCode::BeingLiquidated)?;
+            }
+        }
+
+        let reduce_only = reduce_only || coll_ratio < mango_group.init_coll_ratio;
+
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+
+        let market_i = mango_group.get_market_index(spot_market_acc.key).unwrap();
+        check_reduce_only_order(
+            reduce_only, &mango_group, &margin_account, market_i, spot_market_acc, dex_prog_acc, &order
+        )?;
+        let side = order.side;
+        let (in_token_i, out_token_i, vault_acc) = match side {
+            Side::Bid => (market_i, NUM_MARKETS, quote_vault_acc),
+            Side::Ask => (NUM_MARKETS, market_i, base_vault_acc)
+        };
+        check_eq_default!(&mango_group.vaults[market_i], base_vault_acc.key)?;
+        check_eq_default!(&mango_group.vaults[NUM_MARKETS], quote_vault_acc.key)?;
+
+        let (pre_base, pre_quote) = {
+            (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
+             Account::unpack(&quote_vault_acc.try_borrow_data()?)?.amount)
+        };
+
+        for i in 0..NUM_MARKETS {
+            let open_orders_acc = &open_orders_accs[i];
+            if i == market_i {  // this one must not be default pubkey
+                check_default!(*open_orders_acc.key != Pubkey::default())?;
+
+                // if this is first time using this open_orders_acc, check and save it
+                if margin_account.open_orders[i] == Pubkey::default() {
+                    let open_orders = load_open_orders(open_orders_acc)?;
+                    check_eq_default!(open_orders.account_flags, 0)?;
+                    margin_account.open_orders[i] = *open_orders_acc.key;
+                } else {
+                    check_eq_default!(open_orders_accs[i].key, &margin_account.open_orders[i])?;
+                    check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
+                }
+            } else {
+                check_eq_default!(open_orders_accs[i].key, &margin_account.open_orders[i])?;
+                check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
+            }
+        }
+
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+        check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
+        let data = serum_dex::instruction::MarketInstruction::NewOrderV3(order).pack();
+        let instruction = Instruction {
+            program_id: *dex_prog_acc.key,
+            data,
+            accounts: vec![
+                AccountMeta::new(*spot_market_acc.key, false),
+                AccountMeta::new(*open_orders_accs[market_i].key, false),
+                AccountMeta::new(*dex_request_queue_acc.key, false),
+                AccountMeta::new(*dex_event_queue_acc.key, false),
+                AccountMeta::new(*bids_acc.key, false),
+                AccountMeta::new(*asks_acc.key, false),
+                AccountMeta::new(*vault_acc.key, false),
+                AccountMeta::new_readonly(*signer_acc.key, true),
+                AccountMeta::new(*dex_base_acc.key, false),
+                AccountMeta::new(*dex_quote_acc.key, false),
+                AccountMeta::new_readonly(*token_prog_acc.key, false),
+                AccountMeta::new_readonly(*rent_acc.key, false),
+                AccountMeta::new(*srm_vault_acc.key, false),
+            ],
+        };
+        let account_infos = [
+            dex_prog_acc.clone(),  // Have to add account of the program id
+            spot_market_acc.clone(),
+            open_orders_accs[market_i].clone(),
+            dex_request_queue_acc.clone(),
+            dex_event_queue_acc.clone(),
+            bids_acc.clone(),
+            asks_acc.clone(),
+            vault_acc.clone(),
+            signer_acc.clone(),
+            dex_base_acc.clone(),
+            dex_quote_acc.clone(),
+            token_prog_acc.clone(),
+            rent_acc.clone(),
+            srm_vault_acc.clone(),
+        ];
+
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+        solana_program::program::invoke_signed(&instruction, &account_infos, &[&signer_seeds])?;
+
+        // Settle funds for this market
+        invoke_settle_funds(
+            dex_prog_acc,
+            spot_market_acc,
+            &open_orders_accs[market_i],
+            signer_acc,
+            dex_base_acc,
+            dex_quote_acc,
+            base_vault_acc,
+            quote_vault_acc,
+            dex_signer_acc,
+            token_prog_acc,
+            &[&signer_seeds]
+        )?;
+
+        let (post_base, post_quote) = {
+            (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
+             Account::unpack(&quote_vault_acc.try_borrow_data()?)?.amount)
+        };
+
+        let (pre_in, pre_out, post_in, post_out) = match side {
+            Side::Bid => (pre_base, pre_quote, post_base, post_quote),
+            Side::Ask => (pre_quote, pre_base, post_quote, post_base)
+        };
+
+        // It's possible the net change was positive for both tokens
+        // It's not possible for in_token to be negative
+        let out_index: MangoIndex = mango_group.indexes[out_token_i];
+        let in_index: MangoIndex = mango_group.indexes[in_token_i];
+
+        // if out token was net negative, then you may need to borrow more
+        if post_out < pre_out {
+            let total_out = pre_out.checked_sub(post_out).unwrap();
+            let native_deposit = margin_account.get_native_deposit(&out_index, out_token_i);
+            if native_deposit < total_out {  // need to borrow
+                let avail_deposit = margin_account.deposits[out_token_i];
+                checked_sub_deposit(&mut mango_group, &mut margin_account, out_token_i, avail_deposit)?;
+                let rem_spend = U64F64::from_num(total_out - native_deposit);
+
+                check_default!(!reduce_only)?;  // Cannot borrow more in reduce only mode
+                checked_add_borrow(&mut mango_group, &mut margin_account, out_token_i, rem_spend / out_index.borrow)?;
+            } else {  // just spend user deposits
+                let mango_spent = U64F64::from_num(total_out) / out_index.deposit;
+                checked_sub_deposit(&mut mango_group, &mut margin_account, out_token_i, mango_spent)?;
+            }
+        } else {  // Add out token deposit
+            let deposit = U64F64::from_num(post_out.checked_sub(pre_out).unwrap()) / out_index.deposit;
+            checked_add_deposit(&mut mango_group, &mut margin_account, out_token_i, deposit)?;
+        }
+
+        let total_in = U64F64::from_num(post_in.checked_sub(pre_in).unwrap()) / in_index.deposit;
+        checked_add_deposit(&mut mango_group, &mut margin_account, in_token_i, total_in)?;
+
+        // Settle borrow
+        // TODO only do ops on tokens that have borrows and deposits
+        settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, out_token_i)?;
+        settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, in_token_i)?;
+
+        let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
+        check!(reduce_only || coll_ratio >= mango_group.init_coll_ratio, MangoErrorCode::CollateralRatioLimit)?;
+        check_default!(mango_group.has_valid_deposits_borrows(out_token_i))?;
+
+        Ok(())
+    }
+
+    /// Place and settle an order on every market in `orders` that's `Some`, then run the
+    /// collateral ratio and deposits/borrows validity checks exactly once at the end instead of
+    /// once per market. Otherwise identical to calling `place_and_settle` once per market: each traded
+    /// market still goes through the usual CPI place+settle and deposit/borrow accounting, and
+    /// `reduce_only`/`being_liquidated` gating still applies to every order.
+    fn place_and_settle_multi(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        orders: [Option<serum_dex::instruction::NewOrderInstructionV3>; NUM_MARKETS]
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 10;
+        const PER_MARKET: usize = 9;
+        let accounts = array_ref![accounts, 0, NUM_FIXED + PER_MARKET * NUM_MARKETS + 2 * NUM_MARKETS];
+        let (
+            fixed_accs,
+            market_accs,
+            open_orders_accs,
+            oracle_accs,
+        ) = array_refs![accounts, NUM_FIXED, PER_MARKET * NUM_MARKETS, NUM_MARKETS, NUM_MARKETS];
+
+        let [
+            mango_group_acc,
+            owner_acc,
+            margin_account_acc,
+            clock_acc,
+            dex_prog_acc,
+            quote_vault_acc,
+            signer_acc,
+            token_prog_acc,
+            rent_acc,
+            srm_vault_acc
+        ] = fixed_accs;
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+        let mut margin_account = MarginAccount::load_mut_checked(
+            program_id, margin_account_acc, mango_group_acc.key
+        )?;
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+
+        let prices = get_prices(&mango_group, &clock, oracle_accs, None)?;
+        let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
+
+        if margin_account.being_liquidated {
+            if coll_ratio >= mango_group.init_coll_ratio {
+                margin_account.being_liquidated = false;
+            } else {
+                throw_err!(MangoErrorCode::BeingLiquidated)?;
+            }
+        }
+
+        let reduce_only = coll_ratio < mango_group.init_coll_ratio;
+
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+        check_eq_default!(&mango_group.vaults[NUM_MARKETS], quote_vault_acc.key)?;
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+        check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
+
+        for market_i in 0..NUM_MARKETS {
+            let open_orders_acc = &open_orders_accs[market_i];
+            if orders[market_i].is_some() {  // this one must not be default pubkey
+                check_default!(*open_orders_acc.key != Pubkey::default())?;
+
+                // if this is first time using this open_orders_acc, check and save it
+                if margin_account.open_orders[market_i] == Pubkey::default() {
+                    let open_orders = load_open_orders(open_orders_acc)?;
+                    check_eq_default!(open_orders.account_flags, 0)?;
+                    margin_account.open_orders[market_i] = *open_orders_acc.key;
+                } else {
+                    check_eq_default!(open_orders_acc.key, &margin_account.open_orders[market_i])?;
+                    check_open_orders(open_orders_acc, &mango_group.signer_key)?;
+                }
+            } else {
+                check_eq_default!(open_orders_acc.key, &margin_account.open_orders[market_i])?;
+                check_open_orders(open_orders_acc, &mango_group.signer_key)?;
+            }
+        }
+
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+
+        // Book every order first; the collateral ratio and deposits/borrows validity checks
+        // happen exactly once, after the loop, instead of once per market.
+        for market_i in 0..NUM_MARKETS {
+            let order = match orders[market_i] {
+                Some(order) => order,
+                None => continue,
+            };
+
+            let m = array_ref![market_accs, PER_MARKET * market_i, PER_MARKET];
+            let [
+                spot_market_acc,
+                dex_request_queue_acc,
+                dex_event_queue_acc,
+                bids_acc,
+                asks_acc,
+                base_vault_acc,
+                dex_base_acc,
+                dex_quote_acc,
+                dex_signer_acc
+            ] = m;
+
+            check_eq_default!(&mango_group.spot_markets[market_i], spot_market_acc.key)?;
+            check_eq_default!(&mango_group.vaults[market_i], base_vault_acc.key)?;
+            check_reduce_only_order(
+                reduce_only, &mango_group, &margin_account, market_i, spot_market_acc, dex_prog_acc, &order
+            )?;
+
+            let open_orders_acc = &open_orders_accs[market_i];
+            let side = order.side;
+            let (in_token_i, out_token_i, vault_acc) = match side {
+                Side::Bid => (market_i, NUM_MARKETS, quote_vault_acc),
+                Side::Ask => (NUM_MARKETS, market_i, base_vault_acc)
+            };
+
+            let (pre_base, pre_quote) = {
+                (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
+                 Account::unpack(&quote_vault_acc.try_borrow_data()?)?.amount)
+            };
+
+            let data = serum_dex::instruction::MarketInstruction::NewOrderV3(order).pack();
+            let instruction = Instruction {
+                program_id: *dex_prog_acc.key,
+                data,
+                accounts: vec![
+                    AccountMeta::new(*spot_market_acc.key, false),
+                    AccountMeta::new(*open_orders_acc.key, false),
+                    AccountMeta::new(*dex_request_queue_acc.key, false),
+                    AccountMeta::new(*dex_event_queue_acc.key, false),
+                    AccountMeta::new(*bids_acc.key, false),
+                    AccountMeta::new(*asks_acc.key, false),
+                    AccountMeta::new(*vault_acc.key, false),
+                    AccountMeta::new_readonly(*signer_acc.key, true),
+                    AccountMeta::new(*dex_base_acc.key, false),
+                    AccountMeta::new(*dex_quote_acc.key, false),
+                    AccountMeta::new_readonly(*token_prog_acc.key, false),
+                    AccountMeta::new_readonly(*rent_acc.key, false),
+                    AccountMeta::new(*srm_vault_acc.key, false),
+                ],
+            };
+            let account_infos = [
+                dex_prog_acc.clone(),  // Have to add account of the program id
+                spot_market_acc.clone(),
+                open_orders_acc.clone(),
+                dex_request_queue_acc.clone(),
+                dex_event_queue_acc.clone(),
+                bids_acc.clone(),
+                asks_acc.clone(),
+                vault_acc.clone(),
+                signer_acc.clone(),
+                dex_base_acc.clone(),
+                dex_quote_acc.clone(),
+                token_prog_acc.clone(),
+                rent_acc.clone(),
+                srm_vault_acc.clone(),
+            ];
+            solana_program::program::invoke_signed(&instruction, &account_infos, &[&signer_seeds])?;
+
+            invoke_settle_funds(
+                dex_prog_acc,
+                spot_market_acc,
+                open_orders_acc,
+                signer_acc,
+                dex_base_acc,
+                dex_quote_acc,
+                base_vault_acc,
+                quote_vault_acc,
+                dex_signer_acc,
+                token_prog_acc,
+                &[&signer_seeds]
+            )?;
+
+            let (post_base, post_quote) = {
+                (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
+                 Account::unpack(&quote_vault_acc.try_borrow_data()?)?.amount)
+            };
+
+            let (pre_in, pre_out, post_in, post_out) = match side {
+                Side::Bid => (pre_base, pre_quote, post_base, post_quote),
+                Side::Ask => (pre_quote, pre_base, post_quote, post_base)
+            };
+
+            let out_index: MangoIndex = mango_group.indexes[out_token_i];
+            let in_index: MangoIndex = mango_group.indexes[in_token_i];
+
+            if post_out < pre_out {
+                let total_out = pre_out.checked_sub(post_out).unwrap();
+                let native_deposit = margin_account.get_native_deposit(&out_index, out_token_i);
+                if native_deposit < total_out {  // need to borrow
+                    let avail_deposit = margin_account.deposits[out_token_i];
+                    checked_sub_deposit(&mut mango_group, &mut margin_account, out_token_i, avail_deposit)?;
+                    let rem_spend = U64F64::from_num(total_out - native_deposit);
+
+                    check_default!(!reduce_only)?;  // Cannot borrow more in reduce only mode
+                    checked_add_borrow(&mut mango_group, &mut margin_account, out_token_i, rem_spend / out_index.borrow)?;
+                } else {  // just spend user deposits
+                    let mango_spent = U64F64::from_num(total_out) / out_index.deposit;
+                    checked_sub_deposit(&mut mango_group, &mut margin_account, out_token_i, mango_spent)?;
+                }
+            } else {  // Add out token deposit
+                let deposit = U64F64::from_num(post_out.checked_sub(pre_out).unwrap()) / out_index.deposit;
+                checked_add_deposit(&mut mango_group, &mut margin_account, out_token_i, deposit)?;
+            }
+
+            let total_in = U64F64::from_num(post_in.checked_sub(pre_in).unwrap()) / in_index.deposit;
+            checked_add_deposit(&mut mango_group, &mut margin_account, in_token_i, total_in)?;
+
+            settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, out_token_i)?;
+            settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, in_token_i)?;
+        }
+
+        let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
+        check!(reduce_only || coll_ratio >= mango_group.init_coll_ratio, MangoErrorCode::CollateralRatioLimit)?;
+        for token_i in 0..NUM_TOKENS {
+            check_default!(mango_group.has_valid_deposits_borrows(token_i))?;
+        }
+
+        Ok(())
+    }
+
+    /// Deposits `quantity` into whichever vault the order's side spends, then places and settles
+    /// the order, so a caller never ends up with funds deposited but no order placed (or vice
+    /// versa) if a later instruction in a multi-instruction transaction were to fail.
+    #[inline(never)]
+    fn deposit_and_place(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        quantity: u64,
+        order: serum_dex::instruction::NewOrderInstructionV3
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 20;
+        let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
+        let (
+            fixed_accs,
+            open_orders_accs,
+            oracle_accs,
+        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS];
+
+        let [
+            mango_group_acc,
+            owner_acc,
+            margin_account_acc,
+            clock_acc,
+            token_account_acc,
+            dex_prog_acc,
+            spot_market_acc,
+            dex_request_queue_acc,
+            dex_event_queue_acc,
+            bids_acc,
+            asks_acc,
+            base_vault_acc,
+            quote_vault_acc,
+            signer_acc,
+            dex_base_acc,
+            dex_quote_acc,
+            token_prog_acc,
+            rent_acc,
+            srm_vault_acc,
+            dex_signer_acc
+        ] = fixed_accs;
+
+        let mut mango_group = MangoGroup::load_mut_checked(mango_group_acc, program_id)?;
+        let mut margin_account = MarginAccount::load_mut_checked(
+            program_id, margin_account_acc, mango_group_acc.key
+        )?;
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+
+        check_default!(owner_acc.is_signer)?;
+        check_eq_default!(&margin_account.owner, owner_acc.key)?;
+        check_eq_default!(&mango_group.vaults[mango_group.get_market_index(spot_market_acc.key).unwrap()], base_vault_acc.key)?;
+        check_eq_default!(&mango_group.vaults[NUM_MARKETS], quote_vault_acc.key)?;
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+
+        // Deposit into whichever vault the order's side is going to spend
+        let deposit_vault_acc = match order.side {
+            Side::Bid => quote_vault_acc,
+            Side::Ask => base_vault_acc,
+        };
+        let deposit_token_index = mango_group.get_token_index_with_vault(deposit_vault_acc.key).unwrap();
+        let deposit_instruction = spl_token::instruction::transfer(
+            &spl_token::id(),
+            token_account_acc.key,
+            deposit_vault_acc.key,
+            &owner_acc.key, &[], quantity
+        )?;
+        let deposit_accs = [
+            token_account_acc.clone(),
+            deposit_vault_acc.clone(),
+            owner_acc.clone(),
+            token_prog_acc.clone()
+        ];
+        solana_program::program::invoke_signed(&deposit_instruction, &deposit_accs, &[])?;
+
+        let deposit: U64F64 = U64F64::from_num(quantity) / mango_group.indexes[deposit_token_index].deposit;
+        checked_add_deposit(&mut mango_group, &mut margin_account, deposit_token_index, deposit)?;
+        settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, deposit_token_index)?;
 
-        let prices = get_prices(&mango_group, oracle_accs)?;
+        let prices = get_prices(&mango_group, &clock, oracle_accs, None)?;
         let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
 
         if margin_account.being_liquidated {
@@ -937,17 +1892,15 @@ impl Processor {
 
         let reduce_only = coll_ratio < mango_group.init_coll_ratio;
 
-        check_default!(owner_acc.is_signer)?;
-        check_eq_default!(&margin_account.owner, owner_acc.key)?;
-
         let market_i = mango_group.get_market_index(spot_market_acc.key).unwrap();
+        check_reduce_only_order(
+            reduce_only, &mango_group, &margin_account, market_i, spot_market_acc, dex_prog_acc, &order
+        )?;
         let side = order.side;
         let (in_token_i, out_token_i, vault_acc) = match side {
             Side::Bid => (market_i, NUM_MARKETS, quote_vault_acc),
             Side::Ask => (NUM_MARKETS, market_i, base_vault_acc)
         };
-        check_eq_default!(&mango_group.vaults[market_i], base_vault_acc.key)?;
-        check_eq_default!(&mango_group.vaults[NUM_MARKETS], quote_vault_acc.key)?;
 
         let (pre_base, pre_quote) = {
             (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
@@ -974,7 +1927,6 @@ impl Processor {
             }
         }
 
-        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
         check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
         let data = serum_dex::instruction::MarketInstruction::NewOrderV3(order).pack();
         let instruction = Instruction {
@@ -1013,7 +1965,8 @@ impl Processor {
             srm_vault_acc.clone(),
         ];
 
-        let signer_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signer_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         solana_program::program::invoke_signed(&instruction, &account_infos, &[&signer_seeds])?;
 
         // Settle funds for this market
@@ -1041,12 +1994,9 @@ impl Processor {
             Side::Ask => (pre_quote, pre_base, post_quote, post_base)
         };
 
-        // It's possible the net change was positive for both tokens
-        // It's not possible for in_token to be negative
         let out_index: MangoIndex = mango_group.indexes[out_token_i];
         let in_index: MangoIndex = mango_group.indexes[in_token_i];
 
-        // if out token was net negative, then you may need to borrow more
         if post_out < pre_out {
             let total_out = pre_out.checked_sub(post_out).unwrap();
             let native_deposit = margin_account.get_native_deposit(&out_index, out_token_i);
@@ -1069,8 +2019,6 @@ impl Processor {
         let total_in = U64F64::from_num(post_in.checked_sub(pre_in).unwrap()) / in_index.deposit;
         checked_add_deposit(&mut mango_group, &mut margin_account, in_token_i, total_in)?;
 
-        // Settle borrow
-        // TODO only do ops on tokens that have borrows and deposits
         settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, out_token_i)?;
         settle_borrow_full_unchecked(&mut mango_group, &mut margin_account, in_token_i)?;
 
@@ -1090,12 +2038,13 @@ impl Processor {
         limit: u8
     ) -> MangoResult<()> {
         const NUM_FIXED: usize = 16;
-        let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
+        let accounts = array_ref![accounts, 0, NUM_FIXED + 3 * NUM_MARKETS];
         let (
             fixed_accs,
             open_orders_accs,
             oracle_accs,
-        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS];
+            oracle2_accs,
+        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS];
 
         let [
             mango_group_acc,
@@ -1141,7 +2090,7 @@ impl Processor {
 
         let clock = Clock::from_account_info(clock_acc)?;
         mango_group.update_indexes(&clock)?;
-        let prices = get_prices(&mango_group, oracle_accs)?;
+        let prices = get_prices(&mango_group, &clock, oracle_accs, Some(&oracle2_accs[..]))?;
         let coll_ratio = liqee_margin_account.get_collateral_ratio(
             &mango_group, &prices, open_orders_accs)?;
 
@@ -1157,7 +2106,8 @@ impl Processor {
             throw_err!(MangoErrorCode::NotLiquidatable)?;
         }
         let open_orders_acc = &open_orders_accs[market_i];
-        let signers_seeds = gen_signer_seeds(&mango_group.signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signers_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
 
         invoke_cancel_orders(open_orders_acc, dex_prog_acc, spot_market_acc, bids_acc, asks_acc, signer_acc,
                              dex_event_queue_acc, &[&signers_seeds], limit)?;
@@ -1191,6 +2141,365 @@ impl Processor {
 
         Ok(())
     }
+
+    /// Cancel and settle a liquidatee's open orders on every market that has any, in a single
+    /// instruction. Otherwise identical to calling `force_cancel_orders` once per market: the
+    /// same `invoke_cancel_orders` + `invoke_settle_funds` + `checked_add_deposit` accounting
+    /// runs per market, but `being_liquidated` and the collateral ratio are only re-evaluated
+    /// once, after every market has been cleared.
+    fn force_cancel_all_orders(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        limit: u8
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 8;
+        const PER_MARKET: usize = 8;
+        let accounts = array_ref![accounts, 0, NUM_FIXED + PER_MARKET * NUM_MARKETS + 3 * NUM_MARKETS];
+        let (
+            fixed_accs,
+            market_accs,
+            open_orders_accs,
+            oracle_accs,
+            oracle2_accs,
+        ) = array_refs![accounts, NUM_FIXED, PER_MARKET * NUM_MARKETS, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS];
+
+        let [
+            mango_group_acc,
+            liqor_acc,
+            liqee_margin_account_acc,
+            quote_vault_acc,
+            signer_acc,
+            token_prog_acc,
+            dex_prog_acc,
+            clock_acc
+        ] = fixed_accs;
+
+        check_eq!(token_prog_acc.key, &spl_token::id(), MangoErrorCode::InvalidProgramId)?;
+        check!(liqor_acc.is_signer, MangoErrorCode::SignerNecessary)?;
+        let mut mango_group = MangoGroup::load_mut_checked(
+            mango_group_acc, program_id
+        )?;
+        check_eq!(dex_prog_acc.key, &mango_group.dex_program_id, MangoErrorCode::InvalidProgramId)?;
+        check_eq!(signer_acc.key, &mango_group.signer_key, MangoErrorCode::InvalidSignerKey)?;
+        check_eq!(&mango_group.vaults[NUM_MARKETS], quote_vault_acc.key, MangoErrorCode::InvalidMangoVault)?;
+
+        let mut liqee_margin_account = MarginAccount::load_mut_checked(
+            program_id, liqee_margin_account_acc, mango_group_acc.key
+        )?;
+
+        for i in 0..NUM_MARKETS {
+            check_eq!(open_orders_accs[i].key, &liqee_margin_account.open_orders[i],
+                MangoErrorCode::InvalidOpenOrdersAccount)?;
+            check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
+        }
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+        let prices = get_prices(&mango_group, &clock, oracle_accs, Some(&oracle2_accs[..]))?;
+        let coll_ratio = liqee_margin_account.get_collateral_ratio(
+            &mango_group, &prices, open_orders_accs)?;
+
+        // Only allow liquidations on accounts already being liquidated and below init or accounts below maint
+        if liqee_margin_account.being_liquidated {
+            if coll_ratio >= mango_group.init_coll_ratio {
+                liqee_margin_account.being_liquidated = false;
+                return Ok(());
+            }
+        } else if coll_ratio < mango_group.maint_coll_ratio {
+            liqee_margin_account.being_liquidated = true;
+        } else {
+            throw_err!(MangoErrorCode::NotLiquidatable)?;
+        }
+
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signers_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+
+        for market_i in 0..NUM_MARKETS {
+            if liqee_margin_account.open_orders[market_i] == Pubkey::default() {
+                continue;
+            }
+
+            let m = array_ref![market_accs, PER_MARKET * market_i, PER_MARKET];
+            let [
+                base_vault_acc,
+                spot_market_acc,
+                bids_acc,
+                asks_acc,
+                dex_event_queue_acc,
+                dex_base_acc,
+                dex_quote_acc,
+                dex_signer_acc
+            ] = m;
+
+            check_eq!(&mango_group.vaults[market_i], base_vault_acc.key, MangoErrorCode::InvalidMangoVault)?;
+            check_eq_default!(spot_market_acc.key, &mango_group.spot_markets[market_i])?;
+
+            let open_orders_acc = &open_orders_accs[market_i];
+            invoke_cancel_orders(open_orders_acc, dex_prog_acc, spot_market_acc, bids_acc, asks_acc, signer_acc,
+                                 dex_event_queue_acc, &[&signers_seeds], limit)?;
+
+            let (pre_base, pre_quote) = {
+                let open_orders = load_open_orders(open_orders_acc)?;
+                (open_orders.native_coin_free, open_orders.native_pc_free + open_orders.referrer_rebates_accrued)
+            };
+
+            if pre_base == 0 && pre_quote == 0 {
+                continue;
+            }
+
+            invoke_settle_funds(dex_prog_acc, spot_market_acc, open_orders_acc, signer_acc, dex_base_acc,
+                                dex_quote_acc, base_vault_acc, quote_vault_acc, dex_signer_acc,
+                                token_prog_acc, &[&signers_seeds])?;
+
+            let (post_base, post_quote) = {
+                let open_orders = load_open_orders(open_orders_acc)?;
+                (open_orders.native_coin_free, open_orders.native_pc_free + open_orders.referrer_rebates_accrued)
+            };
+
+            check_default!(post_base <= pre_base)?;
+            check_default!(post_quote <= pre_quote)?;
+
+            let base_change = U64F64::from_num(pre_base - post_base) / mango_group.indexes[market_i].deposit;
+            let quote_change = U64F64::from_num(pre_quote - post_quote) / mango_group.indexes[NUM_MARKETS].deposit;
+
+            checked_add_deposit(&mut mango_group, &mut liqee_margin_account, market_i, base_change)?;
+            checked_add_deposit(&mut mango_group, &mut liqee_margin_account, NUM_MARKETS, quote_change)?;
+        }
+
+        Ok(())
+    }
+
+    /// Unwind a liquidatee's spot positions directly against the dex, for the case the `Liquidate`
+    /// handler's doc comment calls out but never implemented: no liquidator wants to take the other
+    /// side of the position. For every market where the liqee has a nonzero net base position,
+    /// places a single IOC order sized to flatten it, priced `FORCE_LIQUIDATE_SLIPPAGE_BPS` through
+    /// the oracle price so it's guaranteed to cross the book, then settles the fill into the
+    /// base/quote vaults exactly like `force_cancel_all_orders` does for cancelled orders. After
+    /// each market's fill, that market's base and quote borrows are settled against whatever
+    /// deposits the fill freed up. `being_liquidated` gates the instruction the same way `force_cancel_all_orders`
+    /// does, so a position spanning multiple markets can be unwound market by market over multiple
+    /// transactions, and `limit` bounds the matching work the dex does per order to stay within the
+    /// compute budget.
+    #[inline(never)]
+    fn force_liquidate_on_dex(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        limit: u16
+    ) -> MangoResult<()> {
+        const NUM_FIXED: usize = 10;
+        const PER_MARKET: usize = 9;
+        let accounts = array_ref![accounts, 0, NUM_FIXED + PER_MARKET * NUM_MARKETS + 3 * NUM_MARKETS];
+        let (
+            fixed_accs,
+            market_accs,
+            open_orders_accs,
+            oracle_accs,
+            oracle2_accs,
+        ) = array_refs![accounts, NUM_FIXED, PER_MARKET * NUM_MARKETS, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS];
+
+        let [
+            mango_group_acc,
+            liqor_acc,
+            liqee_margin_account_acc,
+            clock_acc,
+            dex_prog_acc,
+            quote_vault_acc,
+            signer_acc,
+            token_prog_acc,
+            rent_acc,
+            srm_vault_acc
+        ] = fixed_accs;
+
+        check_eq_default!(token_prog_acc.key, &spl_token::id())?;
+        check!(liqor_acc.is_signer, MangoErrorCode::SignerNecessary)?;
+        let mut mango_group = MangoGroup::load_mut_checked(
+            mango_group_acc, program_id
+        )?;
+        check_eq_default!(dex_prog_acc.key, &mango_group.dex_program_id)?;
+        check_eq_default!(&mango_group.vaults[NUM_MARKETS], quote_vault_acc.key)?;
+        check_eq_default!(srm_vault_acc.key, &mango_group.srm_vault)?;
+
+        let mut liqee_margin_account = MarginAccount::load_mut_checked(
+            program_id, liqee_margin_account_acc, mango_group_acc.key
+        )?;
+
+        for i in 0..NUM_MARKETS {
+            check_eq!(open_orders_accs[i].key, &liqee_margin_account.open_orders[i],
+                MangoErrorCode::InvalidOpenOrdersAccount)?;
+            check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
+        }
+
+        let clock = Clock::from_account_info(clock_acc)?;
+        mango_group.update_indexes(&clock)?;
+        let prices = get_prices(&mango_group, &clock, oracle_accs, Some(&oracle2_accs[..]))?;
+        let coll_ratio = liqee_margin_account.get_collateral_ratio(
+            &mango_group, &prices, open_orders_accs)?;
+
+        // Only allow liquidations on accounts already being liquidated and below init or accounts below maint
+        if liqee_margin_account.being_liquidated {
+            if coll_ratio >= mango_group.init_coll_ratio {
+                liqee_margin_account.being_liquidated = false;
+                return Ok(());
+            }
+        } else if coll_ratio < mango_group.maint_coll_ratio {
+            liqee_margin_account.being_liquidated = true;
+        } else {
+            throw_err!(MangoErrorCode::NotLiquidatable)?;
+        }
+
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signers_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
+        let slippage = U64F64::from_num(FORCE_LIQUIDATE_SLIPPAGE_BPS) / U64F64::from_num(10_000u64);
+
+        for market_i in 0..NUM_MARKETS {
+            if liqee_margin_account.open_orders[market_i] == Pubkey::default() {
+                continue;
+            }
+
+            let m = array_ref![market_accs, PER_MARKET * market_i, PER_MARKET];
+            let [
+                spot_market_acc,
+                dex_request_queue_acc,
+                dex_event_queue_acc,
+                bids_acc,
+                asks_acc,
+                base_vault_acc,
+                dex_base_acc,
+                dex_quote_acc,
+                dex_signer_acc
+            ] = m;
+
+            check_eq_default!(&mango_group.vaults[market_i], base_vault_acc.key)?;
+            check_eq_default!(spot_market_acc.key, &mango_group.spot_markets[market_i])?;
+
+            let index = mango_group.indexes[market_i];
+            let native_deposit = liqee_margin_account.get_native_deposit(&index, market_i);
+            let native_borrow = liqee_margin_account.get_native_borrow(&index, market_i);
+            let (side, net_size) = if native_deposit > native_borrow {
+                (Side::Ask, native_deposit - native_borrow)
+            } else if native_borrow > native_deposit {
+                (Side::Bid, native_borrow - native_deposit)
+            } else {
+                continue;
+            };
+
+            let (spot_market, _) = load_market_state(spot_market_acc, dex_prog_acc.key)?;
+            let coin_lot_size = spot_market.coin_lot_size;
+            let pc_lot_size = spot_market.pc_lot_size;
+            let max_coin_qty = net_size / coin_lot_size;
+            if max_coin_qty == 0 {
+                continue;
+            }
+
+            // Price the IOC order through the book by `slippage` so the fill is guaranteed --
+            // the liqee isn't around to set a limit price the way `place_order`'s caller would.
+            let oracle_price = prices[market_i];
+            let order_price = match side {
+                Side::Ask => oracle_price - oracle_price * slippage,
+                Side::Bid => oracle_price + oracle_price * slippage,
+            };
+            let limit_price = (order_price * U64F64::from_num(coin_lot_size) / U64F64::from_num(pc_lot_size))
+                .to_num::<u64>();
+            if limit_price == 0 {
+                continue;
+            }
+            let max_native_pc_qty = limit_price.checked_mul(max_coin_qty).unwrap()
+                .checked_mul(pc_lot_size).unwrap();
+
+            let order = serum_dex::instruction::NewOrderInstructionV3 {
+                side,
+                limit_price: std::num::NonZeroU64::new(limit_price).unwrap(),
+                max_coin_qty: std::num::NonZeroU64::new(max_coin_qty).unwrap(),
+                max_native_pc_qty_including_fees: std::num::NonZeroU64::new(max_native_pc_qty).unwrap(),
+                self_trade_behavior: serum_dex::instruction::SelfTradeBehavior::DecrementTake,
+                order_type: serum_dex::matching::OrderType::ImmediateOrCancel,
+                client_order_id: 0,
+                limit,
+            };
+
+            let (in_token_i, out_token_i, vault_acc) = match side {
+                Side::Bid => (market_i, NUM_MARKETS, quote_vault_acc),
+                Side::Ask => (NUM_MARKETS, market_i, base_vault_acc)
+            };
+
+            let (pre_base, pre_quote) = {
+                (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
+                 Account::unpack(&quote_vault_acc.try_borrow_data()?)?.amount)
+            };
+
+            let data = serum_dex::instruction::MarketInstruction::NewOrderV3(order).pack();
+            let open_orders_acc = &open_orders_accs[market_i];
+            let instruction = Instruction {
+                program_id: *dex_prog_acc.key,
+                data,
+                accounts: vec![
+                    AccountMeta::new(*spot_market_acc.key, false),
+                    AccountMeta::new(*open_orders_acc.key, false),
+                    AccountMeta::new(*dex_request_queue_acc.key, false),
+                    AccountMeta::new(*dex_event_queue_acc.key, false),
+                    AccountMeta::new(*bids_acc.key, false),
+                    AccountMeta::new(*asks_acc.key, false),
+                    AccountMeta::new(*vault_acc.key, false),
+                    AccountMeta::new_readonly(*signer_acc.key, true),
+                    AccountMeta::new(*dex_base_acc.key, false),
+                    AccountMeta::new(*dex_quote_acc.key, false),
+                    AccountMeta::new_readonly(*token_prog_acc.key, false),
+                    AccountMeta::new_readonly(*rent_acc.key, false),
+                    AccountMeta::new(*srm_vault_acc.key, false),
+                ],
+            };
+            let account_infos = [
+                dex_prog_acc.clone(),
+                spot_market_acc.clone(),
+                open_orders_acc.clone(),
+                dex_request_queue_acc.clone(),
+                dex_event_queue_acc.clone(),
+                bids_acc.clone(),
+                asks_acc.clone(),
+                vault_acc.clone(),
+                signer_acc.clone(),
+                dex_base_acc.clone(),
+                dex_quote_acc.clone(),
+                token_prog_acc.clone(),
+                rent_acc.clone(),
+                srm_vault_acc.clone(),
+            ];
+            solana_program::program::invoke_signed(&instruction, &account_infos, &[&signers_seeds])?;
+
+            invoke_settle_funds(dex_prog_acc, spot_market_acc, open_orders_acc, signer_acc, dex_base_acc,
+                                dex_quote_acc, base_vault_acc, quote_vault_acc, dex_signer_acc,
+                                token_prog_acc, &[&signers_seeds])?;
+
+            let (post_base, post_quote) = {
+                (Account::unpack(&base_vault_acc.try_borrow_data()?)?.amount,
+                 Account::unpack(&quote_vault_acc.try_borrow_data()?)?.amount)
+            };
+
+            let (pre_in, pre_out, post_in, post_out) = match side {
+                Side::Bid => (pre_base, pre_quote, post_base, post_quote),
+                Side::Ask => (pre_quote, pre_base, post_quote, post_base)
+            };
+
+            // The unwind is always sized to the liqee's existing net position, so it can only
+            // ever free up deposits, never require borrowing more -- unlike `place_and_settle`,
+            // there is no reduce_only escape hatch here, the account is already in liquidation.
+            check_default!(post_out <= pre_out)?;
+            let out_index: MangoIndex = mango_group.indexes[out_token_i];
+            let in_index: MangoIndex = mango_group.indexes[in_token_i];
+
+            let spent = U64F64::from_num(pre_out - post_out) / out_index.deposit;
+            checked_sub_deposit(&mut mango_group, &mut liqee_margin_account, out_token_i, spent)?;
+
+            let received = U64F64::from_num(post_in.checked_sub(pre_in).unwrap()) / in_index.deposit;
+            checked_add_deposit(&mut mango_group, &mut liqee_margin_account, in_token_i, received)?;
+
+            settle_borrow_full_unchecked(&mut mango_group, &mut liqee_margin_account, out_token_i)?;
+            settle_borrow_full_unchecked(&mut mango_group, &mut liqee_margin_account, in_token_i)?;
+        }
+
+        Ok(())
+    }
+
     #[inline(never)]
     fn partial_liquidate(
         program_id: &Pubkey,
@@ -1200,12 +2509,13 @@ impl Processor {
 
         const NUM_FIXED: usize = 10;
         // TODO make it so canceling orders feature is optional if no orders outstanding to cancel
-        let accounts = array_ref![accounts, 0, NUM_FIXED + 2 * NUM_MARKETS];
+        let accounts = array_ref![accounts, 0, NUM_FIXED + 3 * NUM_MARKETS];
         let (
             fixed_accs,
             open_orders_accs,
             oracle_accs,
-        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS];
+            oracle2_accs,
+        ) = array_refs![accounts, NUM_FIXED, NUM_MARKETS, NUM_MARKETS, NUM_MARKETS];
 
         let [
             mango_group_acc,
@@ -1217,7 +2527,7 @@ impl Processor {
             out_vault_acc,
             signer_acc,
             token_prog_acc,
-            _clock_acc,
+            clock_acc,
         ] = fixed_accs;
         check!(token_prog_acc.key == &spl_token::ID, MangoErrorCode::InvalidProgramId)?;
         check!(liqor_acc.is_signer, MangoErrorCode::SignerNecessary)?;
@@ -1245,26 +2555,18 @@ impl Processor {
             check_open_orders(&open_orders_accs[i], &mango_group.signer_key)?;
         }
 
-        // TODO - add a check to make sure indexes were updated in last hour
-        //      if not updated, then update indexes and return without continuing
-        //      there is not enough compute to continue
-        //      code is written below but needs to be tested on devnet first
-
-        // let clock = Clock::from_account_info(clock_acc)?;
-        // let now_ts = clock.unix_timestamp as u64;
-        // for i in 0..NUM_TOKENS {
-        //     if now_ts > mango_group.indexes[i].last_update + 3600 {
-        //         msg!("Invalid indexes");
-        //         mango_group.update_indexes(&clock)?;
-        //         return Ok(());
-        //     }
-        // }
-
-        let prices = get_prices(&mango_group, oracle_accs)?;
+        let clock = Clock::from_account_info(clock_acc)?;
+        if !require_fresh_indexes(&mango_group, &clock, mango_group.max_index_staleness) {
+            msg!("MangoErrorCode::StaleIndexes Indexes are stale; refreshing and returning for retry");
+            mango_group.update_indexes(&clock)?;
+            return Ok(());
+        }
+
+        let prices = get_prices(&mango_group, &clock, oracle_accs, Some(&oracle2_accs[..]))?;
         let start_assets = liqee_margin_account.get_assets(&mango_group, open_orders_accs)?;
         let start_liabs = liqee_margin_account.get_liabs(&mango_group)?;
         let coll_ratio = liqee_margin_account.coll_ratio_from_assets_liabs(
-            &prices, &start_assets, &start_liabs)?;
+            &mango_group, &prices, &start_assets, &start_liabs)?;
 
         // Only allow liquidations on accounts already being liquidated and below init or accounts below maint
         if liqee_margin_account.being_liquidated {
@@ -1304,8 +2606,8 @@ impl Processor {
             &mut mango_group, &mut liqee_margin_account, open_orders_accs, &prices, in_token_index,
             out_token_index, max_deposit
         )?;
-        let signer_nonce = mango_group.signer_nonce;
-        let signers_seeds = gen_signer_seeds(&signer_nonce, mango_group_acc.key);
+        let signer_nonce_seed = mango_group.signer_nonce_seed();
+        let signers_seeds = [mango_group_acc.key.as_ref(), signer_nonce_seed.as_slice()];
         invoke_transfer(token_prog_acc, liqor_in_token_acc, in_vault_acc, liqor_acc,
                         &[&signers_seeds], in_quantity)?;
         invoke_transfer(token_prog_acc, out_vault_acc, liqor_out_token_acc, signer_acc,
@@ -1315,7 +2617,7 @@ impl Processor {
         let end_assets = liqee_margin_account.get_assets(&mango_group, open_orders_accs)?;
         let end_liabs = liqee_margin_account.get_liabs(&mango_group)?;
         let coll_ratio = liqee_margin_account.coll_ratio_from_assets_liabs(
-            &prices, &end_assets, &end_liabs)?;
+            &mango_group, &prices, &end_assets, &end_liabs)?;
         let mut total_deposits = [ZERO_U64F64; NUM_TOKENS];
 
         let mut socialized_losses = false;
@@ -1326,7 +2628,10 @@ impl Processor {
             // if all asset vals is dust (less than 1 cent?) socialize loss on lenders
             let assets_val = liqee_margin_account.get_assets_val(&mango_group, &prices, open_orders_accs)?;
 
-            if assets_val < DUST_THRESHOLD {
+            // Below LIQ_MIN_COLL_RATIO there isn't enough collateral left for further partial
+            // liquidations to make progress, so socialize the full shortfall now instead of
+            // leaving the account to be picked at call by call.
+            if assets_val < mango_group.liquidation_params.dust_threshold || coll_ratio < LIQ_MIN_COLL_RATIO {
                 for i in 0..NUM_TOKENS {
                     let native_borrow: U64F64 = end_liabs[i];
                     let total_deposits_native: U64F64 = mango_group.total_deposits[i] * mango_group.indexes[i].deposit;
@@ -1347,8 +2652,20 @@ impl Processor {
             }
         }
 
-        // Note total_deposits is only logged with reasonable values if assets_val < DUST_THRESHOLD
-        log_liquidation_details(&start_assets, &start_liabs, &end_assets, &end_liabs, &prices, socialized_losses, &total_deposits);
+        // Note total_deposits is only meaningful if socialized_losses is true
+        LiquidationEvent {
+            liqee: *liqee_margin_account_acc.key,
+            liqor: *liqor_acc.key,
+            slot: clock.slot,
+            start_assets,
+            start_liabs,
+            end_assets,
+            end_liabs,
+            prices,
+            total_deposits,
+            socialized_losses: socialized_losses as u8,
+            padding: [0u8; 7],
+        }.emit();
         // TODO do I need to check total deposits and total borrows?
         // TODO log deposit indexes before and after liquidation as a way to measure socialize of losses
         Ok(())
@@ -1383,10 +2700,10 @@ impl Processor {
         let instruction = MangoInstruction::unpack(data).ok_or(ProgramError::InvalidInstructionData)?;
         match instruction {
             MangoInstruction::InitMangoGroup {
-                signer_nonce, maint_coll_ratio, init_coll_ratio, borrow_limits
+                signer_nonce, maint_coll_ratio, init_coll_ratio, borrow_limits, borrow_fee_params
             } => {
                 msg!("Mango: InitMangoGroup");
-                Self::init_mango_group(program_id, accounts, signer_nonce, maint_coll_ratio, init_coll_ratio, borrow_limits)?;
+                Self::init_mango_group(program_id, accounts, signer_nonce, maint_coll_ratio, init_coll_ratio, borrow_limits, borrow_fee_params)?;
             }
             MangoInstruction::InitMarginAccount => {
                 msg!("Mango: InitMarginAccount");
@@ -1418,11 +2735,16 @@ impl Processor {
                 msg!("Mango: SettleBorrow");
                 Self::settle_borrow(program_id, accounts, token_index, quantity)?;
             }
+            MangoInstruction::SettleBorrowAll => {
+                msg!("Mango: SettleBorrowAll");
+                Self::settle_borrow_all(program_id, accounts)?;
+            }
             MangoInstruction::Liquidate {
                 deposit_quantities
             } => {
                 // Either user takes the position
-                // Or the program can liquidate on the serum dex (in case no liquidator wants to take pos)
+                // Or the program can liquidate on the serum dex (in case no liquidator wants to take
+                // pos) -- see `ForceLiquidateOnDex`/`force_liquidate_on_dex`
                 msg!("Mango: Liquidate");
                 Self::liquidate(program_id, accounts, deposit_quantities)?;
             }
@@ -1439,10 +2761,11 @@ impl Processor {
                 Self::withdraw_srm(program_id, accounts, quantity)?;
             }
             MangoInstruction::PlaceOrder {
-                order
+                order,
+                reduce_only
             } => {
                 msg!("Mango: PlaceOrder");
-                Self::place_order(program_id, accounts, order)?;
+                Self::place_order(program_id, accounts, order, reduce_only)?;
             }
             MangoInstruction::SettleFunds => {
                 msg!("Mango: SettleFunds");
@@ -1461,6 +2784,12 @@ impl Processor {
                 msg!("Mango: CancelOrderByClientId");
                 Self::cancel_order(program_id, accounts, client_id.to_le_bytes().to_vec())?;
             }
+            MangoInstruction::CancelAllOrders {
+                limit
+            } => {
+                msg!("Mango: CancelAllOrders");
+                Self::cancel_all_orders(program_id, accounts, limit)?;
+            }
 
             MangoInstruction::ChangeBorrowLimit {
                 token_index, borrow_limit
@@ -1468,11 +2797,18 @@ impl Processor {
                 msg!("Mango: ChangeBorrowLimit");
                 Self::change_borrow_limit(program_id, accounts, token_index, borrow_limit)?;
             }
+            MangoInstruction::ChangeInterestParams {
+                token_index, interest_rate_params
+            } => {
+                msg!("Mango: ChangeInterestParams");
+                Self::change_interest_params(program_id, accounts, token_index, interest_rate_params)?;
+            }
             MangoInstruction::PlaceAndSettle {
-                order
+                order,
+                reduce_only
             } => {
                 msg!("Mango: PlaceAndSettle");
-                Self::place_and_settle(program_id, accounts, order)?;
+                Self::place_and_settle(program_id, accounts, order, reduce_only)?;
             }
             MangoInstruction::ForceCancelOrders {
                 limit
@@ -1492,44 +2828,72 @@ impl Processor {
                 msg!("Mango: AddMarginAccountInfo");
                 Self::add_margin_account_info(program_id, accounts, info)?;
             }
+            MangoInstruction::DepositAndPlace {
+                quantity,
+                order
+            } => {
+                msg!("Mango: DepositAndPlace");
+                Self::deposit_and_place(program_id, accounts, quantity, order)?;
+            }
+            MangoInstruction::DepositMsrm {
+                quantity
+            } => {
+                msg!("Mango: DepositMsrm");
+                Self::deposit_msrm(program_id, accounts, quantity)?;
+            }
+            MangoInstruction::WithdrawMsrm {
+                quantity
+            } => {
+                msg!("Mango: WithdrawMsrm");
+                Self::withdraw_msrm(program_id, accounts, quantity)?;
+            }
+            MangoInstruction::PlaceAndSettleMulti {
+                orders
+            } => {
+                msg!("Mango: PlaceAndSettleMulti");
+                Self::place_and_settle_multi(program_id, accounts, orders)?;
+            }
+            MangoInstruction::ForceCancelAllOrders {
+                limit
+            } => {
+                msg!("Mango: ForceCancelAllOrders");
+                Self::force_cancel_all_orders(program_id, accounts, limit)?;
+            }
+            MangoInstruction::ChangeLiquidationParams {
+                liquidation_params
+            } => {
+                msg!("Mango: ChangeLiquidationParams");
+                Self::change_liquidation_params(program_id, accounts, liquidation_params)?;
+            }
+            MangoInstruction::ForceLiquidateOnDex {
+                limit
+            } => {
+                msg!("Mango: ForceLiquidateOnDex");
+                Self::force_liquidate_on_dex(program_id, accounts, limit)?;
+            }
+            MangoInstruction::ChangeCollateralWeights {
+                token_index,
+                asset_weight,
+                liab_weight,
+            } => {
+                msg!("Mango: ChangeCollateralWeights");
+                Self::change_collateral_weights(program_id, accounts, token_index, asset_weight, liab_weight)?;
+            }
+            MangoInstruction::Migrate => {
+                msg!("Mango: Migrate");
+                Self::migrate(program_id, accounts)?;
+            }
+            MangoInstruction::SetOracle2 {
+                token_index
+            } => {
+                msg!("Mango: SetOracle2");
+                Self::set_oracle2(program_id, accounts, token_index)?;
+            }
         }
         Ok(())
     }
 }
 
-fn log_liquidation_details(
-    start_assets: &[U64F64; NUM_TOKENS],
-    start_liabs: &[U64F64; NUM_TOKENS],
-    end_assets: &[U64F64; NUM_TOKENS],
-    end_liabs: &[U64F64; NUM_TOKENS],
-    prices: &[U64F64; NUM_TOKENS],
-    socialized_losses: bool,
-    total_deposits: &[U64F64; NUM_TOKENS]
-) {
-    let mut prices_f64 = [0_f64; NUM_TOKENS];
-    let mut start_assets_u64 = [0u64; NUM_TOKENS];
-    let mut start_liabs_u64 = [0u64; NUM_TOKENS];
-    let mut end_assets_u64 = [0u64; NUM_TOKENS];
-    let mut end_liabs_u64 = [0u64; NUM_TOKENS];
-    let mut total_deposits_u64 = [0u64; NUM_TOKENS];
-    for i in 0..NUM_TOKENS {
-        prices_f64[i] = prices[i].to_num::<f64>();
-        start_assets_u64[i] = start_assets[i].to_num();
-        start_liabs_u64[i] = start_liabs[i].to_num();
-        end_assets_u64[i] = end_assets[i].to_num();
-        end_liabs_u64[i] = end_liabs[i].to_num();
-        total_deposits_u64[i] = total_deposits[i].to_num();
-    }
-
-    msg!("liquidation details: {{ \
-                \"start\": {{ \"assets\": {:?}, \"liabs\": {:?} }}, \
-                \"end\": {{ \"assets\": {:?}, \"liabs\": {:?} }}, \
-                \"prices\": {:?}, \
-                \"socialized_losses\": {}, \
-                \"total_deposits\": {:?} \
-            }}", start_assets_u64, start_liabs_u64, end_assets_u64, end_liabs_u64, prices_f64, socialized_losses, total_deposits_u64);
-}
-
 fn settle_borrow_unchecked(
     mango_group: &mut MangoGroup,
     margin_account: &mut MarginAccount,
@@ -1596,7 +2960,12 @@ fn socialize_loss(
     let quantity: U64F64 = reduce_quantity_native / mango_group.indexes[token_index].borrow;
     checked_sub_borrow(mango_group, margin_account, token_index, quantity)?;
 
-    let percentage_loss = reduce_quantity_native.checked_div(total_deposits_native).unwrap();
+    let max_loss_fraction = U64F64::from_num(mango_group.liquidation_params.max_socialized_loss_bps)
+        / U64F64::from_num(10_000u16);
+    let percentage_loss = min(
+        reduce_quantity_native.checked_div(total_deposits_native).unwrap(),
+        max_loss_fraction
+    );
     let index: &mut MangoIndex = &mut mango_group.indexes[token_index];
     index.deposit = index.deposit
         .checked_sub(percentage_loss.checked_mul(index.deposit).unwrap()).unwrap();
@@ -1660,13 +3029,83 @@ fn checked_add_borrow(
     Ok(())
 }
 
+fn check_borrow_limit(mango_group: &MangoGroup, token_index: usize) -> MangoResult<()> {
+    check!(
+        mango_group.get_total_native_borrow(token_index) <= mango_group.borrow_limits[token_index],
+        MangoErrorCode::BorrowLimitExceeded
+    )
+}
+
+/// While `reduce_only` (the account is below `init_coll_ratio`), only forward orders that shrink
+/// the account's existing net position in `market_i`, sized no larger than that position. Mango
+/// never carries a nonzero deposit and a nonzero borrow of the same token at once, so the net
+/// position's sign gives the one order side that's still allowed.
+fn check_reduce_only_order(
+    reduce_only: bool,
+    mango_group: &MangoGroup,
+    margin_account: &MarginAccount,
+    market_i: usize,
+    spot_market_acc: &AccountInfo,
+    dex_prog_acc: &AccountInfo,
+    order: &serum_dex::instruction::NewOrderInstructionV3,
+) -> MangoResult<()> {
+    if !reduce_only {
+        return Ok(());
+    }
+
+    let index = &mango_group.indexes[market_i];
+    let native_deposit = margin_account.get_native_deposit(index, market_i);
+    let native_borrow = margin_account.get_native_borrow(index, market_i);
+
+    // Closing side is the opposite of however the account is currently positioned; if flat,
+    // neither side closes anything so any nonzero order is rejected below.
+    let (closing_side, net_size) = if native_deposit > native_borrow {
+        (Side::Ask, native_deposit - native_borrow)
+    } else {
+        (Side::Bid, native_borrow - native_deposit)
+    };
+    check!(order.side == closing_side, MangoErrorCode::ReduceOnlyViolated)?;
+
+    let (spot_market, _) = load_market_state(spot_market_acc, dex_prog_acc.key)?;
+    let order_size = order.max_coin_qty.get().checked_mul(spot_market.coin_lot_size).unwrap();
+    check!(order_size <= net_size, MangoErrorCode::ReduceOnlyViolated)
+}
+
+/// True if every token's interest index was updated within `max_age_secs` of `clock`. Liquidation
+/// math reads `mango_group.indexes` directly to price collateral and debt, so a caller should
+/// check this before acting rather than liquidating against indexes that predate recent interest
+/// accrual or price movement.
+fn require_fresh_indexes(mango_group: &MangoGroup, clock: &Clock, max_age_secs: u64) -> bool {
+    let now_ts = clock.unix_timestamp as u64;
+    mango_group.indexes.iter().all(|index| now_ts <= index.last_update + max_age_secs)
+}
+
+/// Reads each market's price off its primary oracle. `oracle2_accs`, when `Some`, is checked
+/// against `mango_group.oracles2` for every market that has a secondary oracle configured: the
+/// secondary's median must agree with the primary's within `max_oracle_spread_bps`, or this
+/// throws `StaleOrUnreliableOracle` rather than pricing off a single feed. Liquidation-sensitive
+/// callers (`partial_liquidate`, `force_cancel_orders`, `force_cancel_all_orders`,
+/// `force_liquidate_on_dex`) pass `Some`; every other caller passes `None` and relies on the
+/// primary oracle alone, same as before.
+///
+/// Deliberately deferred: a dispersion/confidence check on a single aggregator's own submissions
+/// (as opposed to cross-checking two independent feeds). `flux_aggregator::read_median`'s `Answer`
+/// only surfaces the computed median and its update timestamp, not a spread across the
+/// aggregator's underlying oracle submissions, so there's nothing here to threshold against
+/// without also changing what `read_median` returns upstream. The round-age check
+/// (`now_ts <= answer.updated_at + max_index_staleness`, just below) already covers single-feed
+/// staleness; a same-round dispersion check would need to land alongside a `read_median` change,
+/// not here.
 pub fn get_prices(
     mango_group: &MangoGroup,
-    oracle_accs: &[AccountInfo]
+    clock: &Clock,
+    oracle_accs: &[AccountInfo],
+    oracle2_accs: Option<&[AccountInfo]>
 ) -> MangoResult<[U64F64; NUM_TOKENS]> {
     let mut prices = [ZERO_U64F64; NUM_TOKENS];
     prices[NUM_MARKETS] = ONE_U64F64;  // quote currency is 1
     let quote_decimals: u8 = mango_group.mint_decimals[NUM_MARKETS];
+    let now_ts = clock.unix_timestamp as u64;
 
     for i in 0..NUM_MARKETS {
         check_eq_default!(&mango_group.oracles[i], oracle_accs[i].key)?;
@@ -1677,9 +3116,32 @@ pub fn get_prices(
         );
 
         let answer = flux_aggregator::read_median(&oracle_accs[i])?; // this is in USD cents
+        check!(
+            now_ts <= answer.updated_at + mango_group.max_index_staleness,
+            MangoErrorCode::StaleOracle
+        )?;
 
         let value = U64F64::from_num(answer.median);
 
+        if let Some(oracle2_accs) = oracle2_accs {
+            let oracle2_pk = mango_group.oracles2[i];
+            if oracle2_pk != Pubkey::default() {
+                check_eq_default!(&oracle2_pk, oracle2_accs[i].key)?;
+
+                let answer2 = flux_aggregator::read_median(&oracle2_accs[i])?;
+                check!(
+                    now_ts <= answer2.updated_at + mango_group.max_index_staleness,
+                    MangoErrorCode::StaleOracle
+                )?;
+
+                let value2 = U64F64::from_num(answer2.median);
+                let diff = if value2 > value { value2 - value } else { value - value2 };
+                let max_diff = value * U64F64::from_num(mango_group.max_oracle_spread_bps)
+                    / U64F64::from_num(10_000u16);
+                check!(diff <= max_diff, MangoErrorCode::StaleOrUnreliableOracle)?;
+            }
+        }
+
         let base_adj = U64F64::from_num(10u64.pow(mango_group.mint_decimals[i] as u32));
         prices[i] = quote_adj
             .checked_div(base_adj).unwrap()
@@ -1784,11 +3246,15 @@ fn invoke_cancel_orders<'a>(
 
     mut limit: u8
 ) -> MangoResult<()> {
-    let mut cancels = vec![];
+    // Orders placed with a client id batch into `CancelOrdersByClientIds`, up to
+    // MAX_CANCEL_ORDERS_BY_CLIENT_IDS per CPI; orders without one (client_order_id == 0) fall
+    // back to a CancelOrderV2 per order, same as before this split.
+    let mut by_client_id = vec![];
+    let mut by_order_id = vec![];
     {
         let open_orders = load_open_orders(open_orders_acc)?;
 
-        let market = load_market_state(spot_market_acc, dex_prog_acc.key)?;
+        let (market, _) = load_market_state(spot_market_acc, dex_prog_acc.key)?;
         let bids = load_bids_mut(&market, bids_acc)?;
         let asks = load_asks_mut(&market, asks_acc)?;
 
@@ -1815,9 +3281,12 @@ fn invoke_cancel_orders<'a>(
                 }
             };
 
-            let cancel_instruction = serum_dex::instruction::CancelOrderInstructionV2 { side, order_id };
-
-            cancels.push(cancel_instruction);
+            let client_order_id = open_orders.client_order_ids[j];
+            if client_order_id != 0 {
+                by_client_id.push(client_order_id);
+            } else {
+                by_order_id.push(serum_dex::instruction::CancelOrderInstructionV2 { side, order_id });
+            }
 
             limit -= 1;
             if limit == 0 {
@@ -1849,7 +3318,15 @@ fn invoke_cancel_orders<'a>(
         dex_event_queue_acc.clone()
     ];
 
-    for cancel in cancels.iter() {
+    for chunk in by_client_id.chunks(MAX_CANCEL_ORDERS_BY_CLIENT_IDS) {
+        let mut client_order_ids = [0u64; MAX_CANCEL_ORDERS_BY_CLIENT_IDS];
+        client_order_ids[..chunk.len()].copy_from_slice(chunk);
+        let cancel_instruction = serum_dex::instruction::MarketInstruction::CancelOrdersByClientIds(client_order_ids);
+        instruction.data = cancel_instruction.pack();
+        solana_program::program::invoke_signed(&instruction, &account_infos, signers_seeds)?;
+    }
+
+    for cancel in by_order_id.iter() {
         let cancel_instruction = serum_dex::instruction::MarketInstruction::CancelOrderV2(cancel.clone());
         instruction.data = cancel_instruction.pack();
         solana_program::program::invoke_signed(&instruction, &account_infos, signers_seeds)?;
@@ -1894,14 +3371,20 @@ fn get_in_out_quantities(
     out_token_index: usize,
     liqor_max_in: u64
 ) -> MangoResult<(u64, u64)> {
+    let coll_ratio = margin_account.get_collateral_ratio(&mango_group, &prices, open_orders_accs)?;
+    let liquidation_fee = mango_group.liquidation_params.scaled_liquidation_fee_multiplier(
+        coll_ratio, mango_group.maint_coll_ratio, mango_group.init_coll_ratio);
+    // Already capped by LiquidationParams::close_factor, so a single call can't seize more than
+    // that fraction of the account's full init_coll_ratio deficit.
     let deficit_val = margin_account.get_partial_liq_deficit(&mango_group, &prices, open_orders_accs)? + ONE_U64F64;
     let out_avail: U64F64 = margin_account.deposits[out_token_index].checked_mul(mango_group.indexes[out_token_index].deposit).unwrap();
     let out_avail_val = out_avail * prices[out_token_index];
 
-    // liq incentive is max of 1/2 the dist between
+    // liq incentive scales with how far underwater the account is; see
+    // LiquidationParams::scaled_liquidation_fee_multiplier
 
     // Can only deposit as much as it is possible to withdraw out_token
-    let max_in_val = out_avail_val / PARTIAL_LIQ_INCENTIVE;
+    let max_in_val = out_avail_val / liquidation_fee;
     let max_in_val = min(deficit_val, max_in_val);
 
     // we know prices are not 0; if they are this will error;
@@ -1918,14 +3401,21 @@ fn get_in_out_quantities(
 
     // Withdraw incentive funds to liqor
     let in_val: U64F64 = in_quantity.checked_mul(prices[in_token_index]).unwrap();
-    let out_val: U64F64 = in_val * PARTIAL_LIQ_INCENTIVE;
+    let out_val: U64F64 = in_val * liquidation_fee;
     let out_quantity: U64F64 = out_val / prices[out_token_index];
 
     let withdraw = out_quantity / mango_group.indexes[out_token_index].deposit;
 
     checked_sub_deposit(mango_group, margin_account, out_token_index, withdraw)?;
 
-    // TODO account for the rounded amounts as deposits -- could be valuable in some tokens
+    // The liqor actually transfers in_quantity.ceil() in and out_quantity.floor() out, but the
+    // account's borrow/deposit were only adjusted by the exact fixed-point in_quantity/out_quantity
+    // above -- both roundings leave native tokens sitting in the vaults that no deposit accounts
+    // for. Capture that as protocol dust instead of leaking it; see `MangoGroup::dust`.
+    let in_quantity_native = in_quantity.checked_ceil().unwrap();
+    let out_quantity_native = out_quantity.checked_floor().unwrap();
+    mango_group.add_dust(in_token_index, in_quantity_native - in_quantity)?;
+    mango_group.add_dust(out_token_index, out_quantity - out_quantity_native)?;
 
-    Ok((in_quantity.checked_ceil().unwrap().to_num(), out_quantity.checked_floor().unwrap().to_num()))
+    Ok((in_quantity_native.to_num(), out_quantity_native.to_num()))
 }
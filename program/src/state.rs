@@ -2,38 +2,176 @@ use std::cell::{Ref, RefMut};
 use std::convert::identity;
 use std::mem::size_of;
 
+use arrayref::array_ref;
 use bytemuck::{cast_slice, cast_slice_mut, from_bytes, from_bytes_mut, Pod, try_from_bytes, try_from_bytes_mut, Zeroable};
 use enumflags2::BitFlags;
 use fixed::types::U64F64;
+use serum_dex::matching::Side;
 use serum_dex::state::ToAlignedBytes;
 use solana_program::account_info::AccountInfo;
 use solana_program::clock::Clock;
 use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
 
 use fixed_macro::types::U64F64;
 
 use crate::error::{check_assert, MangoResult, SourceFileId, MangoErrorCode, MangoError};
 
-/// Initially launching with BTC/USDT, ETH/USDT
-pub const NUM_TOKENS: usize = 5;
+/// Every per-token/per-market field on `MangoGroup` and `MarginAccount` is a fixed-size array of
+/// this length, so this is the hard ceiling on how many spot markets a single group can ever list.
+/// Raised from the original launch-day value of 5 (BTC/USDT, ETH/USDT) now that more markets are
+/// queued up; bumping it again later is a purely mechanical, backwards-incompatible resize of
+/// both account types (nothing else in the program hardcodes a token/market count). A fully
+/// variable-length layout -- storing only as many slots as a given group actually uses, via the
+/// `strip_header`-style machinery below -- would remove the need to ever bump this again, but
+/// that also means turning every fixed-size `[T; NUM_TOKENS]`/`[AccountInfo; NUM_MARKETS]` account
+/// and instruction-data layout in processor.rs/instruction.rs into a runtime-counted one; tracked
+/// as follow-up work rather than bundled into this resize.
+pub const NUM_TOKENS: usize = 16;
 pub const NUM_MARKETS: usize = NUM_TOKENS - 1;
-pub const MANGO_GROUP_PADDING: usize = 8 - (NUM_TOKENS + NUM_MARKETS) % 8;
+pub const MANGO_GROUP_PADDING: usize = 8 - (NUM_TOKENS + NUM_MARKETS + 2) % 8;
 pub const MINUTE: u64 = 60;
 pub const HOUR: u64 = 3600;
 pub const DAY: u64 = 86400;
 pub const YEAR: U64F64 = U64F64!(31536000);
-const OPTIMAL_UTIL: U64F64 = U64F64!(0.7);
-const OPTIMAL_R: U64F64 = U64F64!(1.9025875190258751902587e-09);  // 6% APR -> 0.06 / YEAR
-const MAX_R: U64F64 = U64F64!(4.7564687975646879756468e-08); // max 150% APR -> 2 / YEAR
+
+/// Starting point for `MangoGroup::max_index_staleness`, set at `init_mango_group`. Liquidations
+/// read `indexes[i].last_update` directly to price collateral, so an hour-old index is the oldest
+/// a liquidator should be allowed to act against.
+pub const DEFAULT_MAX_INDEX_STALENESS: u64 = HOUR;
+
+/// Starting point for `MangoGroup::max_oracle_spread_bps`, set at `init_mango_group`. Caps how far
+/// a configured secondary oracle's median may diverge from the primary's (in bps of the primary)
+/// before `get_prices` refuses to act for a liquidation-sensitive caller; see `StaleOrUnreliableOracle`.
+pub const DEFAULT_MAX_ORACLE_SPREAD_BPS: u16 = 100;
+
+/// Starting point for `MangoGroup::interest_rate_params`, set on every token at `init_mango_group`
+/// and from then on only mutable per-token via `change_interest_params`. Reproduces the rate curve
+/// this kinked model replaced: 0% APR at 0% utilization, 6% APR at the 70% kink, 150% APR at 100%
+/// utilization.
+pub const DEFAULT_INTEREST_RATE_PARAMS: InterestRateParams = InterestRateParams {
+    optimal_util: U64F64!(0.7),
+    base_rate: U64F64!(0),
+    rate_slope1: U64F64!(1.9025875190258751902587e-09),  // 6% APR -> 0.06 / YEAR
+    rate_slope2: U64F64!(4.5662100456621004566210e-08), // 150% APR -> 1.5 / YEAR, minus rate_slope1
+};
 
 pub const ONE_U64F64: U64F64 = U64F64!(1);
 pub const ZERO_U64F64: U64F64 = U64F64!(0);
-pub const PARTIAL_LIQ_INCENTIVE: U64F64 = U64F64!(1.05);
-pub const DUST_THRESHOLD: U64F64 = U64F64!(1);  // TODO make this part of MangoGroup state
 pub const EPSILON: U64F64 = U64F64!(1.0e-17);
 pub const INFO_LEN: usize = 32;
 
+/// Governance-tunable knobs for `partial_liquidate`, stored on `MangoGroup` so they can be
+/// adjusted without a program redeploy. See `DEFAULT_LIQUIDATION_PARAMS` for the starting point.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[repr(C)]
+pub struct LiquidationParams {
+    /// Below this native-quote asset value, `partial_liquidate` socializes the full shortfall
+    /// immediately instead of leaving the account for further partial liquidations to pick at.
+    pub dust_threshold: U64F64,
+    /// Bonus paid to the liquidator, baked into `get_in_out_quantities`'s `in_quantity`/
+    /// `out_quantity` as a bps markup on top of the 1:1 exchange rate; see
+    /// `liquidation_fee_multiplier`.
+    pub liquidation_fee_bps: u16,
+    /// Caps, as bps of a token's total deposits, how much `socialize_loss` may write down
+    /// `MangoIndex::deposit` in a single call -- bounds how much of one bad debt event a single
+    /// liquidation can pass on to lenders.
+    pub max_socialized_loss_bps: u16,
+    /// Caps, as bps of the full amount needed to bring an account back to `init_coll_ratio`, how
+    /// much of that deficit a single `partial_liquidate` call may close; see
+    /// `LiquidationParams::close_factor`. Forces a liquidator to leave a severely underwater
+    /// account for more than one call instead of seizing all of its collateral at once.
+    pub close_factor_bps: u16,
+}
+unsafe impl Zeroable for LiquidationParams {}
+unsafe impl Pod for LiquidationParams {}
+
+impl LiquidationParams {
+    /// `1 + liquidation_fee_bps / 10000` -- the multiplier applied to the liquidator's incoming
+    /// debt repayment to get the discounted collateral payout.
+    pub fn liquidation_fee_multiplier(&self) -> U64F64 {
+        ONE_U64F64 + U64F64::from_num(self.liquidation_fee_bps) / U64F64::from_num(10_000u16)
+    }
+
+    /// Health-scaled (Dutch-auction) version of `liquidation_fee_multiplier`, used by
+    /// `get_in_out_quantities` so the liquidator bonus ramps with how underwater the account is
+    /// instead of jumping straight to the max bonus. `coll_ratio` is the account's current
+    /// collateral ratio; `maint_coll_ratio`/`init_coll_ratio` come from `MangoGroup`. Barely-unhealthy
+    /// accounts (`coll_ratio` near `init_coll_ratio`) pay close to no bonus; accounts at or below
+    /// `maint_coll_ratio` pay the full `liquidation_fee_bps` bonus -- same payout as the old fixed
+    /// multiplier, so fully-underwater accounts see no regression.
+    pub fn scaled_liquidation_fee_multiplier(
+        &self,
+        coll_ratio: U64F64,
+        maint_coll_ratio: U64F64,
+        init_coll_ratio: U64F64,
+    ) -> U64F64 {
+        let max_bonus = self.liquidation_fee_multiplier() - ONE_U64F64;
+        if init_coll_ratio <= maint_coll_ratio {
+            return self.liquidation_fee_multiplier();
+        }
+        let span = init_coll_ratio - maint_coll_ratio;
+        let frac = if coll_ratio >= init_coll_ratio {
+            ZERO_U64F64
+        } else if coll_ratio <= maint_coll_ratio {
+            ONE_U64F64
+        } else {
+            (init_coll_ratio - coll_ratio) / span
+        };
+        ONE_U64F64 + max_bonus * frac
+    }
+
+    /// `close_factor_bps / 10000` -- the fraction of the full init-coll-ratio deficit that
+    /// `get_partial_liq_deficit` allows a single `partial_liquidate` call to close.
+    pub fn close_factor(&self) -> U64F64 {
+        U64F64::from_num(self.close_factor_bps) / U64F64::from_num(10_000u16)
+    }
+}
+
+/// Starting point for `MangoGroup::liquidation_params`, set at `init_mango_group` and from then
+/// on only mutable via `ChangeLiquidationParams`. Reproduces the previous hardcoded behavior: a
+/// 1 native-quote dust threshold, a 5% liquidator bonus, no cap on socialized losses, and no cap
+/// on how much of the deficit a single `partial_liquidate` call may close.
+pub const DEFAULT_LIQUIDATION_PARAMS: LiquidationParams = LiquidationParams {
+    dust_threshold: U64F64!(1),
+    liquidation_fee_bps: 500,
+    max_socialized_loss_bps: 10_000,
+    close_factor_bps: 10_000,
+};
+
+/// Per-token borrow origination fee config, mirroring `ReserveFees` from token-lending-style
+/// protocols. Set explicitly per token at `init_mango_group`; see
+/// `MangoInstruction::InitMangoGroup::borrow_fee_params`.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[repr(C)]
+pub struct BorrowFeeParams {
+    /// Fraction of a `borrow` call's `quantity` added to the borrower's debt on top of the
+    /// amount borrowed; see `Processor::borrow`.
+    pub origination_fee_rate: U64F64,
+    /// Bps of the origination fee paid out to the instruction's host account (for front-end
+    /// referrers) instead of staying with the protocol; see `MangoGroup::fees`.
+    pub host_fee_bps: u16,
+}
+unsafe impl Zeroable for BorrowFeeParams {}
+unsafe impl Pod for BorrowFeeParams {}
+
+/// Starting point for `MangoGroup::borrow_fee_params`: no origination fee, reproducing `borrow`'s
+/// behavior before fees existed.
+pub const DEFAULT_BORROW_FEE_PARAMS: BorrowFeeParams = BorrowFeeParams {
+    origination_fee_rate: ZERO_U64F64,
+    host_fee_bps: 0,
+};
+
+/// Current on-disk layout version for `MangoGroup`. A group whose `version` field reads anything
+/// else needs `MangoGroup::migrate` run on it before `load_checked`/`load_mut_checked` will load
+/// it -- see `MangoGroup::version`.
+pub const MANGO_GROUP_VERSION: u8 = 1;
+/// Current on-disk layout version for `MarginAccount`; see `MANGO_GROUP_VERSION`.
+pub const MARGIN_ACCOUNT_VERSION: u8 = 1;
+/// Current on-disk layout version for `MangoSrmAccount`; see `MANGO_GROUP_VERSION`.
+pub const MANGO_SRM_ACCOUNT_VERSION: u8 = 1;
+
 
 macro_rules! check_default {
     ($cond:expr) => {
@@ -46,6 +184,15 @@ macro_rules! check_eq_default {
     }
 }
 
+/// Asserts an account's `version` field matches the layout this program version expects,
+/// throwing `MangoErrorCode::UnsupportedVersion` (instead of the generic `Default`) so a client
+/// that hits this can tell "call migrate first" apart from "this account is just malformed".
+macro_rules! check_version {
+    ($x:expr, $y:expr) => {
+        check_assert($x == $y, MangoErrorCode::UnsupportedVersion, line!(), SourceFileId::State)
+    }
+}
+
 macro_rules! throw {
     () => {
         MangoError::MangoErrorCode {
@@ -56,18 +203,34 @@ macro_rules! throw {
     }
 }
 
+/// Like `throw!`, but for `MangoSrmAccount::load_mut_checked`, which has to distinguish "this is
+/// a pre-version account, same size as `MangoSrmAccountV0`, go call migrate" from "this is just
+/// malformed" before it can even get as far as `check_version!`.
+macro_rules! throw_version {
+    () => {
+        MangoError::MangoErrorCode {
+            mango_error_code: MangoErrorCode::UnsupportedVersion,
+            line: line!(),
+            source_file_id: SourceFileId::State
+        }
+    }
+}
+
 
 pub trait Loadable: Pod {
     fn load_mut<'a>(account: &'a AccountInfo) -> Result<RefMut<'a, Self>, ProgramError> {
-        // TODO verify if this checks for size
-        Ok(RefMut::map(account.try_borrow_mut_data()?, |data| from_bytes_mut(data)))
+        check_assert(account.data_len() == size_of::<Self>(), MangoErrorCode::InvalidAccountSize, line!(), SourceFileId::State)?;
+        // size already verified above, so the try_from_bytes_mut inside can't fail
+        Ok(RefMut::map(account.try_borrow_mut_data()?, |data| try_from_bytes_mut(data).unwrap()))
     }
     fn load<'a>(account: &'a AccountInfo) -> Result<Ref<'a, Self>, ProgramError> {
-        Ok(Ref::map(account.try_borrow_data()?, |data| from_bytes(data)))
+        check_assert(account.data_len() == size_of::<Self>(), MangoErrorCode::InvalidAccountSize, line!(), SourceFileId::State)?;
+        Ok(Ref::map(account.try_borrow_data()?, |data| try_from_bytes(data).unwrap()))
     }
 
     fn load_from_bytes(data: &[u8]) -> Result<&Self, ProgramError> {
-        Ok(from_bytes(data))
+        check_assert(data.len() == size_of::<Self>(), MangoErrorCode::InvalidAccountSize, line!(), SourceFileId::State)?;
+        Ok(try_from_bytes(data).unwrap())
     }
 }
 
@@ -86,7 +249,25 @@ pub enum AccountFlag {
     Initialized = 1u64 << 0,
     MangoGroup = 1u64 << 1,
     MarginAccount = 1u64 << 2,
-    MangoSrmAccount = 1u64 << 3
+    MangoSrmAccount = 1u64 << 3,
+    /// Set on `MangoGroup`s whose `signer_nonce` is a canonical one-byte bump seed (searched
+    /// downward from 255, like `Pubkey::find_program_address`) rather than the original 8-byte
+    /// nonce. Never set retroactively -- groups initialized before this flag existed keep
+    /// validating their signer key against the legacy seed scheme. See
+    /// `MangoGroup::signer_nonce_seed` and `crate::utils::create_signer_key_and_nonce`.
+    CanonicalSignerNonce = 1u64 << 4
+}
+
+/// Reads just the `account_flags` word out of `account` -- which every `MangoGroup`,
+/// `MarginAccount`, and `MangoSrmAccount` leads with, at every past and present layout version --
+/// without committing to decoding the rest of the account as any one of those types. Used by
+/// `Processor::migrate` to work out which of the three `target_acc` is before dispatching to its
+/// `migrate`.
+pub fn peek_account_flags(account: &AccountInfo) -> MangoResult<BitFlags<AccountFlag>> {
+    check_default!(account.data_len() >= size_of::<u64>())?;
+    let data = account.try_borrow_data()?;
+    let account_flags = u64::from_le_bytes(*array_ref![data, 0, 8]);
+    BitFlags::from_bits(account_flags).map_err(|_| throw!())
 }
 
 
@@ -101,13 +282,180 @@ unsafe impl Zeroable for MangoIndex {}
 unsafe impl Pod for MangoIndex {}
 
 
+/// A per-token kinked interest rate curve: flat-ish below `optimal_util`, steeper above it. See
+/// `MangoGroup::get_interest_rate` for how the pieces combine into a borrow APR.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[repr(C)]
+pub struct InterestRateParams {
+    pub optimal_util: U64F64,
+    pub base_rate: U64F64,
+    pub rate_slope1: U64F64,
+    pub rate_slope2: U64F64
+}
+unsafe impl Zeroable for InterestRateParams {}
+unsafe impl Pod for InterestRateParams {}
+
+impl InterestRateParams {
+    /// Borrow APR at 0% utilization -- the `min_borrow_rate` of a token-lending-style reserve
+    /// config.
+    pub fn min_borrow_rate(&self) -> U64F64 {
+        self.base_rate
+    }
+    /// Borrow APR at `optimal_util` -- the `optimal_borrow_rate` of a token-lending-style reserve
+    /// config, where the curve's kink sits.
+    pub fn optimal_borrow_rate(&self) -> U64F64 {
+        self.base_rate + self.rate_slope1
+    }
+    /// Borrow APR at 100% utilization -- the `max_borrow_rate` of a token-lending-style reserve
+    /// config.
+    pub fn max_borrow_rate(&self) -> U64F64 {
+        self.base_rate + self.rate_slope1 + self.rate_slope2
+    }
+}
+
+
+/// Number of decimals on the SRM mint. Used to scale the native SRM amounts in
+/// `DEFAULT_SRM_FEE_TIER_THRESHOLDS`.
+pub const SRM_DECIMALS: u32 = 6;
+
+/// Step schedule for SRM-staked fee discounts, Serum style: tier 0 is the undiscounted base
+/// rate, and each subsequent tier is unlocked once `MangoSrmAccount::amount` reaches the
+/// corresponding threshold below.
+pub const NUM_FEE_TIERS: usize = 5;
+
+/// Ascending native SRM thresholds (scaled by `SRM_DECIMALS`) that unlock
+/// `srm_fee_tiers[1..]`. Tier `i` is reached once the staked amount is >= `thresholds[i - 1]`.
+pub const DEFAULT_SRM_FEE_TIER_THRESHOLDS: [u64; NUM_FEE_TIERS - 1] = [
+    100 * 10u64.pow(SRM_DECIMALS),
+    1_000 * 10u64.pow(SRM_DECIMALS),
+    10_000 * 10u64.pow(SRM_DECIMALS),
+    100_000 * 10u64.pow(SRM_DECIMALS),
+];
+
+/// Native SRM-equivalent value of a single (whole, 0-decimal) MSRM token. Holding MSRM grants the
+/// maximum fee tier outright, so this is set comfortably above the top `DEFAULT_SRM_FEE_TIER_THRESHOLDS`
+/// entry: 1 MSRM is worth 1,000,000 SRM, scaled up to native units by `SRM_DECIMALS`.
+pub const MSRM_TO_NATIVE_SRM: u64 = 1_000_000 * 10u64.pow(SRM_DECIMALS);
+
+/// Default maker/taker rates for each tier, in basis points of notional. A negative `maker_bps`
+/// is a rebate paid to the maker rather than a fee charged.
+pub const DEFAULT_SRM_FEE_TIERS: [FeeTier; NUM_FEE_TIERS] = [
+    FeeTier { maker_bps: -3, taker_bps: 22 },
+    FeeTier { maker_bps: -3, taker_bps: 20 },
+    FeeTier { maker_bps: -3, taker_bps: 18 },
+    FeeTier { maker_bps: -4, taker_bps: 16 },
+    FeeTier { maker_bps: -5, taker_bps: 14 },
+];
+
+/// Maker/taker fee rates for a single SRM fee-discount tier, in basis points of notional.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FeeTier {
+    pub maker_bps: i16,
+    pub taker_bps: i16,
+}
+unsafe impl Zeroable for FeeTier {}
+unsafe impl Pod for FeeTier {}
+
+/// Serum dex's own hardcoded SRM/MSRM fee-tier schedule -- distinct from `FeeTier`/
+/// `MangoGroup::srm_fee_tier_thresholds`, which is Mango's governance-tunable schedule for
+/// Mango's own fees. Lets order-placing code predict what serum itself will charge before
+/// submitting an order (to size the order and reserve quote correctly) instead of discovering it
+/// only after the fill comes back.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SerumFeeTier {
+    Base,
+    Srm2,
+    Srm3,
+    Srm4,
+    Srm5,
+    Srm6,
+    Msrm,
+}
+
+/// Ascending native SRM thresholds unlocking `Srm2..Srm6`, mirroring serum dex's own schedule:
+/// 100, 1_000, 10_000, 100_000, 1_000_000 SRM, scaled by `SRM_DECIMALS`.
+const SERUM_FEE_TIER_THRESHOLDS: [u64; 5] = [
+    100 * 10u64.pow(SRM_DECIMALS),
+    1_000 * 10u64.pow(SRM_DECIMALS),
+    10_000 * 10u64.pow(SRM_DECIMALS),
+    100_000 * 10u64.pow(SRM_DECIMALS),
+    1_000_000 * 10u64.pow(SRM_DECIMALS),
+];
+
+impl SerumFeeTier {
+    /// Matches serum dex's own tiering: holding at least one native MSRM wins the top tier
+    /// outright; otherwise the SRM balance is walked against `SERUM_FEE_TIER_THRESHOLDS`.
+    pub fn from_balances(srm_amount: u64, msrm_amount: u64) -> Self {
+        if msrm_amount >= 1 {
+            return SerumFeeTier::Msrm;
+        }
+        let mut tier = SerumFeeTier::Base;
+        for (i, &threshold) in SERUM_FEE_TIER_THRESHOLDS.iter().enumerate() {
+            if srm_amount < threshold {
+                break;
+            }
+            tier = match i {
+                0 => SerumFeeTier::Srm2,
+                1 => SerumFeeTier::Srm3,
+                2 => SerumFeeTier::Srm4,
+                3 => SerumFeeTier::Srm5,
+                4 => SerumFeeTier::Srm6,
+                _ => unreachable!(),
+            };
+        }
+        tier
+    }
+
+    /// Taker rate in hundred-thousandths of a percent -- `serum_taker_fee` divides by `10_000_000` --
+    /// matching serum dex's own per-tier schedule. Higher tiers pay progressively less.
+    pub fn taker_rate(self) -> u64 {
+        match self {
+            SerumFeeTier::Base => 2_200,
+            SerumFeeTier::Srm2 => 2_000,
+            SerumFeeTier::Srm3 => 1_800,
+            SerumFeeTier::Srm4 => 1_600,
+            SerumFeeTier::Srm5 => 1_400,
+            SerumFeeTier::Srm6 => 1_200,
+            SerumFeeTier::Msrm => 1_000,
+        }
+    }
+
+    /// Maker rebate, same units as `taker_rate`; serum only widens the rebate at the top tier.
+    pub fn maker_rebate(self) -> u64 {
+        match self {
+            SerumFeeTier::Msrm => 500,
+            _ => 300,
+        }
+    }
+}
+
+/// Native taker fee serum will charge for an order of `native_qty` at `tier`, rounded up like
+/// serum's own matching engine (a taker never underpays because of truncation).
+pub fn serum_taker_fee(tier: SerumFeeTier, native_qty: u64) -> u64 {
+    let numerator = (native_qty as u128) * (tier.taker_rate() as u128);
+    ((numerator + 9_999_999) / 10_000_000) as u64
+}
+
+
 /// A group of spot markets that can be cross margined together
 /// TODO need plans to migrate smart contract
-/// TODO add in fees for devs and UI hosters
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct MangoGroup {
     pub account_flags: u64,
+
+    /// How many of the `NUM_TOKENS`/`NUM_MARKETS` slots below are populated for this group. Today
+    /// `init_mango_group` always sets this to the compile-time maximum -- the account layout is
+    /// still the fixed-size `[T; NUM_TOKENS]` arrays below, not the variable-length, header-plus-tail
+    /// layout described at `NUM_TOKENS`, so no group can yet be created with fewer than the full
+    /// count. What's here is the bookkeeping and valuation-loop plumbing (quote currency at
+    /// `num_tokens - 1`, not the fixed `NUM_TOKENS - 1`; `get_assets`/`get_liabs`/`update_indexes`
+    /// all walk `0..num_tokens`) for that variable-length layout to land on top of without another
+    /// pass over the valuation math; the on-disk resize itself remains follow-up work.
+    pub num_tokens: u8,
+    pub num_markets: u8,
+
     pub tokens: [Pubkey; NUM_TOKENS],  // Last token is shared quote currency
     pub vaults: [Pubkey; NUM_TOKENS],  // where funds are stored
     pub indexes: [MangoIndex; NUM_TOKENS],  // to keep track of interest
@@ -125,6 +473,7 @@ pub struct MangoGroup {
     pub init_coll_ratio: U64F64,  //  1.20
 
     pub srm_vault: Pubkey,  // holds users SRM for fee reduction
+    pub msrm_vault: Pubkey,  // holds users MSRM for fee reduction
 
     /// This admin key is only for alpha release and the only power it has is to amend borrow limits
     /// If users borrow too much too quickly before liquidators are able to handle the volume,
@@ -133,9 +482,73 @@ pub struct MangoGroup {
     pub admin: Pubkey,
     pub borrow_limits: [u64; NUM_TOKENS],
 
+    /// Per-token kinked interest rate curve driving `update_indexes`; defaults to
+    /// `DEFAULT_INTEREST_RATE_PARAMS` at `init_mango_group` and is admin-tunable thereafter via
+    /// `change_interest_params`.
+    pub interest_rate_params: [InterestRateParams; NUM_TOKENS],
+
+    /// Ascending native SRM thresholds that unlock `srm_fee_tiers[1..]`. Governance-tunable so
+    /// the schedule isn't hard-coded; see `DEFAULT_SRM_FEE_TIER_THRESHOLDS` for the starting point.
+    pub srm_fee_tier_thresholds: [u64; NUM_FEE_TIERS - 1],
+    /// Maker/taker fee rates for each SRM fee-discount tier; see `DEFAULT_SRM_FEE_TIERS`.
+    pub srm_fee_tiers: [FeeTier; NUM_FEE_TIERS],
+
     pub mint_decimals: [u8; NUM_TOKENS],
     pub oracle_decimals: [u8; NUM_MARKETS],
-    pub padding: [u8; MANGO_GROUP_PADDING]
+
+    /// Max age in seconds of `indexes[i].last_update` and of an oracle's underlying aggregator
+    /// round before `liquidate`/`partial_liquidate` refuse to act against them; see
+    /// `require_fresh_indexes` and `DEFAULT_MAX_INDEX_STALENESS`.
+    pub max_index_staleness: u64,
+
+    /// Liquidator incentive and lender-protection knobs for `partial_liquidate`; see
+    /// `LiquidationParams` and `DEFAULT_LIQUIDATION_PARAMS`.
+    pub liquidation_params: LiquidationParams,
+
+    /// Optional second price feed per market, checked against `oracles[i]` by `get_prices`
+    /// whenever a liquidation-sensitive caller passes oracle2 accounts. `Pubkey::default()` means
+    /// no secondary oracle is configured for that market and the cross-check is skipped. Set via
+    /// the `SetOracle2` admin instruction.
+    pub oracles2: [Pubkey; NUM_MARKETS],
+    /// Max allowed divergence, in bps of the primary oracle's median, between `oracles[i]` and
+    /// `oracles2[i]` before `get_prices` throws `StaleOrUnreliableOracle` for a liquidation-sensitive
+    /// caller; see `DEFAULT_MAX_ORACLE_SPREAD_BPS`.
+    pub max_oracle_spread_bps: u16,
+
+    /// Native-token rounding remainders `get_in_out_quantities` leaves behind when its
+    /// `checked_ceil`/`checked_floor` round the liqor's in/out legs to whole native units --
+    /// per-token, in the same native units as `total_deposits`/`total_borrows`. Accumulates across
+    /// every partial liquidation instead of leaking silently; see `MangoGroup::add_dust`.
+    /// There is no sweep instruction yet -- these are exposed for a future insurance-fund sweep.
+    pub dust: [U64F64; NUM_TOKENS],
+
+    /// Per-token origination fee and host-referral split charged by `borrow`; set explicitly at
+    /// `init_mango_group`. See `BorrowFeeParams` and `DEFAULT_BORROW_FEE_PARAMS`.
+    pub borrow_fee_params: [BorrowFeeParams; NUM_TOKENS],
+    /// Protocol-retained portion of accumulated `borrow_fee_params` origination fees -- the host's
+    /// `host_fee_bps` share is paid out immediately instead, the rest is tallied here in the same
+    /// native-unit ledger convention as `dust`. There is no sweep instruction yet -- exposed for a
+    /// future dev-fee sweep.
+    pub fees: [U64F64; NUM_TOKENS],
+
+    /// Risk-tiered collateral haircut per token, applied in `get_assets_val` (and therefore
+    /// `get_equity`/`coll_ratio_from_assets_liabs`) so a volatile token's deposits count for less
+    /// than its oracle value towards collateral (e.g. 0.9 for BTC/ETH, 1.0 for the stable quote
+    /// currency). Defaults to `ONE_U64F64` at `init_mango_group`, preserving the pre-weighting
+    /// behavior, and is admin-tunable via `ChangeCollateralWeights`.
+    pub asset_weights: [U64F64; NUM_TOKENS],
+    /// Risk-tiered liability markup per token, applied in `get_liabs_val` so a volatile token's
+    /// borrows count for more than their oracle value against collateral ratio. Defaults to
+    /// `ONE_U64F64`, same convention as `asset_weights`.
+    pub liab_weights: [U64F64; NUM_TOKENS],
+
+    /// Layout version, bumped by `migrate` whenever this account gains new fields. Every
+    /// `MangoGroup` written before this field existed has this byte read back as 0 -- it lives
+    /// inside what used to be `padding`, which is zero-initialized at account creation and never
+    /// otherwise written -- so `load_checked`/`load_mut_checked` treat 0 as "needs `migrate`"
+    /// rather than silently treating the rest of the account as the current layout.
+    pub version: u8,
+    pub padding: [u8; MANGO_GROUP_PADDING - 1]
 }
 impl_loadable!(MangoGroup);
 
@@ -151,7 +564,11 @@ impl MangoGroup {
         check_eq_default!(account.owner, program_id)?;
 
         let mango_group = Self::load_mut(account)?;
-        check_eq_default!(mango_group.account_flags, (AccountFlag::Initialized | AccountFlag::MangoGroup).bits())?;
+        // `CanonicalSignerNonce` may or may not be set depending on when this group was
+        // initialized; only the base flags are mandatory.
+        check_default!(BitFlags::from_bits(mango_group.account_flags).unwrap()
+            .contains(AccountFlag::Initialized | AccountFlag::MangoGroup))?;
+        check_version!(mango_group.version, MANGO_GROUP_VERSION)?;
 
         Ok(mango_group)
     }
@@ -163,33 +580,66 @@ impl MangoGroup {
         check_eq_default!(account.owner, program_id)?;
 
         let mango_group = Self::load(account)?;
-        check_eq_default!(mango_group.account_flags, (AccountFlag::Initialized | AccountFlag::MangoGroup).bits())?;
+        check_default!(BitFlags::from_bits(mango_group.account_flags).unwrap()
+            .contains(AccountFlag::Initialized | AccountFlag::MangoGroup))?;
+        check_version!(mango_group.version, MANGO_GROUP_VERSION)?;
 
         Ok(mango_group)
     }
+    /// Rewrites a pre-versioning `MangoGroup` (`version == 0`) into the current layout in place:
+    /// bumps `version` and zeroes what's left of `padding` now that one of its bytes carries a new
+    /// meaning. No other field moves -- `version` was carved out of previously-unused padding, not
+    /// inserted ahead of anything -- so there's nothing else to shift.
+    pub fn migrate(account: &AccountInfo, program_id: &Pubkey) -> MangoResult<()> {
+        check_eq_default!(account.data_len(), size_of::<Self>())?;
+        check_eq_default!(account.owner, program_id)?;
+
+        let mut mango_group = Self::load_mut(account)?;
+        check_default!(BitFlags::from_bits(mango_group.account_flags).unwrap()
+            .contains(AccountFlag::Initialized | AccountFlag::MangoGroup))?;
+        check_eq_default!(mango_group.version, 0)?;
+
+        mango_group.version = MANGO_GROUP_VERSION;
+        mango_group.padding = [0u8; MANGO_GROUP_PADDING - 1];
+        Ok(())
+    }
     pub fn get_token_index(&self, mint_pk: &Pubkey) -> Option<usize> {
         self.tokens.iter().position(|token| token == mint_pk)
     }
     pub fn get_token_index_with_vault(&self, vault: &Pubkey) -> Option<usize> {
         self.vaults.iter().position(|pk| pk == vault)
     }
-    /// interest is in units per second (e.g. 0.01 => 1% interest per second)
-    pub fn get_interest_rate(&self, token_index: usize) -> U64F64 {
+    /// `native_borrows / native_deposits` for a token, i.e. how much of the deposit base is lent
+    /// out. `None` when borrows equal or exceed deposits (including the `deposits == 0` case), which
+    /// `get_interest_rate` treats as maxed-out utilization.
+    pub fn utilization(&self, token_index: usize) -> Option<U64F64> {
         let index: &MangoIndex = &self.indexes[token_index];
         let native_deposits = index.deposit.checked_mul(self.total_deposits[token_index]).unwrap();
         let native_borrows = index.borrow.checked_mul(self.total_borrows[token_index]).unwrap();
         if native_deposits <= native_borrows {  // if deps == 0, this is always true
-            return MAX_R;  // kind of an error state
+            None
+        } else {
+            Some(native_borrows.checked_div(native_deposits).unwrap())
         }
+    }
 
-        let utilization = native_borrows.checked_div(native_deposits).unwrap();
-        if utilization > OPTIMAL_UTIL {
-            let extra_util = utilization - OPTIMAL_UTIL;
-            let slope = (MAX_R - OPTIMAL_R) / (ONE_U64F64 - OPTIMAL_UTIL);
-            OPTIMAL_R + slope * extra_util
+    /// interest is in units per second (e.g. 0.01 => 1% interest per second), driven by this
+    /// token's `interest_rate_params` kinked curve: below `optimal_util` the rate ramps linearly
+    /// from `min_borrow_rate` to `optimal_borrow_rate`, above it `rate_slope2` takes over up to
+    /// `max_borrow_rate` at 100% utilization.
+    pub fn get_interest_rate(&self, token_index: usize) -> U64F64 {
+        let params = &self.interest_rate_params[token_index];
+        let utilization = match self.utilization(token_index) {
+            Some(u) => u,
+            None => return params.max_borrow_rate(),  // kind of an error state
+        };
+
+        if utilization > params.optimal_util {
+            let extra_util = utilization - params.optimal_util;
+            let slope = params.rate_slope2 / (ONE_U64F64 - params.optimal_util);
+            params.base_rate + params.rate_slope1 + slope * extra_util
         } else {
-            let slope = OPTIMAL_R / OPTIMAL_UTIL;
-            slope * utilization
+            params.base_rate + params.rate_slope1 * (utilization / params.optimal_util)
         }
     }
 
@@ -202,7 +652,7 @@ impl MangoGroup {
 
         let curr_ts = clock.unix_timestamp as u64;
 
-        for i in 0..NUM_TOKENS {
+        for i in 0..self.num_tokens as usize {
             let interest_rate = self.get_interest_rate(i);
             let index: &mut MangoIndex = &mut self.indexes[i];
             if index.last_update == curr_ts || self.total_deposits[i] == ZERO_U64F64 {
@@ -250,6 +700,27 @@ impl MangoGroup {
         self.spot_markets.iter().position(|market| market == spot_market_pk)
     }
 
+    /// Maps a native (raw) staked SRM amount to its fee tier, by walking `srm_fee_tier_thresholds`
+    /// in ascending order. Tier 0 is the undiscounted base rate.
+    pub fn srm_fee_tier(&self, srm_amount: u64) -> usize {
+        let mut tier = 0;
+        for &threshold in self.srm_fee_tier_thresholds.iter() {
+            if srm_amount >= threshold {
+                tier += 1;
+            } else {
+                break;
+            }
+        }
+        tier
+    }
+
+    /// Maker/taker fee rates, in basis points of notional, for a native staked SRM amount.
+    /// Always recomputed from the live amount, never cached, so a withdrawal demotes the tier
+    /// immediately.
+    pub fn srm_fee_rates(&self, srm_amount: u64) -> FeeTier {
+        self.srm_fee_tiers[self.srm_fee_tier(srm_amount)]
+    }
+
     pub fn checked_add_borrow(&mut self, token_i: usize, v: U64F64) -> MangoResult<()> {
         Ok(self.total_borrows[token_i] = self.total_borrows[token_i].checked_add(v).ok_or(throw!())?)
     }
@@ -265,6 +736,29 @@ impl MangoGroup {
     pub fn checked_sub_deposit(&mut self, token_i: usize, v: U64F64) -> MangoResult<()> {
         Ok(self.total_deposits[token_i] = self.total_deposits[token_i].checked_sub(v).ok_or(throw!())?)
     }
+
+    /// Credit `amount` native units of `token_i` to `dust`; see `MangoGroup::dust`.
+    pub fn add_dust(&mut self, token_i: usize, amount: U64F64) -> MangoResult<()> {
+        Ok(self.dust[token_i] = self.dust[token_i].checked_add(amount).ok_or(throw!())?)
+    }
+
+    /// Credit `amount` native units of `token_i` to `fees`; see `MangoGroup::fees`.
+    pub fn add_fee(&mut self, token_i: usize, amount: U64F64) -> MangoResult<()> {
+        Ok(self.fees[token_i] = self.fees[token_i].checked_add(amount).ok_or(throw!())?)
+    }
+
+    /// The second PDA seed for this group's `signer_key`: the canonical one-byte bump if this
+    /// group was created with `AccountFlag::CanonicalSignerNonce` set, else the legacy 8-byte
+    /// nonce. Pass the result alongside `mango_group_pk.as_ref()` to `invoke_signed`.
+    pub fn signer_nonce_seed(&self) -> Vec<u8> {
+        let is_canonical = BitFlags::from_bits(self.account_flags).unwrap()
+            .contains(AccountFlag::CanonicalSignerNonce);
+        if is_canonical {
+            vec![self.signer_nonce as u8]
+        } else {
+            self.signer_nonce.to_le_bytes().to_vec()
+        }
+    }
 }
 
 
@@ -286,7 +780,10 @@ pub struct MarginAccount {
     pub being_liquidated: bool,
     pub has_borrows: bool, // does the account have any open borrows? set by checked_add_borrow and checked_sub_borrow
     pub info: [u8; INFO_LEN],
-    pub padding: [u8; 38] // padding for future expansion
+    /// Layout version; see `MangoGroup::version` for the convention this follows and
+    /// `MarginAccount::migrate`.
+    pub version: u8,
+    pub padding: [u8; 37] // padding for future expansion
 }
 impl_loadable!(MarginAccount);
 
@@ -303,6 +800,7 @@ impl MarginAccount {
         check_eq_default!(margin_account.account_flags, (AccountFlag::Initialized | AccountFlag::MarginAccount).bits())?;
         // prog_assert_eq!(&margin_account.owner, owner_pk)?; // not necessary
         check_eq_default!(&margin_account.mango_group, mango_group_pk)?;
+        check_version!(margin_account.version, MARGIN_ACCOUNT_VERSION)?;
 
         Ok(margin_account)
     }
@@ -318,17 +816,33 @@ impl MarginAccount {
         check_eq_default!(margin_account.account_flags, (AccountFlag::Initialized | AccountFlag::MarginAccount).bits())?;
         // prog_assert_eq!(&margin_account.owner, owner_pk)?;  // not necessary
         check_eq_default!(&margin_account.mango_group, mango_group_pk)?;
+        check_version!(margin_account.version, MARGIN_ACCOUNT_VERSION)?;
 
         Ok(margin_account)
     }
+    /// Rewrites a pre-versioning `MarginAccount` (`version == 0`) into the current layout in
+    /// place; see `MangoGroup::migrate` for why this is just a version bump and a padding zero
+    /// rather than a field-by-field copy.
+    pub fn migrate(program_id: &Pubkey, account: &AccountInfo) -> MangoResult<()> {
+        check_eq_default!(account.owner, program_id)?;
+        check_eq_default!(account.data_len(), size_of::<MarginAccount>())?;
+
+        let mut margin_account = Self::load_mut(account)?;
+        check_eq_default!(margin_account.account_flags, (AccountFlag::Initialized | AccountFlag::MarginAccount).bits())?;
+        check_eq_default!(margin_account.version, 0)?;
+
+        margin_account.version = MARGIN_ACCOUNT_VERSION;
+        margin_account.padding = [0u8; 37];
+        Ok(())
+    }
     pub fn get_equity(
         &self,
         mango_group: &MangoGroup,
         prices: &[U64F64; NUM_TOKENS],
         open_orders_accs: &[AccountInfo; NUM_MARKETS]
     ) -> MangoResult<U64F64> {
-        // TODO weight collateral differently
-        // equity = val(deposits) + val(positions) + val(open_orders) - val(borrows)
+        // equity = val(deposits) + val(positions) + val(open_orders) - val(borrows), each
+        // weighted by the token's asset_weights/liab_weights haircut
         let assets = self.get_assets_val(mango_group, prices, open_orders_accs)?;
         let liabs = self.get_liabs_val(mango_group, prices)?;
         if liabs > assets {
@@ -356,15 +870,20 @@ impl MarginAccount {
 
     pub fn coll_ratio_from_assets_liabs(
         &self,
+        mango_group: &MangoGroup,
         prices: &[U64F64; NUM_TOKENS],
         assets: &[U64F64; NUM_TOKENS],
         liabs: &[U64F64; NUM_TOKENS]
     ) -> MangoResult<U64F64> {
         let mut assets_val: U64F64 = ZERO_U64F64;
         let mut liabs_val: U64F64 = ZERO_U64F64;
-        for i in 0..NUM_TOKENS {
-            liabs_val = liabs[i].checked_mul(prices[i]).unwrap().checked_add(liabs_val).unwrap();
-            assets_val = assets[i].checked_mul(prices[i]).unwrap().checked_add(assets_val).unwrap();
+        for i in 0..mango_group.num_tokens as usize {
+            liabs_val = liabs[i].checked_mul(prices[i]).unwrap()
+                .checked_mul(mango_group.liab_weights[i]).unwrap()
+                .checked_add(liabs_val).unwrap();
+            assets_val = assets[i].checked_mul(prices[i]).unwrap()
+                .checked_mul(mango_group.asset_weights[i]).unwrap()
+                .checked_add(assets_val).unwrap();
         }
 
         if liabs_val == ZERO_U64F64 {
@@ -380,20 +899,21 @@ impl MarginAccount {
         open_orders_accs: &[AccountInfo; NUM_MARKETS]
     ) -> MangoResult<[U64F64; NUM_TOKENS]> {
         let mut assets = [ZERO_U64F64; NUM_TOKENS];
+        let quote_index = mango_group.num_tokens as usize - 1;
 
-        for i in 0..NUM_TOKENS {
+        for i in 0..mango_group.num_tokens as usize {
             assets[i] = mango_group.indexes[i].deposit.checked_mul(self.deposits[i]).unwrap()
                 .checked_add(assets[i]).unwrap();
         }
 
-        for i in 0..NUM_MARKETS {
+        for i in 0..mango_group.num_markets as usize {
             if *open_orders_accs[i].key == Pubkey::default() {
                 continue;
             }
             let open_orders = load_open_orders(&open_orders_accs[i])?;
 
             assets[i] = U64F64::from_num(open_orders.native_coin_total).checked_add(assets[i]).unwrap();
-            assets[NUM_TOKENS-1] = U64F64::from_num(open_orders.native_pc_total + open_orders.referrer_rebates_accrued).checked_add(assets[NUM_TOKENS-1]).unwrap();
+            assets[quote_index] = U64F64::from_num(open_orders.native_pc_total + open_orders.referrer_rebates_accrued).checked_add(assets[quote_index]).unwrap();
         }
         Ok(assets)
     }
@@ -405,7 +925,7 @@ impl MarginAccount {
     ) -> MangoResult<[U64F64; NUM_TOKENS]> {
         let mut liabs = [ZERO_U64F64; NUM_TOKENS];
 
-        for i in 0..NUM_TOKENS {
+        for i in 0..mango_group.num_tokens as usize {
             liabs[i] = mango_group.indexes[i].borrow.checked_mul(self.borrows[i]).unwrap()
                 .checked_add(liabs[i]).unwrap();
         }
@@ -420,10 +940,11 @@ impl MarginAccount {
         prices: &[U64F64; NUM_TOKENS],
         open_orders_accs: &[AccountInfo; NUM_MARKETS]
     ) -> MangoResult<U64F64> {
-        // TODO weight collateral differently
-        // equity = val(deposits) + val(positions) + val(open_orders) - val(borrows)
+        // equity = val(deposits) + val(positions) + val(open_orders) - val(borrows), each
+        // weighted by mango_group.asset_weights so riskier tokens count for less collateral
+        let quote_weight = mango_group.asset_weights[mango_group.num_tokens as usize - 1];
         let mut assets: U64F64 = ZERO_U64F64;
-        for i in 0..NUM_MARKETS {  // Add up all the value in open orders
+        for i in 0..mango_group.num_markets as usize {  // Add up all the value in open orders
             // TODO check open orders details
             if *open_orders_accs[i].key == Pubkey::default() {
                 continue;
@@ -432,14 +953,17 @@ impl MarginAccount {
             let open_orders = load_open_orders(&open_orders_accs[i])?;
             assets = U64F64::from_num(open_orders.native_coin_total)
                 .checked_mul(prices[i]).unwrap()
-                .checked_add(U64F64::from_num(open_orders.native_pc_total + open_orders.referrer_rebates_accrued)).unwrap()
+                .checked_mul(mango_group.asset_weights[i]).unwrap()
+                .checked_add(U64F64::from_num(open_orders.native_pc_total + open_orders.referrer_rebates_accrued)
+                    .checked_mul(quote_weight).unwrap()).unwrap()
                 .checked_add(assets).unwrap();
         }
-        for i in 0..NUM_TOKENS {  // add up the value in margin account deposits and positions
+        for i in 0..mango_group.num_tokens as usize {  // add up the value in margin account deposits and positions
             let index: &MangoIndex = &mango_group.indexes[i];
             let native_deposits = index.deposit.checked_mul(self.deposits[i]).unwrap();
             assets = native_deposits
                 .checked_mul(prices[i]).unwrap()
+                .checked_mul(mango_group.asset_weights[i]).unwrap()
                 .checked_add(assets).unwrap()
         }
         Ok(assets)
@@ -453,10 +977,10 @@ impl MarginAccount {
         prices: &[U64F64; NUM_TOKENS],
     ) -> MangoResult<U64F64> {
         let mut liabs: U64F64 = ZERO_U64F64;
-        for i in 0..NUM_TOKENS {
+        for i in 0..mango_group.num_tokens as usize {
             let index: &MangoIndex = &mango_group.indexes[i];
             let native_borrows = index.borrow * self.borrows[i];
-            liabs += native_borrows * prices[i];
+            liabs += native_borrows * prices[i] * mango_group.liab_weights[i];
         }
         Ok(liabs)
     }
@@ -479,6 +1003,9 @@ impl MarginAccount {
     }
 
 
+    // Native-quote value of collateral `get_in_out_quantities` will let a single
+    // `partial_liquidate` call seize, capped by `LiquidationParams::close_factor` so a severely
+    // underwater account is wound down over several calls instead of all at once.
     pub fn get_partial_liq_deficit(
         &self,
         mango_group: &MangoGroup,
@@ -492,7 +1019,11 @@ impl MarginAccount {
             Ok(ZERO_U64F64)
         } else {
             // TODO make this checked
-            Ok((liabs * mango_group.init_coll_ratio - assets) / (mango_group.init_coll_ratio - PARTIAL_LIQ_INCENTIVE))
+            let coll_ratio = assets.checked_div(liabs).unwrap();
+            let liquidation_fee = mango_group.liquidation_params.scaled_liquidation_fee_multiplier(
+                coll_ratio, mango_group.maint_coll_ratio, mango_group.init_coll_ratio);
+            let full_deficit = (liabs * mango_group.init_coll_ratio - assets) / (mango_group.init_coll_ratio - liquidation_fee);
+            Ok(full_deficit * mango_group.liquidation_params.close_factor())
         }
     }
 
@@ -524,13 +1055,34 @@ impl MarginAccount {
 // The SRM contributed to the pool by this user
 // These SRM are not at risk and have no effect on any margin calculations.
 // Depositing srm is a strictly altruistic act with no upside and no downside
+/// Pre-version on-chain layout of `MangoSrmAccount`, kept solely so `MangoSrmAccount::migrate`
+/// can read an older account's bytes before growing it to the current layout. Unlike
+/// `MangoGroup`/`MarginAccount`, this type never had spare `padding` to carve a `version` byte
+/// out of, so migrating it has to actually resize the account via `AccountInfo::realloc` instead
+/// of just reinterpreting a byte that was already there.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct MangoSrmAccountV0 {
+    pub account_flags: u64,
+    pub mango_group: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub msrm_amount: u64
+}
+impl_loadable!(MangoSrmAccountV0);
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct MangoSrmAccount {
     pub account_flags: u64,
     pub mango_group: Pubkey,
     pub owner: Pubkey,
-    pub amount: u64
+    pub amount: u64,
+    pub msrm_amount: u64,
+    /// Layout version; see `MangoGroup::version` for the convention. `padding` below exists so a
+    /// future field doesn't force another resize-on-migrate like this one did.
+    pub version: u8,
+    pub padding: [u8; 7],
 }
 impl_loadable!(MangoSrmAccount);
 
@@ -541,13 +1093,60 @@ impl MangoSrmAccount {
         mango_group_pk: &Pubkey
     ) -> MangoResult<RefMut<'a, Self>> {
         check_eq_default!(account.owner, program_id)?;
+        if account.data_len() == size_of::<MangoSrmAccountV0>() {
+            return Err(throw_version!());
+        }
         check_eq_default!(account.data_len(), size_of::<MangoSrmAccount>())?;
         let srm_account = Self::load_mut(account)?;
         check_eq_default!(srm_account.account_flags, (AccountFlag::Initialized | AccountFlag::MangoSrmAccount).bits())?;
         check_eq_default!(&srm_account.mango_group, mango_group_pk)?;
+        check_version!(srm_account.version, MANGO_SRM_ACCOUNT_VERSION)?;
 
         Ok(srm_account)
     }
+
+    /// Grows a pre-versioning `MangoSrmAccount` (`size_of::<MangoSrmAccountV0>()` bytes long) to
+    /// the current layout: reads the old fields, reallocates the account, then writes them back
+    /// alongside a fresh `version` and zeroed `padding`. `rent` is used to make sure the account
+    /// stays rent-exempt at its new, larger size -- `migrate` doesn't take a payer to top up
+    /// lamports, so the caller needs to fund the account first if it's short.
+    pub fn migrate(account: &AccountInfo, program_id: &Pubkey, rent: &Rent) -> MangoResult<()> {
+        check_eq_default!(account.owner, program_id)?;
+        check_eq_default!(account.data_len(), size_of::<MangoSrmAccountV0>())?;
+
+        let old = *MangoSrmAccountV0::load(account)?;
+        check_eq_default!(old.account_flags, (AccountFlag::Initialized | AccountFlag::MangoSrmAccount).bits())?;
+        check_default!(rent.is_exempt(account.lamports(), size_of::<MangoSrmAccount>()))?;
+
+        account.realloc(size_of::<MangoSrmAccount>(), true).map_err(MangoError::ProgramError)?;
+
+        let mut srm_account = Self::load_mut(account)?;
+        srm_account.account_flags = old.account_flags;
+        srm_account.mango_group = old.mango_group;
+        srm_account.owner = old.owner;
+        srm_account.amount = old.amount;
+        srm_account.msrm_amount = old.msrm_amount;
+        srm_account.version = MANGO_SRM_ACCOUNT_VERSION;
+        srm_account.padding = [0u8; 7];
+        Ok(())
+    }
+
+    /// Staked SRM plus staked MSRM converted to its native SRM-equivalent value, for fee-tier
+    /// lookup purposes. See `MSRM_TO_NATIVE_SRM`.
+    pub fn effective_srm_amount(&self) -> u64 {
+        self.amount.checked_add(self.msrm_amount.checked_mul(MSRM_TO_NATIVE_SRM).unwrap()).unwrap()
+    }
+
+    /// The fee tier this account's currently staked SRM and MSRM places it in. Always recomputed
+    /// from `amount`/`msrm_amount`, never cached, so withdrawing demotes the tier on the next trade.
+    pub fn fee_tier(&self, mango_group: &MangoGroup) -> usize {
+        mango_group.srm_fee_tier(self.effective_srm_amount())
+    }
+
+    /// Maker/taker fee rates this account currently qualifies for, per `fee_tier`.
+    pub fn fee_rates(&self, mango_group: &MangoGroup) -> FeeTier {
+        mango_group.srm_fee_rates(self.effective_srm_amount())
+    }
 }
 
 
@@ -625,6 +1224,10 @@ fn strip_header_mut<'a, H: Pod, D: Pod>(
 fn strip_data_header_mut<'a, H: Pod, D: Pod>(
     orig_data: RefMut<'a, [u8]>,
 ) -> MangoResult<(RefMut<'a, H>, RefMut<'a, [D]>)> {
+    check_default!(orig_data.len() >= size_of::<H>())?;
+    let inner_len = orig_data.len() - size_of::<H>();
+    check_default!(inner_len > 0 && inner_len % size_of::<D>() == 0)?;
+
     let (header, inner): (RefMut<'a, [H]>, RefMut<'a, [D]>) =
         RefMut::map_split(orig_data, |data| {
 
@@ -632,7 +1235,7 @@ fn strip_data_header_mut<'a, H: Pod, D: Pod>(
             let header: &mut H;
             let inner: &mut [D];
             header = try_from_bytes_mut(header_bytes).unwrap();
-            inner = remove_slop_mut(inner_bytes);
+            inner = cast_slice_mut(inner_bytes);
             (std::slice::from_mut(header), inner)
         });
     let header = RefMut::map(header, |s| s.first_mut().unwrap_or_else(|| unreachable!()));
@@ -643,6 +1246,10 @@ fn strip_data_header_mut<'a, H: Pod, D: Pod>(
 fn strip_data_header<'a, H: Pod, D: Pod>(
     orig_data: Ref<'a, [u8]>,
 ) -> MangoResult<(Ref<'a, H>, Ref<'a, [D]>)> {
+    check_default!(orig_data.len() >= size_of::<H>())?;
+    let inner_len = orig_data.len() - size_of::<H>();
+    check_default!(inner_len > 0 && inner_len % size_of::<D>() == 0)?;
+
     let (header, inner): (Ref<'a, [H]>, Ref<'a, [D]>) =
         Ref::map_split(orig_data, |data| {
 
@@ -650,13 +1257,25 @@ fn strip_data_header<'a, H: Pod, D: Pod>(
             let header: &H;
             let inner: &[D];
             header = try_from_bytes(header_bytes).unwrap();
-            inner = remove_slop(inner_bytes);
+            inner = cast_slice(inner_bytes);
             (std::slice::from_ref(header), inner)
         });
     let header = Ref::map(header, |s| s.first().unwrap_or_else(|| unreachable!()));
     Ok((header, inner))
 }
 
+/// Rejects a dex queue header whose `head`/`count`/`seq_num` claim more than `buf_len` slots are
+/// in use -- such a header could only come from a corrupted account or a stale/mismatched owner,
+/// and walking it as-is would index `buf` out of bounds. `seq_num` is the monotonic count of
+/// events ever pushed, so it can only be smaller than `count` if the header is inconsistent.
+fn check_queue_header_bounds(head: u64, count: u64, seq_num: u64, buf_len: usize) -> MangoResult<()> {
+    let buf_len = buf_len as u64;
+    check_default!(head < buf_len)?;
+    check_default!(count <= buf_len)?;
+    check_default!(seq_num >= count)?;
+    Ok(())
+}
+
 fn strip_dex_padding<'a>(acc: &'a AccountInfo) -> MangoResult<Ref<'a, [u8]>> {
     check_default!(acc.data_len() >= 12)?;
     let unpadded_data: Ref<[u8]> = Ref::map(acc.try_borrow_data()?, |data| {
@@ -731,22 +1350,64 @@ pub fn check_open_orders(
 }
 
 
+/// The three authority pubkeys a permissioned market's `MarketStateV2` appends right after the
+/// plain V1 `MarketState` layout -- `open_orders_authority`, `prune_authority`, and
+/// `consume_events_authority`, in that order. `None` for an ordinary (non-`Permissioned`) market.
+/// `load_market_state` only hands these back, it doesn't enforce them -- callers that need to
+/// gate an instruction on one of these authorities signing should check it themselves.
+#[derive(Copy, Clone, Debug)]
+pub struct MarketAuthorities {
+    pub open_orders_authority: Pubkey,
+    pub prune_authority: Pubkey,
+    pub consume_events_authority: Pubkey,
+}
+
+/// Loads `market_account` as a serum dex `MarketState`, regardless of whether the account is
+/// actually laid out as the plain V1 struct or the larger `MarketStateV2` permissioned-market
+/// variant -- `MarketStateV2` only ever appends fields after the V1-compatible header, so slicing
+/// to exactly `size_of::<MarketState>()` (instead of casting however many unpadded bytes happen to
+/// be present) reads the right struct either way, rather than panicking or misparsing the
+/// `MarketStateV2` tail as `MarketState` padding. When `account_flags` carries `Permissioned`,
+/// also decodes that tail into `MarketAuthorities`.
 pub fn load_market_state<'a>(
     market_account: &'a AccountInfo,
     program_id: &Pubkey,
-) -> MangoResult<RefMut<'a, serum_dex::state::MarketState>> {
+) -> MangoResult<(RefMut<'a, serum_dex::state::MarketState>, Option<MarketAuthorities>)> {
     check_eq_default!(market_account.owner, program_id)?;
+    check_default!(market_account.data_len() >= 12 + size_of::<serum_dex::state::MarketState>())?;
+
+    let authorities = {
+        let data = market_account.try_borrow_data()?;
+        let unpadded_len = data.len() - 12;
+        let (_, rest) = data.split_at(5);
+        let (mid, _) = rest.split_at(unpadded_len);
+
+        let header: &serum_dex::state::MarketState =
+            try_from_bytes(&mid[..size_of::<serum_dex::state::MarketState>()]).unwrap();
+        let flags = BitFlags::from_bits(header.account_flags).unwrap();
+        if flags.contains(serum_dex::state::AccountFlag::Permissioned) {
+            let tail = &mid[size_of::<serum_dex::state::MarketState>()..];
+            check_default!(tail.len() >= 3 * 32)?;
+            Some(MarketAuthorities {
+                open_orders_authority: Pubkey::new(&tail[0..32]),
+                prune_authority: Pubkey::new(&tail[32..64]),
+                consume_events_authority: Pubkey::new(&tail[64..96]),
+            })
+        } else {
+            None
+        }
+    };
 
     let state: RefMut<'a, serum_dex::state::MarketState>;
     state = RefMut::map(market_account.try_borrow_mut_data()?, |data| {
         let data_len = data.len() - 12;
         let (_, rest) = data.split_at_mut(5);
         let (mid, _) = rest.split_at_mut(data_len);
-        from_bytes_mut(mid)
+        from_bytes_mut(&mut mid[..size_of::<serum_dex::state::MarketState>()])
     });
 
     state.check_flags()?;
-    Ok(state)
+    Ok((state, authorities))
 }
 
 
@@ -756,7 +1417,544 @@ pub fn load_event_queue_mut<'a>(
 
     let orig_data = strip_dex_padding_mut(queue_acc)?;
     let (header, buf) = strip_data_header_mut::<serum_dex::state::EventQueueHeader, serum_dex::state::Event>(orig_data)?;
+    check_queue_header_bounds(header.head, header.count, header.seq_num, buf.len())?;
+
+    Ok(serum_dex::state::Queue { header, buf })
+}
+
+/// Loads a serum market's request queue -- the not-yet-matched new-order and cancel requests
+/// waiting on the next crank -- symmetric to `load_event_queue_mut`. Useful for checking crank
+/// progress or that the request queue is empty before consuming events.
+pub fn load_request_queue_mut<'a>(
+    queue_acc: &'a AccountInfo
+) -> MangoResult<serum_dex::state::Queue<'a, serum_dex::state::RequestQueueHeader>> {
+
+    let orig_data = strip_dex_padding_mut(queue_acc)?;
+    let (header, buf) = strip_data_header_mut::<serum_dex::state::RequestQueueHeader, serum_dex::state::Request>(orig_data)?;
+    check_queue_header_bounds(header.head, header.count, header.seq_num, buf.len())?;
 
+    let flags = BitFlags::from_bits(header.account_flags).unwrap();
+    check_default!(flags.contains(
+        serum_dex::state::AccountFlag::Initialized | serum_dex::state::AccountFlag::RequestQueue
+    ))?;
 
     Ok(serum_dex::state::Queue { header, buf })
 }
+
+/// A serum dex event, decoded from its raw `event_flags` byte instead of leaving every caller to
+/// re-derive fill-vs-out, side, and which raw `native_qty_*` field means what. See `decode_event`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodedEvent {
+    Fill {
+        owner: Pubkey,
+        owner_slot: u8,
+        fee_tier: u8,
+        native_qty_paid: u64,
+        native_qty_received: u64,
+        native_fee_or_rebate: u64,
+        order_id: u128,
+        client_order_id: u64,
+        side: Side,
+    },
+    Out {
+        owner: Pubkey,
+        owner_slot: u8,
+        native_qty_unlocked: u64,
+        native_qty_still_locked: u64,
+        order_id: u128,
+        client_order_id: u64,
+        side: Side,
+    },
+}
+
+/// Decodes a raw serum dex `Event` the same way serum's own crank interprets it: the `Fill` bit
+/// of `event_flags` picks the variant and the `Bid` bit gives which side the event is on; for a
+/// `Fill`, `event.native_qty_released`/`native_qty_paid` become `native_qty_received`/
+/// `native_qty_paid`, and for an `Out` they become `native_qty_unlocked`/`native_qty_still_locked`
+/// instead -- same two fields, different meaning depending on which kind of event it is.
+pub fn decode_event(event: &serum_dex::state::Event) -> DecodedEvent {
+    let flags = BitFlags::<serum_dex::state::EventFlag>::from_bits(event.event_flags).unwrap();
+    // `event` is `#[repr(packed)]`; copy the field out before taking a reference to it so
+    // `bytes_of` isn't handed a potentially-misaligned reference.
+    let owner_raw = event.owner;
+    let owner = Pubkey::new(bytemuck::bytes_of(&owner_raw));
+    let side = if flags.contains(serum_dex::state::EventFlag::Bid) { Side::Bid } else { Side::Ask };
+
+    if flags.contains(serum_dex::state::EventFlag::Fill) {
+        DecodedEvent::Fill {
+            owner,
+            owner_slot: event.owner_slot,
+            fee_tier: event.fee_tier,
+            native_qty_paid: event.native_qty_paid,
+            native_qty_received: event.native_qty_released,
+            native_fee_or_rebate: event.native_fee_or_rebate,
+            order_id: event.order_id,
+            client_order_id: event.client_order_id,
+            side,
+        }
+    } else {
+        DecodedEvent::Out {
+            owner,
+            owner_slot: event.owner_slot,
+            native_qty_unlocked: event.native_qty_released,
+            native_qty_still_locked: event.native_qty_paid,
+            order_id: event.order_id,
+            client_order_id: event.client_order_id,
+            side,
+        }
+    }
+}
+
+/// Decodes every event currently in `queue` (as returned by `load_event_queue_mut`), oldest
+/// first. See `decode_event`.
+pub fn decode_events<'a>(
+    queue: &'a serum_dex::state::EventQueue<'a>
+) -> impl Iterator<Item = DecodedEvent> + 'a {
+    queue.iter().map(decode_event)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_mango_group() -> MangoGroup {
+        let mut mango_group: MangoGroup = Zeroable::zeroed();
+        mango_group.num_tokens = NUM_TOKENS as u8;
+        mango_group.num_markets = NUM_MARKETS as u8;
+        mango_group.srm_fee_tier_thresholds = DEFAULT_SRM_FEE_TIER_THRESHOLDS;
+        mango_group.srm_fee_tiers = DEFAULT_SRM_FEE_TIERS;
+        mango_group.asset_weights = [ONE_U64F64; NUM_TOKENS];
+        mango_group.liab_weights = [ONE_U64F64; NUM_TOKENS];
+        mango_group.version = MANGO_GROUP_VERSION;
+        mango_group
+    }
+
+    #[test]
+    fn srm_fee_tier_climbs_with_staked_amount() {
+        let mango_group = test_mango_group();
+        let threshold = DEFAULT_SRM_FEE_TIER_THRESHOLDS[0];
+
+        assert_eq!(mango_group.srm_fee_tier(0), 0);
+        assert_eq!(mango_group.srm_fee_tier(threshold - 1), 0);
+        assert_eq!(mango_group.srm_fee_tier(threshold), 1);
+        assert_eq!(
+            mango_group.srm_fee_tier(*DEFAULT_SRM_FEE_TIER_THRESHOLDS.last().unwrap()),
+            NUM_FEE_TIERS - 1
+        );
+    }
+
+    #[test]
+    fn srm_fee_account_crossing_threshold_changes_tier_and_demotes_on_withdrawal() {
+        let mango_group = test_mango_group();
+        let mut srm_account: MangoSrmAccount = Zeroable::zeroed();
+        let threshold = DEFAULT_SRM_FEE_TIER_THRESHOLDS[0];
+
+        srm_account.amount = threshold - 1;
+        let tier_before_deposit = srm_account.fee_tier(&mango_group);
+        assert_eq!(tier_before_deposit, 0);
+
+        srm_account.amount += 1;  // deposit crosses the threshold
+        let tier_after_deposit = srm_account.fee_tier(&mango_group);
+        assert_eq!(tier_after_deposit, 1);
+        assert_ne!(tier_before_deposit, tier_after_deposit);
+        assert_eq!(srm_account.fee_rates(&mango_group), DEFAULT_SRM_FEE_TIERS[1]);
+
+        srm_account.amount -= 1;  // withdrawal drops back below the threshold
+        assert_eq!(srm_account.fee_tier(&mango_group), 0);
+        assert_eq!(srm_account.fee_rates(&mango_group), DEFAULT_SRM_FEE_TIERS[0]);
+    }
+
+    #[test]
+    fn single_msrm_unlocks_top_fee_tier() {
+        let mango_group = test_mango_group();
+        let mut srm_account: MangoSrmAccount = Zeroable::zeroed();
+
+        assert_eq!(srm_account.fee_tier(&mango_group), 0);
+
+        srm_account.msrm_amount = 1;
+        assert_eq!(srm_account.fee_tier(&mango_group), NUM_FEE_TIERS - 1);
+        assert_eq!(srm_account.fee_rates(&mango_group), DEFAULT_SRM_FEE_TIERS[NUM_FEE_TIERS - 1]);
+    }
+
+    #[test]
+    fn liquidation_dust_accounts_for_rounding() {
+        // Mirrors how get_in_out_quantities rounds its two legs: the in leg rounds up (liqor pays
+        // a sliver more than the exact debt), the out leg rounds down (liqor receives a sliver
+        // less than the exact payout). In both cases dust should make up the exact difference.
+        let mut mango_group = test_mango_group();
+        let in_quantity = U64F64::from_num(123u64) + U64F64::from_num(7u64) / U64F64::from_num(10u64);
+        let out_quantity = U64F64::from_num(456u64) + U64F64::from_num(3u64) / U64F64::from_num(10u64);
+
+        let in_quantity_native = in_quantity.checked_ceil().unwrap();
+        let out_quantity_native = out_quantity.checked_floor().unwrap();
+
+        mango_group.add_dust(0, in_quantity_native - in_quantity).unwrap();
+        mango_group.add_dust(1, out_quantity - out_quantity_native).unwrap();
+
+        assert_eq!(in_quantity + mango_group.dust[0], in_quantity_native);
+        assert_eq!(out_quantity_native + mango_group.dust[1], out_quantity);
+    }
+
+    #[test]
+    fn interest_rate_kinks_at_optimal_utilization() {
+        let mut mango_group = test_mango_group();
+        mango_group.interest_rate_params[0] = DEFAULT_INTEREST_RATE_PARAMS;
+        mango_group.indexes[0] = MangoIndex { last_update: 0, borrow: ONE_U64F64, deposit: ONE_U64F64 };
+
+        mango_group.total_deposits[0] = U64F64::from_num(100u64);
+        mango_group.total_borrows[0] = ZERO_U64F64;
+        assert_eq!(mango_group.utilization(0), Some(ZERO_U64F64));
+        assert_eq!(mango_group.get_interest_rate(0), DEFAULT_INTEREST_RATE_PARAMS.min_borrow_rate());
+
+        let params = DEFAULT_INTEREST_RATE_PARAMS;
+        mango_group.total_borrows[0] = mango_group.total_deposits[0] * params.optimal_util;
+        assert_eq!(mango_group.get_interest_rate(0), params.optimal_borrow_rate());
+
+        mango_group.total_borrows[0] = mango_group.total_deposits[0];
+        assert_eq!(mango_group.get_interest_rate(0), params.max_borrow_rate());
+
+        mango_group.total_deposits[0] = ZERO_U64F64;
+        assert_eq!(mango_group.utilization(0), None);
+    }
+
+    #[test]
+    fn close_factor_scales_bps_to_a_fraction() {
+        let mut params = DEFAULT_LIQUIDATION_PARAMS;
+
+        params.close_factor_bps = 10_000;
+        assert_eq!(params.close_factor(), ONE_U64F64);
+
+        params.close_factor_bps = 5_000;
+        assert_eq!(params.close_factor(), U64F64::from_num(1u64) / U64F64::from_num(2u64));
+
+        params.close_factor_bps = 0;
+        assert_eq!(params.close_factor(), ZERO_U64F64);
+    }
+
+    #[test]
+    fn collateral_weights_discount_assets_and_markup_liabs() {
+        // asset_weights/liab_weights haircut the raw value of each token before it hits the
+        // coll_ratio -- a sub-1.0 asset_weight should make collateral count for less, and a
+        // above-1.0 liab_weight should make debt count for more.
+        let mango_group = test_mango_group();
+        let mut prices = [ZERO_U64F64; NUM_TOKENS];
+        prices[0] = U64F64::from_num(100u64);
+        prices[1] = U64F64::from_num(100u64);
+        let mut assets = [ZERO_U64F64; NUM_TOKENS];
+        assets[0] = U64F64::from_num(10u64);
+        let mut liabs = [ZERO_U64F64; NUM_TOKENS];
+        liabs[1] = U64F64::from_num(10u64);
+
+        let margin_account: MarginAccount = Zeroable::zeroed();
+
+        // With all weights at 1.0, assets and liabs are both worth 1000 -- coll_ratio of 1.0
+        let baseline_ratio = margin_account.coll_ratio_from_assets_liabs(
+            &mango_group, &prices, &assets, &liabs).unwrap();
+        assert_eq!(baseline_ratio, ONE_U64F64);
+
+        let mut weighted_group = mango_group;
+        weighted_group.asset_weights[0] = U64F64::from_num(1u64) / U64F64::from_num(2u64); // 0.5
+        weighted_group.liab_weights[1] = U64F64::from_num(2u64); // 2.0
+        let weighted_ratio = margin_account.coll_ratio_from_assets_liabs(
+            &weighted_group, &prices, &assets, &liabs).unwrap();
+
+        // assets_val: 10 * 100 * 0.5 = 500; liabs_val: 10 * 100 * 2.0 = 2000 -> ratio 0.25
+        assert_eq!(weighted_ratio, U64F64::from_num(1u64) / U64F64::from_num(4u64));
+        assert!(weighted_ratio < baseline_ratio);
+    }
+
+    #[test]
+    fn loadable_rejects_mismatched_account_size() {
+        // Loadable::load_from_bytes (and load/load_mut, which share this check) must reject a
+        // buffer that isn't exactly size_of::<Self>() instead of letting try_from_bytes panic or
+        // silently reinterpret trailing/missing bytes.
+        let too_short = vec![0u8; size_of::<MangoSrmAccount>() - 1];
+        let err = MangoSrmAccount::load_from_bytes(&too_short).unwrap_err();
+        assert_eq!(err, ProgramError::Custom(MangoErrorCode::InvalidAccountSize.into()));
+
+        let too_long = vec![0u8; size_of::<MangoSrmAccount>() + 1];
+        let err = MangoSrmAccount::load_from_bytes(&too_long).unwrap_err();
+        assert_eq!(err, ProgramError::Custom(MangoErrorCode::InvalidAccountSize.into()));
+
+        let exact = vec![0u8; size_of::<MangoSrmAccount>()];
+        assert!(MangoSrmAccount::load_from_bytes(&exact).is_ok());
+    }
+
+    #[test]
+    fn queue_header_bounds_reject_headers_claiming_more_than_the_buffer_holds() {
+        // check_queue_header_bounds is what load_event_queue_mut/load_request_queue_mut lean on
+        // to avoid indexing a dex queue's ring buffer out of bounds from a corrupted or
+        // mismatched-owner header.
+        let buf_len = 8usize;
+
+        // head/count/seq_num all within [0, buf_len] and seq_num >= count -- valid
+        assert!(check_queue_header_bounds(0, buf_len as u64, buf_len as u64, buf_len).is_ok());
+        assert!(check_queue_header_bounds(buf_len as u64 - 1, 0, 0, buf_len).is_ok());
+
+        // head must be strictly less than buf_len
+        assert!(check_queue_header_bounds(buf_len as u64, 0, 0, buf_len).is_err());
+
+        // count can't exceed buf_len
+        assert!(check_queue_header_bounds(0, buf_len as u64 + 1, buf_len as u64 + 1, buf_len).is_err());
+
+        // seq_num (the monotonic count of events ever pushed) can't be smaller than count
+        assert!(check_queue_header_bounds(0, 2, 1, buf_len).is_err());
+    }
+
+    #[test]
+    fn serum_fee_tier_climbs_with_srm_balance_and_msrm_wins_outright() {
+        assert_eq!(SerumFeeTier::from_balances(0, 0), SerumFeeTier::Base);
+        assert_eq!(
+            SerumFeeTier::from_balances(SERUM_FEE_TIER_THRESHOLDS[0] - 1, 0),
+            SerumFeeTier::Base
+        );
+        assert_eq!(
+            SerumFeeTier::from_balances(SERUM_FEE_TIER_THRESHOLDS[0], 0),
+            SerumFeeTier::Srm2
+        );
+        assert_eq!(
+            SerumFeeTier::from_balances(*SERUM_FEE_TIER_THRESHOLDS.last().unwrap(), 0),
+            SerumFeeTier::Srm6
+        );
+        // A single native MSRM outranks any SRM balance, including the top SRM tier
+        assert_eq!(
+            SerumFeeTier::from_balances(*SERUM_FEE_TIER_THRESHOLDS.last().unwrap(), 1),
+            SerumFeeTier::Msrm
+        );
+
+        // Higher tiers pay progressively less and only the top tier gets the wider maker rebate
+        assert!(SerumFeeTier::Srm2.taker_rate() < SerumFeeTier::Base.taker_rate());
+        assert!(SerumFeeTier::Msrm.taker_rate() < SerumFeeTier::Srm6.taker_rate());
+        assert_eq!(SerumFeeTier::Base.maker_rebate(), SerumFeeTier::Srm6.maker_rebate());
+        assert!(SerumFeeTier::Msrm.maker_rebate() > SerumFeeTier::Srm6.maker_rebate());
+    }
+
+    #[test]
+    fn serum_taker_fee_rounds_up_like_serums_own_matching_engine() {
+        // Base tier taker_rate is 2_200 / 10_000_000 -- an exact multiple shouldn't round up
+        let native_qty = 10_000_000u64;
+        assert_eq!(serum_taker_fee(SerumFeeTier::Base, native_qty), 2_200);
+
+        // Anything that doesn't divide evenly should round up, never leaving serum underpaid
+        assert_eq!(serum_taker_fee(SerumFeeTier::Base, 1), 1);
+        assert_eq!(serum_taker_fee(SerumFeeTier::Base, 0), 0);
+    }
+
+    // `Event` is `Pod`, so an all-zero value is a valid instance -- this lets the test set only
+    // the fields `decode_event` actually reads instead of needing to know its full layout.
+    fn zeroed_event_with_flags(flags: BitFlags<serum_dex::state::EventFlag>) -> serum_dex::state::Event {
+        let mut event: serum_dex::state::Event = unsafe { std::mem::zeroed() };
+        event.event_flags = flags.bits();
+        event
+    }
+
+    #[test]
+    fn decode_event_maps_fill_flag_to_fill_variant_with_released_as_received() {
+        use serum_dex::state::EventFlag;
+
+        let mut event = zeroed_event_with_flags(EventFlag::Fill | EventFlag::Bid);
+        event.owner_slot = 3;
+        event.fee_tier = SerumFeeTier::Srm2 as u8;
+        event.native_qty_paid = 1_000;
+        event.native_qty_released = 2_000;
+        event.native_fee_or_rebate = 4;
+        event.order_id = 42;
+        event.client_order_id = 7;
+
+        match decode_event(&event) {
+            DecodedEvent::Fill {
+                owner_slot,
+                native_qty_paid,
+                native_qty_received,
+                native_fee_or_rebate,
+                order_id,
+                client_order_id,
+                side,
+                ..
+            } => {
+                assert_eq!(owner_slot, 3);
+                // Fill events keep paid/received as-is -- paid in, received out
+                assert_eq!(native_qty_paid, 1_000);
+                assert_eq!(native_qty_received, 2_000);
+                assert_eq!(native_fee_or_rebate, 4);
+                assert_eq!(order_id, 42);
+                assert_eq!(client_order_id, 7);
+                assert_eq!(side, Side::Bid);
+            }
+            DecodedEvent::Out { .. } => panic!("Fill flag must decode to DecodedEvent::Fill"),
+        }
+    }
+
+    #[test]
+    fn decode_event_maps_out_flag_to_out_variant_with_released_as_unlocked() {
+        use serum_dex::state::EventFlag;
+
+        let mut event = zeroed_event_with_flags(BitFlags::from(EventFlag::Ask));
+        event.owner_slot = 1;
+        // For an Out event, native_qty_paid/native_qty_released mean still-locked/unlocked instead
+        event.native_qty_paid = 500;
+        event.native_qty_released = 1_500;
+        event.order_id = 99;
+        event.client_order_id = 11;
+
+        match decode_event(&event) {
+            DecodedEvent::Out {
+                owner_slot,
+                native_qty_unlocked,
+                native_qty_still_locked,
+                order_id,
+                client_order_id,
+                side,
+                ..
+            } => {
+                assert_eq!(owner_slot, 1);
+                assert_eq!(native_qty_unlocked, 1_500);
+                assert_eq!(native_qty_still_locked, 500);
+                assert_eq!(order_id, 99);
+                assert_eq!(client_order_id, 11);
+                assert_eq!(side, Side::Ask);
+            }
+            DecodedEvent::Fill { .. } => panic!("Out flag (no Fill bit) must decode to DecodedEvent::Out"),
+        }
+    }
+
+    #[test]
+    fn decode_event_preserves_order_across_a_run_of_events() {
+        // decode_events is a thin `.iter().map(decode_event)` over a live serum_dex::state::Queue
+        // (no constructor short of a real market account), so this exercises decode_event applied
+        // in sequence -- the same mapping decode_events performs -- to pin down ordering.
+        use serum_dex::state::EventFlag;
+
+        let fill = zeroed_event_with_flags(EventFlag::Fill | EventFlag::Bid);
+        let out = zeroed_event_with_flags(BitFlags::from(EventFlag::Ask));
+        let decoded: Vec<DecodedEvent> = vec![fill, out].iter().map(decode_event).collect();
+
+        assert!(matches!(decoded[0], DecodedEvent::Fill { .. }));
+        assert!(matches!(decoded[1], DecodedEvent::Out { .. }));
+    }
+
+    #[test]
+    fn load_market_state_decodes_permissioned_market_authorities_tail() {
+        // Mirrors tests/helpers::add_dex_empty's "serum" + MarketState + "padding" layout, but with
+        // the Permissioned flag set and the 3-pubkey MarketAuthorities tail add_dex_empty doesn't
+        // need, to pin down load_market_state's V2 decoding path.
+        use serum_dex::state::{AccountFlag, MarketState};
+
+        let market_pk = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let open_orders_authority = Pubkey::new_unique();
+        let prune_authority = Pubkey::new_unique();
+        let consume_events_authority = Pubkey::new_unique();
+
+        let ms = MarketState {
+            account_flags: (AccountFlag::Initialized | AccountFlag::Market | AccountFlag::Permissioned).bits(),
+            own_address: market_pk.to_aligned_bytes(),
+            vault_signer_nonce: 0,
+            coin_mint: Pubkey::new_unique().to_aligned_bytes(),
+            pc_mint: Pubkey::new_unique().to_aligned_bytes(),
+
+            coin_vault: Pubkey::new_unique().to_aligned_bytes(),
+            coin_deposits_total: 0,
+            coin_fees_accrued: 0,
+
+            pc_vault: Pubkey::new_unique().to_aligned_bytes(),
+            pc_deposits_total: 0,
+            pc_fees_accrued: 0,
+            pc_dust_threshold: 0,
+
+            req_q: Pubkey::new_unique().to_aligned_bytes(),
+            event_q: Pubkey::new_unique().to_aligned_bytes(),
+            bids: Pubkey::new_unique().to_aligned_bytes(),
+            asks: Pubkey::new_unique().to_aligned_bytes(),
+
+            coin_lot_size: 1,
+            pc_lot_size: 1,
+
+            fee_rate_bps: 1,
+            referrer_rebates_accrued: 0,
+        };
+
+        let mut data = vec![];
+        data.extend_from_slice(b"serum");
+        data.extend_from_slice(bytemuck::bytes_of(&ms));
+        data.extend_from_slice(&open_orders_authority.to_bytes());
+        data.extend_from_slice(&prune_authority.to_bytes());
+        data.extend_from_slice(&consume_events_authority.to_bytes());
+        data.extend_from_slice(b"padding");
+
+        let mut lamports = 0u64;
+        let account_info = AccountInfo::new(
+            &market_pk,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        let (_state, authorities) = load_market_state(&account_info, &program_id).unwrap();
+        let authorities = authorities.expect("Permissioned flag must decode a MarketAuthorities tail");
+        assert_eq!(authorities.open_orders_authority, open_orders_authority);
+        assert_eq!(authorities.prune_authority, prune_authority);
+        assert_eq!(authorities.consume_events_authority, consume_events_authority);
+    }
+
+    #[test]
+    fn load_market_state_leaves_authorities_none_for_a_plain_v1_market() {
+        use serum_dex::state::{AccountFlag, MarketState};
+
+        let market_pk = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+
+        let ms = MarketState {
+            account_flags: (AccountFlag::Initialized | AccountFlag::Market).bits(),
+            own_address: market_pk.to_aligned_bytes(),
+            vault_signer_nonce: 0,
+            coin_mint: Pubkey::new_unique().to_aligned_bytes(),
+            pc_mint: Pubkey::new_unique().to_aligned_bytes(),
+
+            coin_vault: Pubkey::new_unique().to_aligned_bytes(),
+            coin_deposits_total: 0,
+            coin_fees_accrued: 0,
+
+            pc_vault: Pubkey::new_unique().to_aligned_bytes(),
+            pc_deposits_total: 0,
+            pc_fees_accrued: 0,
+            pc_dust_threshold: 0,
+
+            req_q: Pubkey::new_unique().to_aligned_bytes(),
+            event_q: Pubkey::new_unique().to_aligned_bytes(),
+            bids: Pubkey::new_unique().to_aligned_bytes(),
+            asks: Pubkey::new_unique().to_aligned_bytes(),
+
+            coin_lot_size: 1,
+            pc_lot_size: 1,
+
+            fee_rate_bps: 1,
+            referrer_rebates_accrued: 0,
+        };
+
+        let mut data = vec![];
+        data.extend_from_slice(b"serum");
+        data.extend_from_slice(bytemuck::bytes_of(&ms));
+        data.extend_from_slice(b"padding");
+
+        let mut lamports = 0u64;
+        let account_info = AccountInfo::new(
+            &market_pk,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        let (_state, authorities) = load_market_state(&account_info, &program_id).unwrap();
+        assert!(authorities.is_none());
+    }
+}
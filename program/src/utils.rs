@@ -1,27 +1,45 @@
-use bytemuck::{bytes_of, Contiguous};
+use bytemuck::bytes_of;
 use solana_program::pubkey::{Pubkey, PubkeyError};
 
-pub fn gen_signer_seeds<'a>(nonce: &'a u64, acc_pk: &'a Pubkey) -> [&'a [u8]; 2] {
-    [acc_pk.as_ref(), bytes_of(nonce)]
+/// Canonical one-byte bump seed, matching `Pubkey::find_program_address`'s convention. Used for
+/// every `MangoGroup` created since `AccountFlag::CanonicalSignerNonce` was introduced.
+pub fn gen_signer_seeds<'a>(bump: &'a u8, acc_pk: &'a Pubkey) -> [&'a [u8]; 2] {
+    [acc_pk.as_ref(), std::slice::from_ref(bump)]
 }
 
-
 pub fn gen_signer_key(
-    nonce: u64,
+    bump: u8,
     acc_pk: &Pubkey,
     program_id: &Pubkey,
 ) -> Result<Pubkey, PubkeyError> {
-    let seeds = gen_signer_seeds(&nonce, acc_pk);
+    let seeds = gen_signer_seeds(&bump, acc_pk);
     Pubkey::create_program_address(&seeds, program_id)
 }
 
-
-pub fn create_signer_key_and_nonce(program_id: &Pubkey, acc_pk: &Pubkey) -> (Pubkey, u64) {
-    for i in 0..=u64::MAX_VALUE {
-        if let Ok(pk) = gen_signer_key(i, acc_pk, program_id) {
-            return (pk, i);
+/// Searches bump seeds downward from 255 to 0 -- the same order and convention as
+/// `Pubkey::find_program_address` -- and returns the first (highest) valid signer key and bump.
+pub fn create_signer_key_and_nonce(program_id: &Pubkey, acc_pk: &Pubkey) -> (Pubkey, u8) {
+    for bump in (0..=u8::MAX).rev() {
+        if let Ok(pk) = gen_signer_key(bump, acc_pk, program_id) {
+            return (pk, bump);
         }
     }
     panic!("Could not generate signer key");
+}
+
+/// Legacy seed scheme: a full 8-byte nonce, found by scanning upward from 0 until
+/// `create_program_address` accepts it. Only kept to validate `MangoGroup`s initialized before
+/// `AccountFlag::CanonicalSignerNonce` existed -- see `MangoGroup::signer_nonce_seed`. Never used
+/// for new groups; use `create_signer_key_and_nonce` instead.
+pub fn gen_signer_seeds_legacy<'a>(nonce: &'a u64, acc_pk: &'a Pubkey) -> [&'a [u8]; 2] {
+    [acc_pk.as_ref(), bytes_of(nonce)]
+}
 
-}
\ No newline at end of file
+pub fn gen_signer_key_legacy(
+    nonce: u64,
+    acc_pk: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Pubkey, PubkeyError> {
+    let seeds = gen_signer_seeds_legacy(&nonce, acc_pk);
+    Pubkey::create_program_address(&seeds, program_id)
+}
@@ -18,15 +18,19 @@ use solana_sdk::{
     account_info::IntoAccountInfo,
     account::Account,
     instruction::Instruction,
-    signature::{Keypair, Signer}
+    signature::{Keypair, Signer},
+    transaction::Transaction,
 };
 
 use spl_token::state::{Mint, Account as Token, AccountState};
 use serum_dex::state::{MarketState, AccountFlag, ToAlignedBytes};
 
-use mango::processor::srm_token;
+use mango::processor::{msrm_token, srm_token};
 use mango::instruction::init_mango_group;
-use mango::state::MangoGroup;
+use mango::state::{
+    AccountFlag as MangoAccountFlag, BorrowFeeParams, DEFAULT_BORROW_FEE_PARAMS, Loadable, MangoGroup,
+    MarginAccount, MARGIN_ACCOUNT_VERSION, NUM_MARKETS, NUM_TOKENS,
+};
 
 pub const PRICE_BTC: u64 = 50000;
 pub const PRICE_ETH: u64 = 2000;
@@ -106,6 +110,28 @@ pub fn add_mint_srm(test: &mut ProgramTest) -> TestMint {
     }
 }
 
+pub fn add_mint_msrm(test: &mut ProgramTest) -> TestMint {
+    let authority = Keypair::new();
+    let pubkey = msrm_token::ID;
+    let decimals = 0;
+    test.add_packable_account(
+        pubkey,
+        u32::MAX as u64,
+        &Mint {
+            is_initialized: true,
+            mint_authority: COption::Some(authority.pubkey()),
+            decimals,
+            ..Mint::default()
+        },
+        &spl_token::id(),
+    );
+    TestMint {
+        pubkey,
+        authority,
+        decimals,
+    }
+}
+
 pub struct TestDex {
     pub pubkey: Pubkey,
 }
@@ -174,6 +200,25 @@ pub fn add_token_account(test: &mut ProgramTest, owner: Pubkey, mint: Pubkey, in
     TestTokenAccount { pubkey }
 }
 
+// Overwrites an already-added token account's balance -- used to back a margin account set up via
+// add_margin_account with the vault funds its deposits claim to hold, since that helper skips the
+// deposit() instruction that would normally move the tokens in.
+#[allow(dead_code)]  // Compiler complains about this even tho it is used
+pub fn set_token_balance(test: &mut ProgramTest, pubkey: Pubkey, owner: Pubkey, mint: Pubkey, amount: u64) {
+    test.add_packable_account(
+        pubkey,
+        u32::MAX as u64,
+        &Token {
+            mint: mint,
+            owner: owner,
+            amount: amount,
+            state: AccountState::Initialized,
+            ..Token::default()
+        },
+        &spl_token::id(),
+    );
+}
+
 pub struct TestAggregator {
     pub name: String,
     pub pubkey: Pubkey,
@@ -233,6 +278,9 @@ pub struct TestMangoGroup {
     pub srm_mint: TestMint,
     pub srm_vault: TestTokenAccount,
 
+    pub msrm_mint: TestMint,
+    pub msrm_vault: TestTokenAccount,
+
     pub dex_prog_id: Pubkey,
     // Dexes and Oracles must be sorted in the same way as the first n-1 mints
     // mints[x] / mints[-1]
@@ -240,6 +288,7 @@ pub struct TestMangoGroup {
     pub oracles: Vec<TestAggregator>,
 
     pub borrow_limits: Vec<u64>,
+    pub borrow_fee_params: Vec<BorrowFeeParams>,
 }
 
 
@@ -265,6 +314,7 @@ impl TestMangoGroup {
             U64F64::from_num(1.1),
             U64F64::from_num(1.2),
             to_fixed_array(self.borrow_limits.clone()),
+            to_fixed_array(self.borrow_fee_params.clone()),
         ).unwrap()
     }
 }
@@ -272,6 +322,7 @@ impl TestMangoGroup {
 pub fn add_mango_group_prodlike(test: &mut ProgramTest, program_id: Pubkey) -> TestMangoGroup {
     let mango_group_pk = Pubkey::new_unique();
     let (signer_pk, signer_nonce) = create_signer_key_and_nonce(&program_id, &mango_group_pk);
+    let signer_nonce = signer_nonce as u64;
     test.add_account(mango_group_pk, Account::new(u32::MAX as u64, size_of::<MangoGroup>(), &program_id));
 
     let btc_mint = add_mint(test, 6);
@@ -285,6 +336,9 @@ pub fn add_mango_group_prodlike(test: &mut ProgramTest, program_id: Pubkey) -> T
     let srm_mint = add_mint_srm(test);
     let srm_vault = add_token_account(test, signer_pk, srm_mint.pubkey, 0);
 
+    let msrm_mint = add_mint_msrm(test);
+    let msrm_vault = add_token_account(test, signer_pk, msrm_mint.pubkey, 0);
+
     let dex_prog_id = Pubkey::new_unique();
     let btc_usdt_dex = add_dex_empty(test, btc_mint.pubkey, usdt_mint.pubkey, dex_prog_id);
     let eth_usdt_dex = add_dex_empty(test, eth_mint.pubkey, usdt_mint.pubkey, dex_prog_id);
@@ -298,6 +352,7 @@ pub fn add_mango_group_prodlike(test: &mut ProgramTest, program_id: Pubkey) -> T
     let dexes = vec![btc_usdt_dex, eth_usdt_dex];
     let oracles = vec![btc_usdt, eth_usdt];
     let borrow_limits = vec![100, 100, 100];
+    let borrow_fee_params = vec![DEFAULT_BORROW_FEE_PARAMS; mints.len()];
 
     TestMangoGroup {
         program_id,
@@ -308,11 +363,48 @@ pub fn add_mango_group_prodlike(test: &mut ProgramTest, program_id: Pubkey) -> T
         vaults,
         srm_mint,
         srm_vault,
+        msrm_mint,
+        msrm_vault,
         dex_prog_id,
         dexes,
         oracles,
         borrow_limits,
+        borrow_fee_params,
+    }
+}
+
+// Builds a MarginAccount already holding the given deposits/borrows, bypassing deposit()/borrow()
+// so tests can put an account underwater without needing a price move or a time warp. Only valid
+// right after the group's indexes are initialized to 1.0, since deposits/borrows here are native
+// amounts, not index-scaled ones.
+#[allow(dead_code)]  // Compiler complains about this even tho it is used
+pub fn add_margin_account(
+    test: &mut ProgramTest,
+    program_id: Pubkey,
+    mango_group_pk: Pubkey,
+    owner: Pubkey,
+    deposits: [u64; NUM_TOKENS],
+    borrows: [u64; NUM_TOKENS],
+) -> Pubkey {
+    let pubkey = Pubkey::new_unique();
+    let mut account = Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id);
+    let account_info = (&pubkey, false, &mut account).into_account_info();
+    {
+        let mut margin_account = MarginAccount::load_mut(&account_info).unwrap();
+        margin_account.account_flags = (MangoAccountFlag::Initialized | MangoAccountFlag::MarginAccount).bits();
+        margin_account.mango_group = mango_group_pk;
+        margin_account.owner = owner;
+        margin_account.open_orders = [Pubkey::default(); NUM_MARKETS];
+        for i in 0..NUM_TOKENS {
+            margin_account.deposits[i] = U64F64::from_num(deposits[i]);
+            margin_account.borrows[i] = U64F64::from_num(borrows[i]);
+        }
+        margin_account.has_borrows = borrows.iter().any(|&b| b != 0);
+        margin_account.version = MARGIN_ACCOUNT_VERSION;
     }
+    drop(account_info);
+    test.add_account(pubkey, account);
+    pubkey
 }
 
 #[allow(dead_code)]  // Compiler complains about this even tho it is used
@@ -322,4 +414,56 @@ pub async fn get_token_balance(banks_client: &mut BanksClient, pubkey: Pubkey) -
     spl_token::state::Account::unpack(&token.data[..])
         .unwrap()
         .amount
+}
+
+// A recorded compute-unit baseline for a single instruction, plus how much an instruction's
+// consumption may drift from it before a test fails. Turns the ad-hoc
+// `test.set_bpf_compute_max_units(50_000)` ceiling into a per-instruction regression budget: the
+// hard-coded ceiling only catches gross blowouts, this catches the processor creeping upward one
+// small change at a time.
+#[allow(dead_code)]  // Compiler complains about this even tho it is used
+pub struct ComputeBudget {
+    pub baseline: u64,
+    pub tolerance: u64,
+}
+
+// Simulates `transaction` against the bank's current state (without committing it), pulls the
+// "consumed N of M compute units" line the bpf loader logs for the instruction's program
+// invocation, and asserts it falls within `budget.baseline +/- budget.tolerance`. `label` is
+// only used to name the instruction in the panic message. Returns the consumed units so a test
+// can print them while re-baselining.
+#[allow(dead_code)]  // Compiler complains about this even tho it is used
+pub async fn assert_compute_units_within_budget(
+    banks_client: &mut BanksClient,
+    transaction: Transaction,
+    label: &str,
+    budget: ComputeBudget,
+) -> u64 {
+    let result = banks_client.simulate_transaction(transaction).await.unwrap();
+    let logs = result
+        .simulation_details
+        .map(|details| details.logs)
+        .unwrap_or_default();
+
+    let consumed = logs
+        .iter()
+        .find_map(|line| parse_consumed_compute_units(line))
+        .unwrap_or_else(|| panic!("{}: no compute unit log found in {:#?}", label, logs));
+
+    let lower = budget.baseline.saturating_sub(budget.tolerance);
+    let upper = budget.baseline + budget.tolerance;
+    assert!(
+        consumed >= lower && consumed <= upper,
+        "{}: consumed {} compute units, want {}..={} (baseline {} +/- {})",
+        label, consumed, lower, upper, budget.baseline, budget.tolerance,
+    );
+
+    consumed
+}
+
+// Parses a bpf-loader log line of the form "Program <id> consumed 3621 of 50000 compute units".
+fn parse_consumed_compute_units(log: &str) -> Option<u64> {
+    let after_consumed = log.split("consumed ").nth(1)?;
+    let units = after_consumed.split(" of ").next()?;
+    units.trim().parse().ok()
 }
\ No newline at end of file
@@ -4,8 +4,10 @@
 mod helpers;
 
 use std::mem::size_of;
+use fixed::types::U64F64;
 use helpers::*;
 use solana_program::account_info::AccountInfo;
+use solana_program::program_pack::Pack;
 use solana_program_test::*;
 use solana_sdk::{
     pubkey::Pubkey,
@@ -13,9 +15,11 @@ use solana_sdk::{
     transaction::Transaction,
     account::Account,
 };
+use spl_token::state::Account as Token;
 use mango::{
     entrypoint::process_instruction,
     instruction::{deposit, borrow, init_margin_account},
+    state::BorrowFeeParams,
     state::MarginAccount,
     state::MangoGroup,
 };
@@ -114,6 +118,9 @@ async fn test_borrow_succeeds() {
                     &mango_group.mango_group_pk,
                     &margin_account_pk,
                     &user.pubkey(),
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    None,
                     &margin_account.open_orders,
                     mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>().as_slice(),
                     borrow_token_index,
@@ -258,6 +265,9 @@ async fn test_borrow_fails_overleveraged() {
                     &mango_group.mango_group_pk,
                     &margin_account_pk,
                     &user.pubkey(),
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    None,
                     &margin_account.open_orders,
                     mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>().as_slice(),
                     borrow_token_index,
@@ -306,4 +316,175 @@ async fn test_borrow_fails_overleveraged() {
         // Test nothing is added to total in mango group
         assert_eq!(mango_group.total_borrows[borrow_token_index], 0);
     }
+}
+
+#[tokio::test]
+async fn test_borrow_charges_origination_fee_and_pays_host() {
+    // Test that a nonzero BorrowFeeParams tacks the fee onto the borrower's debt and splits it
+    // between the protocol-retained MangoGroup::fees tally and an immediate host payout
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    // limit to track compute unit increase
+    test.set_bpf_compute_max_units(50_000);
+
+    let deposit_token_index = 0;
+    let borrow_token_index = 1;
+    let deposit_amount = 2000;
+    let borrow_amount = 32000;
+
+    // 1/16 origination fee, half of which is routed to the host account
+    let origination_fee_rate = U64F64::from_num(1) / U64F64::from_num(16);
+    let host_fee_bps = 5000;
+    let fee_native = U64F64::from_num(borrow_amount) * origination_fee_rate;
+    let host_fee_native: u64 = (fee_native * U64F64::from_num(host_fee_bps) / U64F64::from_num(10_000)).to_num();
+    let protocol_fee_native = fee_native - U64F64::from_num(host_fee_native);
+
+    let mut mango_group = add_mango_group_prodlike(&mut test, program_id);
+    let mango_group_pk = mango_group.mango_group_pk;
+    mango_group.borrow_fee_params[borrow_token_index] = BorrowFeeParams {
+        origination_fee_rate,
+        host_fee_bps,
+    };
+
+    let user = Keypair::new();
+    test.add_account(user.pubkey(), Account::new(u32::MAX as u64, 0, &user.pubkey()));
+
+    let user_account = add_token_account(
+        &mut test,
+        user.pubkey(),
+        mango_group.mints[deposit_token_index].pubkey,
+        deposit_amount,
+    );
+
+    let host = Keypair::new();
+    test.add_account(host.pubkey(), Account::new(u32::MAX as u64, 0, &host.pubkey()));
+    let host_account = add_token_account(
+        &mut test,
+        host.pubkey(),
+        mango_group.mints[borrow_token_index].pubkey,
+        0,
+    );
+
+    let margin_account_pk = Pubkey::new_unique();
+    test.add_account(margin_account_pk, Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id));
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // setup mango group and make a deposit
+    {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                mango_group.init_mango_group(&payer.pubkey()),
+                init_margin_account(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &user.pubkey(),
+                ).unwrap(),
+                deposit(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &user.pubkey(),
+                    &user_account.pubkey,
+                    &mango_group.vaults[deposit_token_index].pubkey,
+                    deposit_amount,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+
+        transaction.sign(
+            &[&payer, &user],
+            recent_blockhash,
+        );
+
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    // make a borrow, routing the host split to `host_account`
+    {
+        let mut margin_account = banks_client
+            .get_account(margin_account_pk)
+            .await
+            .unwrap()
+            .unwrap();
+        let account_info: AccountInfo = (&margin_account_pk, &mut margin_account).into();
+        let margin_account = MarginAccount::load_mut_checked(
+            &program_id,
+            &account_info,
+            &mango_group.mango_group_pk,
+        )
+        .unwrap();
+
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                borrow(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &user.pubkey(),
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    Some(&host_account.pubkey),
+                    &margin_account.open_orders,
+                    mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>().as_slice(),
+                    borrow_token_index,
+                    borrow_amount,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+
+        transaction.sign(
+            &[&payer, &user],
+            recent_blockhash,
+        );
+
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+        let mut margin_account = banks_client
+            .get_account(margin_account_pk)
+            .await
+            .unwrap()
+            .unwrap();
+        let account_info: AccountInfo = (&margin_account_pk, &mut margin_account).into();
+        let margin_account = MarginAccount::load_mut_checked(
+            &program_id,
+            &account_info,
+            &mango_group.mango_group_pk,
+        )
+        .unwrap();
+        // Test the recorded debt includes the origination fee on top of the requested quantity
+        assert_eq!(margin_account.borrows[borrow_token_index], U64F64::from_num(borrow_amount) + fee_native);
+
+        let mut mango_group_acc = banks_client
+            .get_account(mango_group_pk)
+            .await
+            .unwrap()
+            .unwrap();
+        let account_info: AccountInfo = (&mango_group_pk, &mut mango_group_acc).into();
+        let mango_group_state = MangoGroup::load_mut_checked(
+            &account_info,
+            &program_id,
+        )
+        .unwrap();
+        // Test the protocol's share is tallied in MangoGroup::fees, not paid out immediately
+        assert_eq!(mango_group_state.fees[borrow_token_index], protocol_fee_native);
+
+        let host_token_account = banks_client
+            .get_account(host_account.pubkey)
+            .await
+            .unwrap()
+            .unwrap();
+        let host_token = Token::unpack(host_token_account.data.as_slice()).unwrap();
+        // Test the host's share was paid out immediately via CPI transfer
+        assert_eq!(host_token.amount, host_fee_native);
+    }
 }
\ No newline at end of file
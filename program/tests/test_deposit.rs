@@ -6,18 +6,20 @@ mod helpers;
 use std::mem::size_of;
 use helpers::*;
 use solana_program::account_info::AccountInfo;
+use solana_program::instruction::InstructionError;
 use solana_program_test::*;
 use solana_sdk::{
     pubkey::Pubkey,
     signature::{Signer, Keypair},
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
     account::Account,
 };
 
 use mango::{
     entrypoint::process_instruction,
-    instruction::{deposit, init_margin_account},
-    state::MarginAccount,
+    error::MangoErrorCode,
+    instruction::{add_margin_account_info, deposit, init_margin_account},
+    state::{INFO_LEN, MarginAccount},
 };
 
 #[tokio::test]
@@ -113,6 +115,29 @@ async fn test_deposit_succeeds() {
         )
         .unwrap();
         assert_eq!(margin_account.deposits[0], deposit_amount);
+
+        // Guard deposit's compute-unit consumption against processor regressions
+        let mut cu_transaction = Transaction::new_with_payer(
+            &[
+                deposit(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &user.pubkey(),
+                    &user_account.pubkey,
+                    &mango_group.vaults[0].pubkey,
+                    deposit_amount,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        cu_transaction.sign(&[&payer, &user], recent_blockhash);
+        assert_compute_units_within_budget(
+            &mut banks_client,
+            cu_transaction,
+            "deposit",
+            ComputeBudget { baseline: 16_000, tolerance: 4_000 },
+        ).await;
     }
 }
 
@@ -228,4 +253,70 @@ async fn test_deposit_fails_invalid_margin_account_owner() {
         .unwrap();
         assert_eq!(margin_account.deposits[0], 0);
     }
+}
+
+#[tokio::test]
+async fn test_add_margin_account_info_fails_invalid_margin_account_owner() {
+    // Test that AddMarginAccountInfo fails with a decodable MangoErrorCode::InvalidMarginAccountOwner
+    // when the signer isn't the margin account's owner, rather than just an opaque is_err().
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    // setup mango group
+    let mango_group = add_mango_group_prodlike(&mut test, program_id);
+
+    // setup user accounts
+    let owner = Keypair::new();
+    test.add_account(owner.pubkey(), Account::new(u32::MAX as u64, 0, &owner.pubkey()));
+    let impostor = Keypair::new();
+    test.add_account(impostor.pubkey(), Account::new(u32::MAX as u64, 0, &impostor.pubkey()));
+
+    // setup marginaccount account
+    let margin_account_pk = Pubkey::new_unique();
+    test.add_account(margin_account_pk, Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id));
+
+    // setup test harness
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            mango_group.init_mango_group(&payer.pubkey()),
+            init_margin_account(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &margin_account_pk,
+                &owner.pubkey(),
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &owner], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            add_margin_account_info(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &margin_account_pk,
+                &impostor.pubkey(),
+                [7u8; INFO_LEN],
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &impostor], recent_blockhash);
+
+    assert_eq!(
+        banks_client.process_transaction(transaction).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(MangoErrorCode::InvalidMarginAccountOwner.into())
+        )
+    );
 }
\ No newline at end of file
@@ -13,11 +13,12 @@ use solana_sdk::{
     account::Account,
 };
 use solana_program::account_info::AccountInfo;
+use fixed::types::U64F64;
 
 use mango::{
     entrypoint::process_instruction,
-    instruction::init_margin_account,
-    state::MarginAccount,
+    instruction::{change_interest_params, change_collateral_weights, init_margin_account, set_oracle2},
+    state::{MarginAccount, MangoGroup, DEFAULT_INTEREST_RATE_PARAMS, NUM_TOKENS},
 };
 
 #[tokio::test]
@@ -71,6 +72,10 @@ async fn test_init_margin_account() {
     let mango_group = add_mango_group_prodlike(&mut test, program_id);
     let margin_account_pk = Pubkey::new_unique();
     test.add_account(margin_account_pk, Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id));
+    // a second, not-yet-initialized margin account used only to measure init_margin_account's
+    // compute-unit consumption without re-initializing margin_account_pk
+    let other_margin_account_pk = Pubkey::new_unique();
+    test.add_account(other_margin_account_pk, Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id));
     let user = Keypair::new();
     test.add_account(user.pubkey(), Account::new(u32::MAX as u64, 0, &user.pubkey()));
 
@@ -108,4 +113,288 @@ async fn test_init_margin_account() {
     for borrow in &margin_account.borrows {
         assert_eq!(borrow.to_bits(), 0);
     }
+
+    // Guard init_margin_account's compute-unit consumption against processor regressions
+    let mut cu_transaction = Transaction::new_with_payer(
+        &[
+            init_margin_account(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &other_margin_account_pk,
+                &user.pubkey(),
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    cu_transaction.sign(&[&payer, &user], recent_blockhash);
+    assert_compute_units_within_budget(
+        &mut banks_client,
+        cu_transaction,
+        "init_margin_account",
+        ComputeBudget { baseline: 3_000, tolerance: 1_500 },
+    ).await;
+}
+
+#[tokio::test]
+async fn test_change_interest_params() {
+    // change_interest_params should accept a sane kinked curve and reject an optimal_util
+    // that would divide by zero (or by zero's complement) in get_interest_rate
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let mango_group = add_mango_group_prodlike(&mut test, program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            mango_group.init_mango_group(&payer.pubkey()),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut good_params = DEFAULT_INTEREST_RATE_PARAMS;
+    good_params.optimal_util = U64F64::from_num(1) / U64F64::from_num(2);
+    let mut valid_transaction = Transaction::new_with_payer(
+        &[
+            change_interest_params(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                0,
+                good_params,
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    valid_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(valid_transaction).await.is_ok());
+
+    let mut account = banks_client.get_account(mango_group.mango_group_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&mango_group.mango_group_pk, &mut account).into();
+    let loaded_group = MangoGroup::load_mut_checked(&account_info, &program_id).unwrap();
+    assert_eq!(loaded_group.interest_rate_params[0].optimal_util, good_params.optimal_util);
+    drop(loaded_group);
+
+    let mut zero_util_params = DEFAULT_INTEREST_RATE_PARAMS;
+    zero_util_params.optimal_util = U64F64::from_num(0);
+    let mut zero_util_transaction = Transaction::new_with_payer(
+        &[
+            change_interest_params(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                0,
+                zero_util_params,
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    zero_util_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(zero_util_transaction).await.is_err());
+
+    let mut full_util_params = DEFAULT_INTEREST_RATE_PARAMS;
+    full_util_params.optimal_util = U64F64::from_num(1);
+    let mut full_util_transaction = Transaction::new_with_payer(
+        &[
+            change_interest_params(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                0,
+                full_util_params,
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    full_util_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(full_util_transaction).await.is_err());
+}
+
+#[tokio::test]
+async fn test_change_collateral_weights() {
+    // change_collateral_weights should accept a discounted asset_weight / marked-up liab_weight,
+    // reject the opposite (asset_weight out of (0,1] or liab_weight below 1.0), and reject an
+    // out-of-range token_index instead of indexing out of bounds
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let mango_group = add_mango_group_prodlike(&mut test, program_id);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            mango_group.init_mango_group(&payer.pubkey()),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut valid_transaction = Transaction::new_with_payer(
+        &[
+            change_collateral_weights(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                0,
+                U64F64::from_num(1) / U64F64::from_num(2), // 0.5 -- discounted collateral
+                U64F64::from_num(2), // 2.0 -- marked-up liability
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    valid_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(valid_transaction).await.is_ok());
+
+    let mut account = banks_client.get_account(mango_group.mango_group_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&mango_group.mango_group_pk, &mut account).into();
+    let loaded_group = MangoGroup::load_mut_checked(&account_info, &program_id).unwrap();
+    assert_eq!(loaded_group.asset_weights[0], U64F64::from_num(1) / U64F64::from_num(2));
+    assert_eq!(loaded_group.liab_weights[0], U64F64::from_num(2));
+    drop(loaded_group);
+
+    let mut zero_asset_weight_transaction = Transaction::new_with_payer(
+        &[
+            change_collateral_weights(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                0,
+                U64F64::from_num(0), // asset_weight must be > 0
+                U64F64::from_num(1),
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    zero_asset_weight_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(zero_asset_weight_transaction).await.is_err());
+
+    let mut low_liab_weight_transaction = Transaction::new_with_payer(
+        &[
+            change_collateral_weights(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                0,
+                U64F64::from_num(1),
+                U64F64::from_num(1) / U64F64::from_num(2), // liab_weight must be >= 1.0
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    low_liab_weight_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(low_liab_weight_transaction).await.is_err());
+
+    let mut out_of_range_index_transaction = Transaction::new_with_payer(
+        &[
+            change_collateral_weights(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                NUM_TOKENS, // one past the last valid token_index
+                U64F64::from_num(1),
+                U64F64::from_num(1),
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    out_of_range_index_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(out_of_range_index_transaction).await.is_err());
+}
+
+#[tokio::test]
+async fn test_set_oracle2() {
+    // set_oracle2 should accept an initialized flux aggregator as a market's secondary oracle,
+    // reject an uninitialized account in its place, and reject an out-of-range token_index
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let mango_group = add_mango_group_prodlike(&mut test, program_id);
+    let btc_usdt_2 = add_aggregator(&mut test, "BTC:USDT-2", 6, PRICE_BTC * 10u64.pow(6), &program_id);
+    let uninitialized_oracle_pk = Pubkey::new_unique();
+    test.add_account(
+        uninitialized_oracle_pk,
+        Account::new(u32::MAX as u64, size_of::<MangoGroup>(), &program_id),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            mango_group.init_mango_group(&payer.pubkey()),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut valid_transaction = Transaction::new_with_payer(
+        &[
+            set_oracle2(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                &btc_usdt_2.pubkey,
+                0,
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    valid_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(valid_transaction).await.is_ok());
+
+    let mut account = banks_client.get_account(mango_group.mango_group_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&mango_group.mango_group_pk, &mut account).into();
+    let loaded_group = MangoGroup::load_mut_checked(&account_info, &program_id).unwrap();
+    assert_eq!(loaded_group.oracles2[0], btc_usdt_2.pubkey);
+    drop(loaded_group);
+
+    let mut uninitialized_oracle_transaction = Transaction::new_with_payer(
+        &[
+            set_oracle2(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                &uninitialized_oracle_pk,
+                1,
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    uninitialized_oracle_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(uninitialized_oracle_transaction).await.is_err());
+
+    let mut out_of_range_index_transaction = Transaction::new_with_payer(
+        &[
+            set_oracle2(
+                &program_id,
+                &mango_group.mango_group_pk,
+                &payer.pubkey(),
+                &btc_usdt_2.pubkey,
+                NUM_TOKENS - 1, // one past the last valid market index (NUM_MARKETS == NUM_TOKENS - 1)
+            ).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    out_of_range_index_transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(out_of_range_index_transaction).await.is_err());
 }
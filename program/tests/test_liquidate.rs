@@ -0,0 +1,475 @@
+// Tests related to liquidating an undercollateralized MarginAccount
+#![cfg(feature="test-bpf")]
+
+mod helpers;
+
+use std::mem::size_of;
+use helpers::*;
+use fixed::types::U64F64;
+use solana_program::account_info::AccountInfo;
+use solana_program_test::*;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Signer, Keypair},
+    transaction::Transaction,
+    account::Account,
+};
+use mango::{
+    entrypoint::process_instruction,
+    instruction::{deposit, borrow, init_margin_account, partial_liquidate},
+    state::{MangoGroup, MarginAccount},
+};
+
+#[tokio::test]
+async fn test_liquidate_fails_on_healthy_account() {
+    // Test that partial_liquidate rejects an account that is still above maint_coll_ratio
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    test.set_bpf_compute_max_units(50_000);
+
+    let deposit_token_index = 0;
+    let borrow_token_index = 1;
+    let initial_amount = 2;
+    let deposit_amount = 1;
+    // 5x leverage -- lands exactly at init_coll_ratio, still above maint_coll_ratio
+    let borrow_amount = (deposit_amount * PRICE_BTC * 5) / PRICE_ETH;
+
+    let mut mango_group = add_mango_group_prodlike(&mut test, program_id);
+    // the helper's default borrow limit of 100 would otherwise reject this 125-unit borrow
+    mango_group.borrow_limits[borrow_token_index] = borrow_amount;
+
+    let liqee = Keypair::new();
+    test.add_account(liqee.pubkey(), Account::new(u32::MAX as u64, 0, &liqee.pubkey()));
+
+    let liqee_token_account = add_token_account(
+        &mut test,
+        liqee.pubkey(),
+        mango_group.mints[deposit_token_index].pubkey,
+        initial_amount,
+    );
+
+    let margin_account_pk = Pubkey::new_unique();
+    test.add_account(margin_account_pk, Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id));
+
+    let liqor = Keypair::new();
+    test.add_account(liqor.pubkey(), Account::new(u32::MAX as u64, 0, &liqor.pubkey()));
+    let liqor_in_token_account = add_token_account(
+        &mut test,
+        liqor.pubkey(),
+        mango_group.mints[borrow_token_index].pubkey,
+        1_000,
+    );
+    let liqor_out_token_account = add_token_account(
+        &mut test,
+        liqor.pubkey(),
+        mango_group.mints[deposit_token_index].pubkey,
+        0,
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // set up mango group, deposit collateral and borrow up to init_coll_ratio
+    {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                mango_group.init_mango_group(&payer.pubkey()),
+                init_margin_account(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &liqee.pubkey(),
+                ).unwrap(),
+                deposit(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &liqee.pubkey(),
+                    &liqee_token_account.pubkey,
+                    &mango_group.vaults[deposit_token_index].pubkey,
+                    deposit_amount,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &liqee], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    {
+        let mut margin_account = banks_client.get_account(margin_account_pk).await.unwrap().unwrap();
+        let account_info: AccountInfo = (&margin_account_pk, &mut margin_account).into();
+        let margin_account = MarginAccount::load_mut_checked(
+            &program_id,
+            &account_info,
+            &mango_group.mango_group_pk,
+        ).unwrap();
+
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                borrow(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &margin_account_pk,
+                    &liqee.pubkey(),
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    None,
+                    &margin_account.open_orders,
+                    mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>().as_slice(),
+                    borrow_token_index,
+                    borrow_amount,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &liqee], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    // attempt to liquidate -- should fail since the account is still at init_coll_ratio
+    {
+        let mut margin_account = banks_client.get_account(margin_account_pk).await.unwrap().unwrap();
+        let account_info: AccountInfo = (&margin_account_pk, &mut margin_account).into();
+        let margin_account = MarginAccount::load_mut_checked(
+            &program_id,
+            &account_info,
+            &mango_group.mango_group_pk,
+        ).unwrap();
+
+        let oracle_pks = mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>();
+        let oracle2_pks = vec![Pubkey::default(); oracle_pks.len()];
+
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                partial_liquidate(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &liqor.pubkey(),
+                    &liqor_in_token_account.pubkey,
+                    &liqor_out_token_account.pubkey,
+                    &margin_account_pk,
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.vaults[deposit_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    &margin_account.open_orders,
+                    oracle_pks.as_slice(),
+                    oracle2_pks.as_slice(),
+                    u64::MAX,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &liqor], recent_blockhash);
+
+        assert!(banks_client.process_transaction(transaction).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_liquidate_succeeds_on_underwater_account() {
+    // Test that partial_liquidate repays debt at a bonus and leaves the account healthier
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    test.set_bpf_compute_max_units(50_000);
+
+    let deposit_token_index = 0; // BTC
+    let borrow_token_index = 1; // ETH
+    // 2 BTC ($100,000) against 46 ETH ($92,000) of debt -- collateral ratio ~1.087, below
+    // maint_coll_ratio (1.1) but with enough collateral for a single partial_liquidate call to
+    // bring the ratio back up to init_coll_ratio (1.2).
+    let borrow_amount = 46;
+
+    let mango_group = add_mango_group_prodlike(&mut test, program_id);
+    let mango_group_pk = mango_group.mango_group_pk;
+
+    let liqee = Keypair::new();
+    test.add_account(liqee.pubkey(), Account::new(u32::MAX as u64, 0, &liqee.pubkey()));
+
+    let mut deposits = [0u64; mango::state::NUM_TOKENS];
+    let mut borrows = [0u64; mango::state::NUM_TOKENS];
+    deposits[deposit_token_index] = 2;
+    borrows[borrow_token_index] = borrow_amount;
+
+    let margin_account_pk = add_margin_account(
+        &mut test,
+        program_id,
+        mango_group_pk,
+        liqee.pubkey(),
+        deposits,
+        borrows,
+    );
+
+    // Back the claimed BTC deposit with real vault funds, since add_margin_account skips deposit()
+    set_token_balance(
+        &mut test,
+        mango_group.vaults[deposit_token_index].pubkey,
+        mango_group.signer_pk,
+        mango_group.mints[deposit_token_index].pubkey,
+        deposits[deposit_token_index],
+    );
+
+    let liqor = Keypair::new();
+    test.add_account(liqor.pubkey(), Account::new(u32::MAX as u64, 0, &liqor.pubkey()));
+    let liqor_in_token_account = add_token_account(
+        &mut test,
+        liqor.pubkey(),
+        mango_group.mints[borrow_token_index].pubkey,
+        borrow_amount,
+    );
+    let liqor_out_token_account = add_token_account(
+        &mut test,
+        liqor.pubkey(),
+        mango_group.mints[deposit_token_index].pubkey,
+        0,
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // init_mango_group sets indexes[i].deposit/borrow to 1.0, matching the raw units used above
+    {
+        let mut transaction = Transaction::new_with_payer(
+            &[mango_group.init_mango_group(&payer.pubkey())],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    let start_borrow = borrow_amount;
+
+    {
+        let oracle_pks = mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>();
+        let oracle2_pks = vec![Pubkey::default(); oracle_pks.len()];
+        let open_orders_pks = [Pubkey::default(); mango::state::NUM_MARKETS];
+
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                partial_liquidate(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &liqor.pubkey(),
+                    &liqor_in_token_account.pubkey,
+                    &liqor_out_token_account.pubkey,
+                    &margin_account_pk,
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.vaults[deposit_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    &open_orders_pks,
+                    oracle_pks.as_slice(),
+                    oracle2_pks.as_slice(),
+                    u64::MAX,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &liqor], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    let mut margin_account = banks_client.get_account(margin_account_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&margin_account_pk, &mut margin_account).into();
+    let margin_account = MarginAccount::load_mut_checked(
+        &program_id,
+        &account_info,
+        &mango_group_pk,
+    ).unwrap();
+
+    // The liquidator repaid part of the debt, leaving less of it outstanding and the account
+    // healthier than before
+    assert!(margin_account.borrows[borrow_token_index].to_num::<u64>() < start_borrow);
+
+    // The liquidator should come out ahead: the collateral it receives is worth more than the
+    // debt it repaid, by roughly the default 5% liquidation_fee_bps (see
+    // LiquidationParams::liquidation_fee_multiplier).
+    let eth_paid = borrow_amount - get_token_balance(&mut banks_client, liqor_in_token_account.pubkey).await;
+    let btc_received = get_token_balance(&mut banks_client, liqor_out_token_account.pubkey).await;
+    assert!(btc_received > 0);
+    let value_paid = U64F64::from_num(eth_paid * PRICE_ETH);
+    let value_received = U64F64::from_num(btc_received * PRICE_BTC);
+    assert!(value_received > value_paid, "liquidator should profit from the liquidation bonus");
+    assert!(
+        value_received <= value_paid * U64F64::from_num(1.10),
+        "liquidation bonus should be close to the default 5% fee, not a runaway payout",
+    );
+}
+
+#[tokio::test]
+async fn test_liquidate_socializes_full_shortfall_when_collateral_exhausted() {
+    // Test that partial_liquidate writes off the remaining debt via socialize_loss once the
+    // liqee's collateral is exhausted -- see the dust_threshold/LIQ_MIN_COLL_RATIO branch in
+    // partial_liquidate and the socialize_loss helper it calls.
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    test.set_bpf_compute_max_units(50_000);
+
+    let deposit_token_index = 0; // BTC
+    let borrow_token_index = 1; // ETH
+    // 1 BTC ($50,000) of collateral against 1000 ETH ($2,000,000) of debt -- so far underwater
+    // that seizing all of the liqee's collateral still leaves a shortfall, forcing the
+    // socialize_loss path instead of leaving the account for further partial_liquidate calls.
+    let liqee_borrow_amount = 1000;
+    // A separate lender whose ETH deposit gives mango_group.total_deposits[borrow_token_index]
+    // something to socialize the loss against; socialize_loss divides by this, so it must be
+    // funded or the liquidation would divide by zero.
+    let lender_deposit_amount = 5000;
+
+    let mango_group = add_mango_group_prodlike(&mut test, program_id);
+    let mango_group_pk = mango_group.mango_group_pk;
+
+    let lender = Keypair::new();
+    test.add_account(lender.pubkey(), Account::new(u32::MAX as u64, 0, &lender.pubkey()));
+    let lender_token_account = add_token_account(
+        &mut test,
+        lender.pubkey(),
+        mango_group.mints[borrow_token_index].pubkey,
+        lender_deposit_amount,
+    );
+    let lender_margin_account_pk = Pubkey::new_unique();
+    test.add_account(lender_margin_account_pk, Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id));
+
+    let liqee = Keypair::new();
+    test.add_account(liqee.pubkey(), Account::new(u32::MAX as u64, 0, &liqee.pubkey()));
+
+    let mut deposits = [0u64; mango::state::NUM_TOKENS];
+    let mut borrows = [0u64; mango::state::NUM_TOKENS];
+    deposits[deposit_token_index] = 1;
+    borrows[borrow_token_index] = liqee_borrow_amount;
+
+    let margin_account_pk = add_margin_account(
+        &mut test,
+        program_id,
+        mango_group_pk,
+        liqee.pubkey(),
+        deposits,
+        borrows,
+    );
+
+    // Back the claimed BTC deposit with real vault funds, since add_margin_account skips deposit()
+    set_token_balance(
+        &mut test,
+        mango_group.vaults[deposit_token_index].pubkey,
+        mango_group.signer_pk,
+        mango_group.mints[deposit_token_index].pubkey,
+        deposits[deposit_token_index],
+    );
+
+    let liqor = Keypair::new();
+    test.add_account(liqor.pubkey(), Account::new(u32::MAX as u64, 0, &liqor.pubkey()));
+    let liqor_in_token_account = add_token_account(
+        &mut test,
+        liqor.pubkey(),
+        mango_group.mints[borrow_token_index].pubkey,
+        liqee_borrow_amount,
+    );
+    let liqor_out_token_account = add_token_account(
+        &mut test,
+        liqor.pubkey(),
+        mango_group.mints[deposit_token_index].pubkey,
+        0,
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    // init_mango_group sets indexes[i].deposit/borrow to 1.0, matching the raw units used above
+    {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                mango_group.init_mango_group(&payer.pubkey()),
+                init_margin_account(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &lender_margin_account_pk,
+                    &lender.pubkey(),
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &lender], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    // Fund the group's ETH deposits so there's something for socialize_loss to write down
+    {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                deposit(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &lender_margin_account_pk,
+                    &lender.pubkey(),
+                    &lender_token_account.pubkey,
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    lender_deposit_amount,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &lender], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    {
+        let oracle_pks = mango_group.oracles.iter().map(|m| m.pubkey).collect::<Vec<Pubkey>>();
+        let oracle2_pks = vec![Pubkey::default(); oracle_pks.len()];
+        let open_orders_pks = [Pubkey::default(); mango::state::NUM_MARKETS];
+
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                partial_liquidate(
+                    &program_id,
+                    &mango_group.mango_group_pk,
+                    &liqor.pubkey(),
+                    &liqor_in_token_account.pubkey,
+                    &liqor_out_token_account.pubkey,
+                    &margin_account_pk,
+                    &mango_group.vaults[borrow_token_index].pubkey,
+                    &mango_group.vaults[deposit_token_index].pubkey,
+                    &mango_group.signer_pk,
+                    &open_orders_pks,
+                    oracle_pks.as_slice(),
+                    oracle2_pks.as_slice(),
+                    u64::MAX,
+                ).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &liqor], recent_blockhash);
+        assert!(banks_client.process_transaction(transaction).await.is_ok());
+    }
+
+    // The liqee's collateral was fully exhausted, so the remaining debt should have been written
+    // off via socialize_loss rather than left outstanding.
+    let mut margin_account = banks_client.get_account(margin_account_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&margin_account_pk, &mut margin_account).into();
+    let margin_account = MarginAccount::load_mut_checked(
+        &program_id,
+        &account_info,
+        &mango_group_pk,
+    ).unwrap();
+    assert_eq!(margin_account.borrows[borrow_token_index].to_num::<u64>(), 0);
+
+    // The group's ETH deposit index should have been marked down to spread the loss across
+    // lenders, per socialize_loss's percentage_loss write-down.
+    let mut group_account = banks_client.get_account(mango_group_pk).await.unwrap().unwrap();
+    let group_account_info: AccountInfo = (&mango_group_pk, &mut group_account).into();
+    let loaded_group = MangoGroup::load_mut_checked(&group_account_info, &program_id).unwrap();
+    assert!(loaded_group.indexes[borrow_token_index].deposit < U64F64::from_num(1));
+}
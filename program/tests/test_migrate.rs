@@ -0,0 +1,150 @@
+// Tests related to migrating pre-versioning MangoGroup/MarginAccount/MangoSrmAccount layouts to
+// their current, versioned form via the Migrate instruction
+#![cfg(feature="test-bpf")]
+
+mod helpers;
+
+use std::mem::size_of;
+use helpers::*;
+use solana_program::account_info::AccountInfo;
+use solana_sdk::account_info::IntoAccountInfo;
+use solana_program_test::*;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Signer, Keypair},
+    transaction::Transaction,
+    account::Account,
+};
+use mango::{
+    entrypoint::process_instruction,
+    instruction::migrate,
+    state::{
+        AccountFlag as MangoAccountFlag, Loadable, MangoGroup, MangoSrmAccount, MangoSrmAccountV0,
+        MarginAccount, MANGO_GROUP_VERSION, MANGO_SRM_ACCOUNT_VERSION, MARGIN_ACCOUNT_VERSION,
+    },
+};
+
+#[tokio::test]
+async fn test_migrate_mango_group_bumps_version() {
+    // A pre-versioning MangoGroup reads version == 0 (it lives in what used to be padding); after
+    // Migrate it should read MANGO_GROUP_VERSION and load_mut_checked should accept it.
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let mango_group_pk = Pubkey::new_unique();
+    let mut account = Account::new(u32::MAX as u64, size_of::<MangoGroup>(), &program_id);
+    {
+        let account_info: AccountInfo = (&mango_group_pk, &mut account).into();
+        let mut mango_group = MangoGroup::load_mut(&account_info).unwrap();
+        mango_group.account_flags = (MangoAccountFlag::Initialized | MangoAccountFlag::MangoGroup).bits();
+        assert_eq!(mango_group.version, 0);
+    }
+    test.add_account(mango_group_pk, account);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[migrate(&program_id, &mango_group_pk).unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut account = banks_client.get_account(mango_group_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&mango_group_pk, &mut account).into();
+    let mango_group = MangoGroup::load_mut_checked(&account_info, &program_id).unwrap();
+    assert_eq!(mango_group.version, MANGO_GROUP_VERSION);
+}
+
+#[tokio::test]
+async fn test_migrate_margin_account_bumps_version() {
+    // Same as test_migrate_mango_group_bumps_version, but for MarginAccount -- version bumps and
+    // the rest of the account (owner, mango_group) is left untouched.
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let mango_group_pk = Pubkey::new_unique();
+    let owner_pk = Pubkey::new_unique();
+    let margin_account_pk = Pubkey::new_unique();
+    let mut account = Account::new(u32::MAX as u64, size_of::<MarginAccount>(), &program_id);
+    {
+        let account_info: AccountInfo = (&margin_account_pk, &mut account).into();
+        let mut margin_account = MarginAccount::load_mut(&account_info).unwrap();
+        margin_account.account_flags = (MangoAccountFlag::Initialized | MangoAccountFlag::MarginAccount).bits();
+        margin_account.mango_group = mango_group_pk;
+        margin_account.owner = owner_pk;
+        assert_eq!(margin_account.version, 0);
+    }
+    test.add_account(margin_account_pk, account);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[migrate(&program_id, &margin_account_pk).unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut account = banks_client.get_account(margin_account_pk).await.unwrap().unwrap();
+    let account_info: AccountInfo = (&margin_account_pk, &mut account).into();
+    let margin_account = MarginAccount::load_mut_checked(&program_id, &account_info, &mango_group_pk).unwrap();
+    assert_eq!(margin_account.version, MARGIN_ACCOUNT_VERSION);
+    assert_eq!(margin_account.owner, owner_pk);
+}
+
+#[tokio::test]
+async fn test_migrate_mango_srm_account_resizes_and_bumps_version() {
+    // MangoSrmAccount never had spare padding to carve a version byte out of, so migrating it has
+    // to grow the account via realloc instead of just reinterpreting an existing byte.
+    let program_id = Pubkey::new_unique();
+
+    let mut test = ProgramTest::new(
+        "mango",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let mango_group_pk = Pubkey::new_unique();
+    let owner_pk = Pubkey::new_unique();
+    let srm_account_pk = Pubkey::new_unique();
+    let mut account = Account::new(u32::MAX as u64, size_of::<MangoSrmAccountV0>(), &program_id);
+    {
+        let account_info: AccountInfo = (&srm_account_pk, &mut account).into();
+        let mut srm_account = MangoSrmAccountV0::load_mut(&account_info).unwrap();
+        srm_account.account_flags = (MangoAccountFlag::Initialized | MangoAccountFlag::MangoSrmAccount).bits();
+        srm_account.mango_group = mango_group_pk;
+        srm_account.owner = owner_pk;
+        srm_account.amount = 1_234;
+        srm_account.msrm_amount = 5;
+    }
+    test.add_account(srm_account_pk, account);
+
+    let (mut banks_client, payer, recent_blockhash) = test.start().await;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[migrate(&program_id, &srm_account_pk).unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(banks_client.process_transaction(transaction).await.is_ok());
+
+    let mut account = banks_client.get_account(srm_account_pk).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), size_of::<MangoSrmAccount>());
+    let account_info: AccountInfo = (&srm_account_pk, &mut account).into();
+    let srm_account = MangoSrmAccount::load_mut_checked(&program_id, &account_info, &mango_group_pk).unwrap();
+    assert_eq!(srm_account.version, MANGO_SRM_ACCOUNT_VERSION);
+    assert_eq!(srm_account.owner, owner_pk);
+    assert_eq!(srm_account.amount, 1_234);
+    assert_eq!(srm_account.msrm_amount, 5);
+}